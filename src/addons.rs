@@ -1,13 +1,12 @@
 use crate::{
-    ipc::{IpcMessage, NS_NAME},
+    ipc::{
+        IpcError, IpcErrorCode, IpcMessage, IpcRequest, IpcResponse, parse_request, send_request,
+        socket_name,
+    },
     os::spawn_self,
 };
-use interprocess::local_socket::{
-    GenericNamespaced,
-    tokio::{Stream, prelude::*},
-};
+use interprocess::local_socket::tokio::{Stream, prelude::*};
 use std::io::{ErrorKind, Read, Write};
-use tokio::io::AsyncWriteExt;
 
 pub const APP_NAME: &str = "top.s121.fd";
 pub const CHROME_EXT_IDS: &[&str] = &[
@@ -196,7 +195,7 @@ fn write_manifests_to_dirs(dirs: &[std::path::PathBuf], json: &str) {
 }
 
 /// 读取浏览器从 stdin 传来的数据
-fn read_native_message() -> Option<IpcMessage> {
+fn read_native_message() -> Option<Vec<u8>> {
     let mut stdin = std::io::stdin().lock();
     let mut len_bytes = [0u8; 4];
     stdin.read_exact(&mut len_bytes).ok()?;
@@ -204,11 +203,10 @@ fn read_native_message() -> Option<IpcMessage> {
     let len = u32::from_ne_bytes(len_bytes) as usize;
     let mut buffer = vec![0u8; len];
     stdin.read_exact(&mut buffer).ok()?;
-
-    serde_json::from_slice(&buffer).ok()
+    Some(buffer)
 }
 
-/// 给浏览器回复成功标识
+/// 给浏览器回复结果
 fn write_native_message<T: serde::Serialize>(msg: &T) {
     if let Ok(json) = serde_json::to_string(msg) {
         let len = json.len() as u32;
@@ -221,12 +219,38 @@ fn write_native_message<T: serde::Serialize>(msg: &T) {
 
 /// 作为代理进程，接管浏览器的请求并转交给主程序
 pub async fn handle_browser_request() -> color_eyre::Result<()> {
-    let payload = read_native_message().unwrap_or(IpcMessage::WakeUp);
-    let msg = serde_json::to_string(&payload)?;
-    let ns_name = NS_NAME.to_ns_name::<GenericNamespaced>()?;
+    let req = match read_native_message() {
+        Some(buf) => parse_request(&String::from_utf8_lossy(&buf)),
+        None => Ok(IpcRequest::new(IpcMessage::WakeUp)),
+    };
+    let response = match req {
+        Ok(req) => {
+            let id = req.id.clone();
+            forward_request(req).await.unwrap_or_else(|e| {
+                let e = IpcError::new(IpcErrorCode::Failed, format!("无法连接到 fast-down：{e}"));
+                IpcResponse::new(id, Err(e))
+            })
+        }
+        Err((id, e)) => IpcResponse::new(id, Err(e)),
+    };
+    // 旧版扩展只检查 status 字段
+    let mut reply = serde_json::to_value(&response)?;
+    let status = if response.into_result().is_ok() {
+        "success"
+    } else {
+        "error"
+    };
+    reply["status"] = status.into();
+    write_native_message(&reply);
+    Ok(())
+}
+
+/// 把请求转交给主程序并等待回复，主程序没有运行时先启动它
+async fn forward_request(req: IpcRequest) -> color_eyre::Result<IpcResponse> {
+    let ns_name = socket_name()?;
 
     let mut retries = 0;
-    let mut stream = loop {
+    let stream = loop {
         match Stream::connect(ns_name.clone()).await {
             Ok(s) => break s,
             Err(e) if matches!(e.kind(), ErrorKind::ConnectionRefused | ErrorKind::NotFound) => {
@@ -244,8 +268,5 @@ pub async fn handle_browser_request() -> color_eyre::Result<()> {
         }
     };
 
-    stream.write_all(format!("{msg}\n").as_bytes()).await?;
-    write_native_message(&serde_json::json!({"status": "success"}));
-
-    Ok(())
+    send_request(stream, &req).await
 }
@@ -0,0 +1,665 @@
+//! 本机的 REST API，所有请求都需要 `Authorization: Bearer <令牌>`，
+//! `EventSource` 无法设置请求头，所以事件流也可以用 `?token=<令牌>`
+//!
+//! - `GET /api/v1/tasks?status=running,paused&q=关键字` 列出任务
+//! - `POST /api/v1/tasks` 新建任务，`{"url": "...", "mirrors": [], "config": {}}`，
+//!   config 中没有的设置项使用全局的下载设置
+//! - `GET /api/v1/tasks/{gid}` 任务详情，包括下载设置
+//! - `POST /api/v1/tasks/{gid}/pause`、`POST /api/v1/tasks/{gid}/resume`、`DELETE /api/v1/tasks/{gid}`
+//! - `GET /api/v1/config`、`PATCH /api/v1/config` 读写常规设置
+//! - `GET /api/v1/events` 以 Server-Sent Events 推送 `status`、`removed`、`progress`、`config` 事件
+//!
+//! `GET /` 是用于管理任务的网页界面，登录密码就是令牌
+//!
+//! 只监听本机地址，其他设备需要通过开启了 HTTPS 的反向代理访问
+
+use crate::{
+    core::{App, TaskEvent, UiBridge, on_config_changed, start_entry_by_gid, start_new_entry},
+    ipc::TaskStatus,
+    persist::{DownloadConfig, GeneralConfig},
+    ui::EntryData,
+    utils::{LocalServer, secret_eq},
+};
+use axum::{
+    Json, Router,
+    extract::{Path, Query, Request, State},
+    http::{StatusCode, header},
+    middleware::{self, Next},
+    response::{
+        Html, IntoResponse, Response,
+        sse::{Event, KeepAlive, Sse},
+    },
+    routing::{get, post},
+};
+use fast_down_ffi::Total;
+use futures::{Stream, stream};
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+use serde_json::{Map, Value, json};
+use slint::{Model, VecModel};
+use std::{convert::Infallible, net::SocketAddr, ops::Range, path::PathBuf, rc::Rc, sync::Arc};
+use tokio::sync::broadcast::error::RecvError;
+use tokio_util::sync::CancellationToken;
+use tracing::warn;
+use url::{Url, form_urlencoded};
+
+pub struct ApiServer {
+    app: App,
+    bridge: UiBridge,
+    server: LocalServer,
+}
+
+#[derive(Clone)]
+struct ApiState {
+    app: App,
+    bridge: UiBridge,
+    token: Arc<str>,
+    shutdown: CancellationToken,
+}
+
+impl ApiServer {
+    /// 需要在 UI 线程调用，创建后调用 apply 按设置启动，之后设置修改时自动重启
+    pub fn new(app: App, list_model: Rc<VecModel<EntryData>>) -> color_eyre::Result<Rc<Self>> {
+        let server = Rc::new(Self {
+            bridge: UiBridge::new(app.clone(), list_model)?,
+            app,
+            server: LocalServer::new("REST API"),
+        });
+        on_config_changed(&server.app.events, Rc::downgrade(&server), Self::apply)?;
+        Ok(server)
+    }
+
+    /// 按常规设置启动、重启或停止服务
+    pub fn apply(&self) {
+        let (addr, token) = {
+            let config = self.app.db.inner.general_config.lock();
+            let addr = SocketAddr::from((config.api_host, config.api_port));
+            let loopback = addr.ip().is_loopback();
+            if config.api_enabled && !loopback {
+                warn!(addr = %addr, "REST API 只能监听本机地址，其他设备请通过 HTTPS 反向代理访问");
+            }
+            (
+                (config.api_enabled && loopback).then_some(addr),
+                config.api_token.clone(),
+            )
+        };
+        self.server.apply(addr, &token, |shutdown| {
+            let state = ApiState {
+                app: self.app.clone(),
+                bridge: self.bridge.clone(),
+                token: token.as_str().into(),
+                shutdown,
+            };
+            let api = Router::new()
+                .route("/tasks", get(list_tasks).post(create_task))
+                .route("/tasks/{gid}", get(get_task).delete(remove_task))
+                .route("/tasks/{gid}/pause", post(pause_task))
+                .route("/tasks/{gid}/resume", post(resume_task))
+                .route("/config", get(get_config).patch(update_config))
+                .route_layer(middleware::from_fn_with_state(state.clone(), auth))
+                .merge(
+                    Router::new()
+                        .route("/events", get(events))
+                        .route_layer(middleware::from_fn_with_state(state.clone(), auth_query)),
+                )
+                .with_state(state);
+            Router::new().route("/", get(index)).nest("/api/v1", api)
+        });
+    }
+}
+
+#[derive(Debug)]
+struct ApiError(StatusCode, String);
+
+impl ApiError {
+    fn bad_request(message: impl Into<String>) -> Self {
+        Self(StatusCode::BAD_REQUEST, message.into())
+    }
+
+    fn not_found(gid: i32) -> Self {
+        Self(StatusCode::NOT_FOUND, format!("任务 {gid} 不存在"))
+    }
+
+    fn unavailable() -> Self {
+        Self(StatusCode::SERVICE_UNAVAILABLE, "程序正在退出".into())
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        (self.0, Json(json!({ "error": self.1 }))).into_response()
+    }
+}
+
+type ApiResult<T> = Result<T, ApiError>;
+
+async fn index() -> Html<&'static str> {
+    Html(include_str!("index.html"))
+}
+
+/// 取出请求带的令牌，`allow_query` 为 true 时也接受 `?token=`
+fn request_token(req: &Request, allow_query: bool) -> Option<String> {
+    let bearer = req
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .map(str::to_string);
+    bearer.or_else(|| {
+        allow_query
+            .then(|| req.uri().query())
+            .flatten()
+            .and_then(|q| {
+                form_urlencoded::parse(q.as_bytes())
+                    .find(|(k, _)| k == "token")
+                    .map(|(_, v)| v.into_owned())
+            })
+    })
+}
+
+fn check_token(state: &ApiState, req: &Request, allow_query: bool) -> ApiResult<()> {
+    if !request_token(req, allow_query).is_some_and(|token| secret_eq(&token, &state.token)) {
+        return Err(ApiError(StatusCode::UNAUTHORIZED, "令牌错误".into()));
+    }
+    Ok(())
+}
+
+async fn auth(State(state): State<ApiState>, req: Request, next: Next) -> ApiResult<Response> {
+    check_token(&state, &req, false)?;
+    Ok(next.run(req).await)
+}
+
+/// 只用于事件流，令牌会出现在地址中，其他路由不接受
+async fn auth_query(
+    State(state): State<ApiState>,
+    req: Request,
+    next: Next,
+) -> ApiResult<Response> {
+    check_token(&state, &req, true)?;
+    Ok(next.run(req).await)
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Task {
+    pub gid: i32,
+    pub url: Url,
+    pub mirrors: Vec<Url>,
+    pub file_name: String,
+    pub file_path: PathBuf,
+    pub status: TaskStatus,
+    pub downloaded: u64,
+    pub total: u64,
+    /// 字节每秒，没有在下载时为 0
+    pub speed: u64,
+    pub elapsed_ms: u64,
+    /// 已下载的区间，`[开始, 结束)`
+    pub progress: Vec<[u64; 2]>,
+    pub error: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub config: Option<DownloadConfig>,
+}
+
+impl Task {
+    fn new(app: &App, row: &EntryData, with_config: bool) -> Option<Self> {
+        let entry = app.db.inner.data.get(&row.gid)?;
+        Some(Self {
+            gid: row.gid,
+            url: entry.url.clone(),
+            mirrors: entry.mirrors.clone(),
+            file_name: entry.file_name.clone(),
+            file_path: entry.file_path.clone(),
+            status: row.status.into(),
+            downloaded: entry.progress.total(),
+            total: entry.file_size,
+            speed: app.speeds.get(&row.gid).map_or(0, |s| *s),
+            elapsed_ms: entry.elapsed.as_millis() as u64,
+            progress: ranges(&entry.progress),
+            error: row.error.to_string(),
+            config: with_config.then(|| entry.config.clone()),
+        })
+    }
+}
+
+fn ranges(progress: &[Range<u64>]) -> Vec<[u64; 2]> {
+    progress.iter().map(|r| [r.start, r.end]).collect()
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct TaskQuery {
+    /// 逗号分隔的状态
+    status: Option<String>,
+    /// 在链接和文件名中查找，不区分大小写
+    q: Option<String>,
+}
+
+#[derive(Debug, Default, PartialEq)]
+struct TaskFilter {
+    statuses: Vec<TaskStatus>,
+    keyword: String,
+}
+
+impl TaskFilter {
+    fn parse(query: &TaskQuery) -> ApiResult<Self> {
+        let statuses = query
+            .status
+            .iter()
+            .flat_map(|s| s.split(','))
+            .map(str::trim)
+            .filter(|s| !s.is_empty())
+            .map(|s| {
+                serde_json::from_value(Value::String(s.to_lowercase()))
+                    .map_err(|_| ApiError::bad_request(format!("未知的状态：{s}")))
+            })
+            .collect::<ApiResult<_>>()?;
+        Ok(Self {
+            statuses,
+            keyword: query.q.as_deref().unwrap_or_default().to_lowercase(),
+        })
+    }
+
+    fn matches(&self, task: &Task) -> bool {
+        (self.statuses.is_empty() || self.statuses.contains(&task.status))
+            && (self.keyword.is_empty()
+                || task.url.as_str().to_lowercase().contains(&self.keyword)
+                || task.file_name.to_lowercase().contains(&self.keyword))
+    }
+}
+
+/// 创建任务时可以指定的下载设置，完成后操作可以运行命令，不允许通过 API 修改
+const TASK_FIELDS: &[&str] = &[
+    "save_dir",
+    "file_name",
+    "threads",
+    "proxy",
+    "headers",
+    "min_chunk_size",
+    "write_buffer_size",
+    "write_queue_cap",
+    "retry_gap",
+    "pull_timeout",
+    "accept_invalid_certs",
+    "accept_invalid_hostnames",
+    "local_address",
+    "max_speculative",
+    "write_method",
+    "retry_times",
+    "chunk_window",
+    "pre_allocate",
+    "part_file",
+    "parse_filename",
+    "checksum",
+    "speed_limit",
+    "expected_size",
+];
+
+/// 可以通过 API 修改的常规设置，不包括完成后操作、开机启动、管理员权限和各服务的地址与密钥
+const CONFIG_FIELDS: &[&str] = &[
+    "max_concurrency",
+    "exit_after_download",
+    "ask_before_download",
+    "skip_headers",
+    "speed_limit",
+    "schedule",
+    "auto_resume",
+    "retry",
+    "file_changed",
+    "min_free_space",
+    "host_connections",
+    "host_overrides",
+    "categorize",
+    "category_dirs",
+    "auto_extract",
+    "delete_archive",
+];
+
+/// 读取设置时不返回的密钥
+const SECRET_FIELDS: &[&str] = &["rpc_secret", "api_token"];
+
+/// 用 patch 中的字段覆盖 base，patch 中有 base 没有或不在 writable 中的字段时出错
+fn merge<T: Serialize + DeserializeOwned>(
+    base: &T,
+    patch: Map<String, Value>,
+    writable: &[&str],
+) -> ApiResult<T> {
+    let Ok(Value::Object(mut value)) = serde_json::to_value(base) else {
+        return Err(ApiError(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "无法序列化设置".into(),
+        ));
+    };
+    for (key, v) in patch {
+        match value.get_mut(&key) {
+            Some(_) if !writable.contains(&key.as_str()) => {
+                return Err(ApiError::bad_request(format!(
+                    "不允许通过 API 修改的设置项：{key}"
+                )));
+            }
+            Some(old) => *old = v,
+            None => return Err(ApiError::bad_request(format!("未知的设置项：{key}"))),
+        }
+    }
+    serde_json::from_value(Value::Object(value))
+        .map_err(|e| ApiError::bad_request(format!("设置有误：{e}")))
+}
+
+fn parse_url(s: &str) -> ApiResult<Url> {
+    Url::parse(s)
+        .ok()
+        .filter(|url| matches!(url.scheme(), "http" | "https"))
+        .ok_or_else(|| ApiError::bad_request(format!("不支持的链接：{s}")))
+}
+
+fn find_row(list: &VecModel<EntryData>, gid: i32) -> ApiResult<EntryData> {
+    list.iter()
+        .find(|row| row.gid == gid)
+        .ok_or_else(|| ApiError::not_found(gid))
+}
+
+async fn list_tasks(
+    State(state): State<ApiState>,
+    Query(query): Query<TaskQuery>,
+) -> ApiResult<Json<Vec<Task>>> {
+    let filter = TaskFilter::parse(&query)?;
+    let tasks = state
+        .bridge
+        .run(move |app, list| {
+            list.iter()
+                .filter_map(|row| Task::new(app, &row, false))
+                .filter(|task| filter.matches(task))
+                .collect()
+        })
+        .await
+        .ok_or_else(ApiError::unavailable)?;
+    Ok(Json(tasks))
+}
+
+#[derive(Debug, Deserialize)]
+struct NewTask {
+    url: String,
+    #[serde(default)]
+    mirrors: Vec<String>,
+    /// 部分下载设置
+    #[serde(default)]
+    config: Map<String, Value>,
+}
+
+async fn create_task(
+    State(state): State<ApiState>,
+    Json(req): Json<NewTask>,
+) -> ApiResult<(StatusCode, Json<Value>)> {
+    let url = parse_url(&req.url)?;
+    let mirrors = req
+        .mirrors
+        .iter()
+        .map(|s| parse_url(s))
+        .collect::<ApiResult<Vec<_>>>()?;
+    // 请求中的设置优先于站点规则
+    let mut base = state.app.db.get_ui_download_config();
+    state.app.db.apply_site_rules(&url, &mut base);
+    let config =
+        merge(&DownloadConfig::from(&base), req.config, TASK_FIELDS)?.to_ui_download_config();
+    let gid = state
+        .bridge
+        .run(move |app, list| start_new_entry(app, url, mirrors, &config, list))
+        .await
+        .ok_or_else(ApiError::unavailable)?;
+    Ok((StatusCode::CREATED, Json(json!({ "gid": gid }))))
+}
+
+async fn get_task(State(state): State<ApiState>, Path(gid): Path<i32>) -> ApiResult<Json<Task>> {
+    state
+        .bridge
+        .run(move |app, list| {
+            let row = find_row(list, gid)?;
+            Task::new(app, &row, true).ok_or_else(|| ApiError::not_found(gid))
+        })
+        .await
+        .ok_or_else(ApiError::unavailable)?
+        .map(Json)
+}
+
+async fn pause_task(State(state): State<ApiState>, Path(gid): Path<i32>) -> ApiResult<StatusCode> {
+    state
+        .bridge
+        .run(move |app, list| {
+            find_row(list, gid)?;
+            app.task_set.cancel_task(&gid);
+            Ok(StatusCode::NO_CONTENT)
+        })
+        .await
+        .ok_or_else(ApiError::unavailable)?
+}
+
+async fn resume_task(State(state): State<ApiState>, Path(gid): Path<i32>) -> ApiResult<StatusCode> {
+    state
+        .bridge
+        .run(move |app, list| {
+            find_row(list, gid)?;
+            start_entry_by_gid(app, gid, list);
+            Ok(StatusCode::NO_CONTENT)
+        })
+        .await
+        .ok_or_else(ApiError::unavailable)?
+}
+
+async fn remove_task(State(state): State<ApiState>, Path(gid): Path<i32>) -> ApiResult<StatusCode> {
+    state
+        .bridge
+        .run(move |app, list| {
+            if !app.remove_entry(gid, list) {
+                return Err(ApiError::not_found(gid));
+            }
+            Ok(StatusCode::NO_CONTENT)
+        })
+        .await
+        .ok_or_else(ApiError::unavailable)?
+}
+
+/// 去掉密钥后的常规设置
+fn redacted(config: &GeneralConfig) -> Value {
+    let mut value = serde_json::to_value(config).unwrap_or_default();
+    if let Value::Object(map) = &mut value {
+        for key in SECRET_FIELDS {
+            map.remove(*key);
+        }
+    }
+    value
+}
+
+async fn get_config(State(state): State<ApiState>) -> Json<Value> {
+    Json(redacted(&state.app.db.get_general_config()))
+}
+
+async fn update_config(
+    State(state): State<ApiState>,
+    Json(patch): Json<Map<String, Value>>,
+) -> ApiResult<Json<Value>> {
+    let config = merge(&state.app.db.get_general_config(), patch, CONFIG_FIELDS)?;
+    let general_config = config.to_ui_general_config();
+    state
+        .bridge
+        .run(move |app, list| {
+            app.set_config(app.db.get_ui_download_config(), general_config, None);
+            app.apply_schedule(list);
+        })
+        .await
+        .ok_or_else(ApiError::unavailable)?;
+    Ok(Json(redacted(&state.app.db.get_general_config())))
+}
+
+fn sse_event(event: TaskEvent) -> Event {
+    let (name, data) = match event {
+        TaskEvent::Status { gid, status } => (
+            "status",
+            json!({ "gid": gid, "status": TaskStatus::from(status) }),
+        ),
+        TaskEvent::Removed { gid } => ("removed", json!({ "gid": gid })),
+        TaskEvent::ConfigChanged => ("config", json!({})),
+        TaskEvent::Progress {
+            gid,
+            downloaded,
+            total,
+            speed,
+            elapsed,
+            progress,
+        } => (
+            "progress",
+            json!({
+                "gid": gid,
+                "downloaded": downloaded,
+                "total": total,
+                "speed": speed,
+                "elapsedMs": elapsed.as_millis() as u64,
+                "progress": ranges(&progress),
+            }),
+        ),
+    };
+    Event::default().event(name).data(data.to_string())
+}
+
+async fn events(
+    State(state): State<ApiState>,
+) -> Sse<impl Stream<Item = Result<Event, Infallible>>> {
+    let rx = state.app.events.subscribe();
+    let stream = stream::unfold((rx, state.shutdown), |(mut rx, shutdown)| async move {
+        loop {
+            let event = tokio::select! {
+                _ = shutdown.cancelled() => return None,
+                event = rx.recv() => event,
+            };
+            match event {
+                Ok(event) => return Some((Ok(sse_event(event)), (rx, shutdown))),
+                Err(RecvError::Lagged(_)) => continue,
+                Err(RecvError::Closed) => return None,
+            }
+        }
+    });
+    Sse::new(stream).keep_alive(KeepAlive::default())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn task(url: &str, file_name: &str, status: TaskStatus) -> Task {
+        Task {
+            gid: 1,
+            url: Url::parse(url).unwrap(),
+            mirrors: Vec::new(),
+            file_name: file_name.into(),
+            file_path: PathBuf::new(),
+            status,
+            downloaded: 0,
+            total: 0,
+            speed: 0,
+            elapsed_ms: 0,
+            progress: Vec::new(),
+            error: String::new(),
+            config: None,
+        }
+    }
+
+    #[test]
+    fn test_task_filter() {
+        let filter = TaskFilter::parse(&TaskQuery {
+            status: Some("running, Paused".into()),
+            q: Some("ISO".into()),
+        })
+        .unwrap();
+        assert_eq!(
+            filter.statuses,
+            vec![TaskStatus::Running, TaskStatus::Paused]
+        );
+        assert!(filter.matches(&task("https://a.com/x", "ubuntu.iso", TaskStatus::Running)));
+        assert!(filter.matches(&task("https://a.com/ISO/x", "x", TaskStatus::Paused)));
+        assert!(!filter.matches(&task("https://a.com/x", "ubuntu.iso", TaskStatus::Error)));
+        assert!(!filter.matches(&task("https://a.com/x", "x", TaskStatus::Running)));
+
+        let all = TaskFilter::parse(&TaskQuery::default()).unwrap();
+        assert!(all.matches(&task("https://a.com/x", "x", TaskStatus::Completed)));
+        assert!(
+            TaskFilter::parse(&TaskQuery {
+                status: Some("unknown".into()),
+                q: None,
+            })
+            .is_err()
+        );
+    }
+
+    #[test]
+    fn test_merge() {
+        let base = DownloadConfig::default();
+        let patch = json!({ "threads": 4, "file_name": "a.bin" });
+        let Value::Object(patch) = patch else {
+            unreachable!()
+        };
+        let merged = merge(&base, patch, TASK_FIELDS).unwrap();
+        assert_eq!(merged.threads, 4);
+        assert_eq!(merged.file_name, "a.bin");
+        assert_eq!(merged.save_dir, base.save_dir);
+
+        let Value::Object(unknown) = json!({ "no_such_field": 1 }) else {
+            unreachable!()
+        };
+        assert_eq!(
+            merge(&base, unknown, TASK_FIELDS).unwrap_err().0,
+            StatusCode::BAD_REQUEST
+        );
+        let Value::Object(wrong) = json!({ "threads": "many" }) else {
+            unreachable!()
+        };
+        assert!(merge(&base, wrong, TASK_FIELDS).is_err());
+
+        // 完成后操作可以运行命令，不允许通过 API 设置
+        let Value::Object(hook) = json!({ "post_actions": [{ "Run": "id" }] }) else {
+            unreachable!()
+        };
+        assert_eq!(
+            merge(&base, hook, TASK_FIELDS).unwrap_err().0,
+            StatusCode::BAD_REQUEST
+        );
+    }
+
+    #[test]
+    fn test_config_fields() {
+        let base = GeneralConfig::default();
+        for key in ["api_token", "rpc_secret", "api_host", "post_actions"] {
+            let mut patch = Map::new();
+            patch.insert(key.into(), Value::Null);
+            assert!(merge(&base, patch, CONFIG_FIELDS).is_err(), "{key}");
+        }
+        let Value::Object(ok) = json!({ "max_concurrency": 4 }) else {
+            unreachable!()
+        };
+        assert_eq!(merge(&base, ok, CONFIG_FIELDS).unwrap().max_concurrency, 4);
+        let value = redacted(&base);
+        assert!(value.get("api_token").is_none() && value.get("rpc_secret").is_none());
+        assert!(value.get("max_concurrency").is_some());
+        // 所有可写字段都存在
+        for key in CONFIG_FIELDS {
+            assert!(value.get(*key).is_some(), "{key}");
+        }
+        let value = serde_json::to_value(DownloadConfig::default()).unwrap();
+        for key in TASK_FIELDS {
+            assert!(value.get(*key).is_some(), "{key}");
+        }
+    }
+
+    #[test]
+    fn test_request_token() {
+        let req = |uri: &str, bearer: Option<&str>| {
+            let mut builder = Request::builder().uri(uri);
+            if let Some(bearer) = bearer {
+                builder = builder.header(header::AUTHORIZATION, format!("Bearer {bearer}"));
+            }
+            builder.body(axum::body::Body::empty()).unwrap()
+        };
+        let query = req("/events?token=a%20b", None);
+        assert_eq!(request_token(&query, true).as_deref(), Some("a b"));
+        assert_eq!(request_token(&query, false), None);
+        let header = req("/tasks?token=b", Some("a"));
+        assert_eq!(request_token(&header, false).as_deref(), Some("a"));
+        assert_eq!(request_token(&header, true).as_deref(), Some("a"));
+        assert_eq!(request_token(&req("/tasks", None), true), None);
+    }
+}
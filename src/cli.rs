@@ -0,0 +1,388 @@
+use crate::{
+    core::parse_metalink_path,
+    fmt::format_size,
+    ipc::{DownloadOptions, IpcMessage, IpcReply, TaskInfo, TaskStatus, request},
+};
+use std::path::{PathBuf, absolute};
+use url::Url;
+
+/// 命令行参数，例如 `fast-down https://example.com/a.iso -d ~/Downloads -o b.iso -H "Cookie: a=b"`
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct CliArgs {
+    pub command: Option<CliCommand>,
+    pub urls: Vec<Url>,
+    /// Metalink 文件的绝对路径
+    pub metalinks: Vec<PathBuf>,
+    /// 保存文件夹，已转为绝对路径以便交给已有实例
+    pub save_dir: Option<PathBuf>,
+    pub file_name: Option<String>,
+    /// 每项为一行 `Key: Value`
+    pub headers: Vec<String>,
+    /// 启动时隐藏窗口，开机自启时使用
+    pub hidden: bool,
+    /// 不创建窗口和托盘，只运行下载、IPC 和 API 服务
+    pub daemon: bool,
+    /// 子命令以 JSON 输出结果
+    pub json: bool,
+    /// 无法识别的参数，在日志初始化后输出
+    pub ignored: Vec<String>,
+}
+
+/// 控制已运行实例的子命令
+#[derive(Debug, Clone, PartialEq)]
+pub enum CliCommand {
+    /// 添加命令行中的链接和 Metalink 文件
+    Add,
+    List,
+    /// 不指定 gid 时输出各状态的任务数
+    Status(Option<i32>),
+    Pause(Vec<i32>),
+    Resume(Vec<i32>),
+    Remove(Vec<i32>),
+    Config {
+        max_concurrency: Option<usize>,
+        /// KiB/s
+        speed_limit: Option<u64>,
+    },
+}
+
+impl CliCommand {
+    fn from_name(name: &str) -> Option<Self> {
+        Some(match name {
+            "add" => Self::Add,
+            "list" => Self::List,
+            "status" => Self::Status(None),
+            "pause" => Self::Pause(Vec::new()),
+            "resume" => Self::Resume(Vec::new()),
+            "remove" => Self::Remove(Vec::new()),
+            "config" => Self::Config {
+                max_concurrency: None,
+                speed_limit: None,
+            },
+            _ => return None,
+        })
+    }
+
+    /// 子命令后的 gid 参数
+    fn push_gid(&mut self, gid: i32) -> bool {
+        match self {
+            Self::Status(g @ None) => *g = Some(gid),
+            Self::Pause(gids) | Self::Resume(gids) | Self::Remove(gids) => gids.push(gid),
+            _ => return false,
+        }
+        true
+    }
+}
+
+impl CliArgs {
+    /// 解析不含程序名的参数，第一个参数可以是子命令
+    pub fn parse(args: impl IntoIterator<Item = String>) -> Self {
+        let mut cli = Self::default();
+        let mut args = args.into_iter().peekable();
+        if let Some(command) = args.peek().and_then(|arg| CliCommand::from_name(arg)) {
+            cli.command = Some(command);
+            args.next();
+        }
+        while let Some(arg) = args.next() {
+            if let (Some(command), Ok(gid)) = (&mut cli.command, arg.parse())
+                && command.push_gid(gid)
+            {
+                continue;
+            }
+            // 同时支持 `--dir D:\` 和 `--dir=D:\`
+            let (flag, inline) = match arg.split_once('=') {
+                Some((flag, value)) if flag.starts_with("--") => (flag, Some(value.to_string())),
+                _ => (arg.as_str(), None),
+            };
+            let mut ignored = None;
+            let mut value = || {
+                let value = inline.clone().or_else(|| args.next());
+                if value.is_none() {
+                    ignored = Some(flag.to_string());
+                }
+                value
+            };
+            match (flag, &mut cli.command) {
+                ("--hidden", _) => cli.hidden = true,
+                ("--daemon" | "--headless", _) => cli.daemon = true,
+                ("--json", _) => cli.json = true,
+                ("-d" | "--dir", _) => {
+                    cli.save_dir = value().and_then(|dir| absolute(dir).ok());
+                }
+                ("-o" | "--out", _) => cli.file_name = value(),
+                ("-H" | "--header", _) => cli.headers.extend(value()),
+                (
+                    "--max-concurrency",
+                    Some(CliCommand::Config {
+                        max_concurrency, ..
+                    }),
+                ) => {
+                    *max_concurrency = value().and_then(|n| n.parse().ok());
+                }
+                ("--speed-limit", Some(CliCommand::Config { speed_limit, .. })) => {
+                    *speed_limit = value().and_then(|n| n.parse().ok());
+                }
+                _ if arg.starts_with('-') => ignored = Some(arg.clone()),
+                _ => match Url::parse(&arg) {
+                    Ok(url) if matches!(url.scheme(), "http" | "https") => cli.urls.push(url),
+                    _ => match parse_metalink_path(&arg).and_then(|p| absolute(p).ok()) {
+                        Some(path) => cli.metalinks.push(path),
+                        None => ignored = Some(arg.clone()),
+                    },
+                },
+            }
+            cli.ignored.extend(ignored);
+        }
+        cli
+    }
+
+    /// 转为交给已有实例的消息，每个链接和 Metalink 文件各一条
+    pub fn ipc_messages(&self) -> Vec<IpcMessage> {
+        let metalinks = self
+            .metalinks
+            .iter()
+            .map(|path| IpcMessage::Metalink { path: path.clone() });
+        let downloads = self.urls.iter().map(|url| {
+            IpcMessage::Download(DownloadOptions {
+                url: url.clone(),
+                headers: (!self.headers.is_empty()).then(|| self.headers.join("\n")),
+                checksum: None,
+                save_dir: self.save_dir.clone(),
+                file_name: self.file_name.clone(),
+            })
+        });
+        metalinks.chain(downloads).collect()
+    }
+
+    /// 把子命令发给已运行的实例并输出结果，返回退出码
+    ///
+    /// 没有运行中的实例时，add 返回 None 以便正常启动并添加任务
+    pub async fn run_command(&self, command: &CliCommand) -> Option<i32> {
+        let msgs = match command {
+            CliCommand::Add => self.ipc_messages(),
+            CliCommand::List | CliCommand::Status(_) => vec![IpcMessage::List],
+            CliCommand::Pause(gids) => vec![IpcMessage::Pause { gids: gids.clone() }],
+            CliCommand::Resume(gids) => vec![IpcMessage::Resume { gids: gids.clone() }],
+            CliCommand::Remove(gids) => vec![IpcMessage::Remove { gids: gids.clone() }],
+            CliCommand::Config {
+                max_concurrency,
+                speed_limit,
+            } => vec![IpcMessage::SetConfig {
+                max_concurrency: *max_concurrency,
+                speed_limit: *speed_limit,
+            }],
+        };
+        if msgs.is_empty() {
+            eprintln!("缺少参数，例如 fast-down add https://example.com/file.zip");
+            return Some(2);
+        }
+        let mut replies = Vec::new();
+        for msg in &msgs {
+            match request(msg).await {
+                Ok(Some(reply)) => replies.push(reply),
+                Ok(None) if *command == CliCommand::Add => return None,
+                Ok(None) => {
+                    eprintln!("fast-down 没有在运行");
+                    return Some(1);
+                }
+                Err(e) => {
+                    eprintln!("与 fast-down 通信失败：{e}");
+                    return Some(1);
+                }
+            }
+        }
+        let mut code = 0;
+        for reply in replies {
+            let reply = match reply {
+                Ok(reply) => reply,
+                Err(e) if self.json => {
+                    println!("{}", serde_json::to_string(&e).unwrap_or_default());
+                    code = 1;
+                    continue;
+                }
+                Err(e) => {
+                    eprintln!("{e}");
+                    code = 1;
+                    continue;
+                }
+            };
+            match (command, reply) {
+                (CliCommand::List, IpcReply::Tasks { tasks }) => self.print_tasks(&tasks),
+                (CliCommand::Status(Some(gid)), IpcReply::Tasks { tasks }) => {
+                    match tasks.iter().find(|t| t.gid == *gid) {
+                        Some(task) => self.print_tasks(std::slice::from_ref(task)),
+                        None => {
+                            eprintln!("任务不存在：{gid}");
+                            code = 1;
+                        }
+                    }
+                }
+                (CliCommand::Status(None), IpcReply::Tasks { tasks }) => self.print_summary(&tasks),
+                (_, IpcReply::Added { gids }) if !self.json => match gids.as_slice() {
+                    [] => println!("没有添加任务"),
+                    gids => println!("已添加任务 {gids:?}"),
+                },
+                (_, reply) if self.json => {
+                    println!("{}", serde_json::to_string(&reply).unwrap_or_default())
+                }
+                _ => println!("完成"),
+            }
+        }
+        Some(code)
+    }
+
+    fn print_tasks(&self, tasks: &[TaskInfo]) {
+        if self.json {
+            println!("{}", serde_json::to_string(tasks).unwrap_or_default());
+            return;
+        }
+        for t in tasks {
+            let percentage = if t.total > 0 {
+                format!("{:.1}%", t.downloaded as f64 / t.total as f64 * 100.)
+            } else {
+                "-".into()
+            };
+            println!(
+                "{:>5}  {:<4}  {:>6}  {:>10} / {:<10}  {:<12}  {}",
+                t.gid,
+                status_label(t.status),
+                percentage,
+                format_size(t.downloaded as f64),
+                format_size(t.total as f64),
+                t.speed,
+                t.file_name
+            );
+            if !t.error.is_empty() {
+                println!("       {}", t.error);
+            }
+        }
+    }
+
+    fn print_summary(&self, tasks: &[TaskInfo]) {
+        let statuses = [
+            TaskStatus::Running,
+            TaskStatus::Verifying,
+            TaskStatus::Extracting,
+            TaskStatus::Waiting,
+            TaskStatus::Paused,
+            TaskStatus::Completed,
+            TaskStatus::Error,
+        ];
+        let counts = statuses.map(|s| (s, tasks.iter().filter(|t| t.status == s).count()));
+        if self.json {
+            let map: serde_json::Map<_, _> = counts
+                .iter()
+                .map(|(s, n)| (serde_json::to_value(s).unwrap_or_default(), n))
+                .filter_map(|(s, n)| Some((s.as_str()?.to_string(), (*n).into())))
+                .collect();
+            println!("{}", serde_json::Value::Object(map));
+            return;
+        }
+        for (status, n) in counts {
+            println!("{}：{n}", status_label(status));
+        }
+    }
+}
+
+fn status_label(status: TaskStatus) -> &'static str {
+    match status {
+        TaskStatus::Running => "下载中",
+        TaskStatus::Verifying => "校验中",
+        TaskStatus::Extracting => "解压中",
+        TaskStatus::Waiting => "等待中",
+        TaskStatus::Paused => "已暂停",
+        TaskStatus::Completed => "已完成",
+        TaskStatus::Error => "出错",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> CliArgs {
+        CliArgs::parse(args.iter().map(|s| s.to_string()))
+    }
+
+    #[test]
+    fn test_parse_args() {
+        let cli = parse(&[
+            "https://example.com/a.iso",
+            "-o",
+            "b.iso",
+            "--dir=/tmp/downloads",
+            "-H",
+            "Cookie: a=b",
+            "--header",
+            "Referer: https://example.com/",
+            "ftp://example.com/c",
+            "--unknown",
+            "http://example.com/d.zip",
+        ]);
+        assert_eq!(cli.command, None);
+        assert_eq!(
+            cli.urls,
+            [
+                Url::parse("https://example.com/a.iso").unwrap(),
+                Url::parse("http://example.com/d.zip").unwrap()
+            ]
+        );
+        assert_eq!(cli.file_name.as_deref(), Some("b.iso"));
+        assert_eq!(cli.save_dir, Some(PathBuf::from("/tmp/downloads")));
+        assert_eq!(
+            cli.headers,
+            ["Cookie: a=b", "Referer: https://example.com/"]
+        );
+        assert_eq!(cli.ignored, ["ftp://example.com/c", "--unknown"]);
+        assert!(!cli.hidden);
+        assert!(!cli.daemon);
+
+        let msgs = cli.ipc_messages();
+        assert_eq!(msgs.len(), 2);
+        let IpcMessage::Download(opts) = &msgs[0] else {
+            panic!("应为下载消息");
+        };
+        assert_eq!(
+            opts.headers.as_deref(),
+            Some("Cookie: a=b\nReferer: https://example.com/")
+        );
+
+        let cli = parse(&["--hidden", "-o"]);
+        assert!(cli.hidden);
+        assert!(!cli.daemon);
+        assert_eq!(cli.file_name, None);
+        assert_eq!(cli.ignored, ["-o"]);
+        assert!(cli.ipc_messages().is_empty());
+
+        assert!(parse(&["--daemon"]).daemon);
+        assert!(parse(&["--headless", "https://example.com/a.iso"]).daemon);
+    }
+
+    #[test]
+    fn test_parse_command() {
+        let cli = parse(&["pause", "3", "5", "--json"]);
+        assert_eq!(cli.command, Some(CliCommand::Pause(vec![3, 5])));
+        assert!(cli.json);
+
+        let cli = parse(&["status", "7", "8"]);
+        assert_eq!(cli.command, Some(CliCommand::Status(Some(7))));
+        assert_eq!(cli.ignored, ["8"]);
+
+        let cli = parse(&["config", "--speed-limit=1024", "--max-concurrency", "4"]);
+        assert_eq!(
+            cli.command,
+            Some(CliCommand::Config {
+                max_concurrency: Some(4),
+                speed_limit: Some(1024)
+            })
+        );
+
+        let cli = parse(&["add", "https://example.com/a.iso", "-d", "/tmp"]);
+        assert_eq!(cli.command, Some(CliCommand::Add));
+        assert_eq!(cli.urls.len(), 1);
+
+        // 子命令只能是第一个参数
+        let cli = parse(&["https://example.com/a.iso", "list"]);
+        assert_eq!(cli.command, None);
+    }
+}
@@ -1,36 +1,193 @@
 use crate::{
-    core::{DownloadEvent, TaskSet, apply_progress_diff},
+    core::{
+        DiskSpace, DownloadEvent, EventSink, HostLimiter, RateLimiter, TaskEvent, TaskSet,
+        Throttle, apply_progress_diff, run_post_actions, start_entry, task_events,
+    },
     fmt::format_size,
-    persist::{self, Database},
-    ui::{self, DownloadConfig, EntryData, GeneralConfig, MainWindow},
-    utils::LogErr,
+    persist::{self, CategoryDirs, Database, ErrorKind, FileChangedPolicy, Priority},
+    ui::{self, DownloadConfig, EntryData, GeneralConfig},
+    utils::{LogErr, PostAction, ScheduleAction, current_action},
 };
 use auto_launch::AutoLaunch;
-use slint::{Model, SharedString, Weak};
-use std::process::exit;
+use chrono::Local;
+use dashmap::DashMap;
+use fast_down_ffi::Total;
+use parking_lot::Mutex;
+use slint::{Model, SharedString, VecModel};
+use std::{
+    collections::HashSet,
+    path::Path,
+    process::exit,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+};
+use tokio::sync::broadcast;
+use tracing::{info, warn};
+
+/// 计划任务的运行状态
+#[derive(Debug)]
+pub struct ScheduleState {
+    action: ScheduleAction,
+    /// 因计划暂停的任务，恢复运行时会重新开始
+    paused: HashSet<i32>,
+}
+
+impl Default for ScheduleState {
+    fn default() -> Self {
+        Self {
+            action: ScheduleAction::Run,
+            paused: HashSet::new(),
+        }
+    }
+}
 
 #[derive(Clone)]
 pub struct App {
     pub db: Database,
     pub task_set: TaskSet<i32>,
-    pub ui: Weak<MainWindow>,
+    /// 主窗口，无界面模式下只更新任务列表
+    pub sink: Arc<dyn EventSink>,
+    /// 全局限速
+    pub limiter: Arc<RateLimiter>,
+    /// 运行中任务的限速
+    pub task_limiters: Arc<DashMap<i32, Arc<RateLimiter>>>,
+    pub schedule: Arc<Mutex<ScheduleState>>,
+    pub disk_space: Arc<DiskSpace>,
+    /// 所有任务共享的单主机连接数
+    pub hosts: Arc<HostLimiter>,
+    /// 正在退出，此时被取消的任务保留原状态，以便下次启动时恢复
+    pub exiting: Arc<AtomicBool>,
+    pub events: broadcast::Sender<TaskEvent>,
+    /// 运行中任务的速度，字节每秒
+    pub speeds: Arc<DashMap<i32, u64>>,
 }
 
 impl App {
+    pub fn new(db: Database, sink: Arc<dyn EventSink>) -> Self {
+        let (max_concurrency, speed_limit, hosts) = {
+            let config = db.inner.general_config.lock();
+            (
+                config.max_concurrency,
+                config.speed_limit,
+                HostLimiter::new(config.host_connections, config.host_overrides.clone()),
+            )
+        };
+        Self {
+            db,
+            task_set: TaskSet::new(max_concurrency),
+            sink,
+            limiter: Arc::new(RateLimiter::new(speed_limit)),
+            task_limiters: Arc::default(),
+            schedule: Arc::default(),
+            disk_space: Arc::default(),
+            hosts: Arc::new(hosts),
+            exiting: Arc::default(),
+            events: task_events(),
+            speeds: Arc::default(),
+        }
+    }
+
     pub fn update_ui_row<F>(&self, gid: i32, mutator: F)
     where
         F: FnOnce(usize, &mut EntryData) + Send + 'static,
     {
-        let _ = self.ui.upgrade_in_event_loop(move |ui| {
-            let list_model = ui.get_all_list();
-            for (row, mut data) in list_model.iter().enumerate() {
-                if data.gid == gid {
-                    mutator(row, &mut data);
-                    list_model.set_row_data(row, data);
-                    break;
+        let events = self.events.clone();
+        self.sink.update_row(
+            gid,
+            Box::new(move |row, data| {
+                let old_status = data.status;
+                mutator(row, data);
+                if data.status != old_status {
+                    let _ = events.send(TaskEvent::Status {
+                        gid,
+                        status: data.status,
+                    });
                 }
+            }),
+        );
+    }
+
+    /// 为任务创建限速器，任务结束后需调用 release_throttle
+    pub fn create_throttle(&self, gid: i32, speed_limit: u64) -> Arc<Throttle> {
+        let task_limiter = Arc::new(RateLimiter::new(speed_limit));
+        self.task_limiters.insert(gid, task_limiter.clone());
+        Arc::new(Throttle::new(self.limiter.clone(), task_limiter))
+    }
+
+    pub fn release_throttle(&self, gid: i32, throttle: &Throttle) {
+        self.task_limiters
+            .remove_if(&gid, |_, l| Arc::ptr_eq(l, throttle.task_limiter()));
+    }
+
+    /// 保存等待队列的顺序
+    pub fn save_queue(&self) {
+        self.db.set_queue(self.task_set.queued_ids());
+    }
+
+    /// 修改任务优先级，排队中的任务会重新排队
+    pub fn set_priority(&self, gid: i32, priority: Priority) {
+        self.db.update_priority(gid, priority);
+        self.task_set.set_priority(&gid, priority);
+        self.save_queue();
+    }
+
+    /// 从列表和数据库中移除任务，运行中的任务会被取消，返回任务是否存在
+    pub fn remove_entry(&self, gid: i32, list_model: &VecModel<EntryData>) -> bool {
+        let mut found = false;
+        for i in (0..list_model.row_count()).rev() {
+            let Some(item) = list_model.row_data(i) else {
+                break;
+            };
+            if item.gid == gid {
+                list_model.remove(i);
+                found = true;
+                break;
             }
-        });
+        }
+        self.task_set.cancel_task(&gid);
+        let _ = self.db.remove_entry(gid).log_err("数据库移除条目失败");
+        if found {
+            let _ = self.events.send(TaskEvent::Removed { gid });
+        }
+        found
+    }
+
+    /// 服务器上的文件改变时的处理方式，没有窗口时无法询问，改为报错停止
+    pub fn file_changed_policy(&self) -> FileChangedPolicy {
+        match self.db.inner.general_config.lock().file_changed {
+            FileChangedPolicy::Ask if !self.sink.has_window() => FileChangedPolicy::Fail,
+            policy => policy,
+        }
+    }
+
+    /// 批量移除任务，一次性重建列表
+    pub fn remove_entries(&self, gids: &HashSet<i32>, list_model: &VecModel<EntryData>) {
+        let mut kept_items = Vec::new();
+        let mut removed = Vec::new();
+        for item in list_model.iter() {
+            if gids.contains(&item.gid) {
+                removed.push(item.gid);
+            } else {
+                kept_items.push(item);
+            }
+        }
+        list_model.set_vec(kept_items);
+        for gid in gids {
+            self.task_set.cancel_task(gid);
+            let _ = self.db.remove_entry(*gid).log_err("数据库移除条目失败");
+        }
+        for gid in removed {
+            let _ = self.events.send(TaskEvent::Removed { gid });
+        }
+    }
+
+    /// 修改运行中任务的限速
+    pub fn set_task_speed_limit(&self, gid: i32, speed_limit: u64) {
+        if let Some(limiter) = self.task_limiters.get(&gid) {
+            limiter.set_rate(speed_limit);
+        }
     }
 
     /// 创建下载过程中的事件处理器
@@ -47,6 +204,7 @@ impl App {
                     .db
                     .init_entry(gid, *info.clone())
                     .log_err("数据库插入条目失败");
+                app.db.update_status(gid, persist::Status::Running);
                 app.update_ui_row(gid, move |_, data| {
                     data.status = ui::Status::Running;
                     data.filename = info.file_name.into();
@@ -55,7 +213,20 @@ impl App {
                 });
             }
             DownloadEvent::Progress(p) => {
+                app.speeds.insert(gid, p.bytes_per_sec);
+                if app.events.receiver_count() > 0 {
+                    let _ = app.events.send(TaskEvent::Progress {
+                        gid,
+                        downloaded: p.progress.total(),
+                        total: file_size,
+                        speed: p.bytes_per_sec,
+                        elapsed: p.elapsed,
+                        progress: p.progress.clone(),
+                    });
+                }
                 app.db.update_entry(gid, p.progress.clone(), p.elapsed);
+                app.disk_space
+                    .update(gid, file_size.saturating_sub(p.progress.total()));
                 app.update_ui_row(gid, move |_, data| {
                     data.downloaded = p.downloaded;
                     data.speed = p.speed;
@@ -80,8 +251,55 @@ impl App {
                     data.error = e;
                 });
             }
+            DownloadEvent::Verifying(p) => {
+                app.update_ui_row(gid, move |_, data| {
+                    data.status = ui::Status::Verifying;
+                    data.error = format!("正在校验文件 {:.2}%", p * 100.).into();
+                });
+            }
+            DownloadEvent::Verified(matched) => app.db.update_verified(gid, matched),
+            DownloadEvent::Renamed(path) => {
+                app.db.update_file_path(gid, path.clone());
+                app.update_ui_row(gid, move |_, data| {
+                    if let Some(file_name) = path.file_name() {
+                        data.filename = file_name.to_string_lossy().as_ref().into();
+                    }
+                    data.path = path.to_string_lossy().as_ref().into();
+                });
+            }
+            DownloadEvent::UpToDate => {
+                app.db.update_status(gid, persist::Status::Completed);
+                app.update_ui_row(gid, move |_, data| {
+                    data.status = ui::Status::Completed;
+                    data.error = "服务器上的文件没有更新".into();
+                });
+            }
+            DownloadEvent::KeptOld => {
+                app.db.update_status(gid, persist::Status::Completed);
+                app.update_ui_row(gid, move |_, data| {
+                    data.status = ui::Status::Completed;
+                    data.error = "服务器上的文件已更新，已保留旧版本".into();
+                });
+            }
+            DownloadEvent::End { is_cancelled }
+                if is_cancelled && app.disk_space.take_paused(gid) =>
+            {
+                app.speeds.remove(&gid);
+                let kind = ErrorKind::DiskFull;
+                app.db.update_status(gid, persist::Status::Paused);
+                app.db.update_error_kind(gid, Some(kind));
+                app.update_ui_row(gid, move |_, data| {
+                    data.status = ui::Status::Paused;
+                    data.error = kind.message().into();
+                    data.hint = kind.suggestion().into();
+                });
+            }
             DownloadEvent::End { is_cancelled } => {
-                let db_status = if is_cancelled {
+                app.speeds.remove(&gid);
+                let schedule_paused = is_cancelled && app.is_schedule_paused(gid);
+                let db_status = if schedule_paused {
+                    persist::Status::Waiting
+                } else if is_cancelled {
                     persist::Status::Paused
                 } else {
                     persist::Status::Completed
@@ -91,11 +309,31 @@ impl App {
                 } else {
                     ui::Status::Completed
                 };
-                app.db.update_status(gid, db_status);
+                let error: SharedString = if schedule_paused {
+                    "已按计划暂停，将在计划允许时继续".into()
+                } else {
+                    SharedString::default()
+                };
+                if !(is_cancelled && app.exiting.load(Ordering::Relaxed)) {
+                    app.db.update_status(gid, db_status);
+                }
                 app.update_ui_row(gid, move |_, data| {
                     data.status = ui_status;
-                    data.error = SharedString::default();
+                    data.error = error;
+                    data.action_result = SharedString::new();
+                    data.action_failed = false;
                 });
+                if !is_cancelled {
+                    app.db.update_action_result(gid, None);
+                    let auto_extract = {
+                        let config = app.db.inner.general_config.lock();
+                        config.auto_extract.then_some(config.delete_archive)
+                    };
+                    let actions = app.post_actions(gid);
+                    if auto_extract.is_some() || !actions.is_empty() {
+                        tokio::spawn(run_post_actions(app.clone(), gid, auto_extract, actions));
+                    }
+                }
             }
         }
     }
@@ -106,10 +344,14 @@ impl App {
         general_config: GeneralConfig,
         auto: Option<&AutoLaunch>,
     ) {
-        self.task_set
-            .set_concurrency(general_config.max_concurrency as usize);
         self.db.set_download_config(&download_config);
         self.db.set_general_config(&general_config);
+        self.apply_limits();
+        {
+            let config = self.db.inner.general_config.lock();
+            self.hosts
+                .set_limits(config.host_connections, config.host_overrides.clone());
+        }
         if let Some(auto) = auto {
             if general_config.auto_start {
                 let _ = auto.enable().log_err("启用开机自启失败");
@@ -117,16 +359,112 @@ impl App {
                 let _ = auto.disable().log_err("禁用开机自启失败");
             }
         }
-        let _ = self.ui.upgrade_in_event_loop(move |ui| {
-            ui.set_download_config(download_config);
-            ui.set_general_config(general_config);
-        });
+        self.sink.config_changed(download_config, general_config);
+        let _ = self.events.send(TaskEvent::ConfigChanged);
+    }
+
+    /// 开启按类型分类时返回各类文件的文件夹，任务单独指定了保存文件夹时不分类
+    pub fn category_dirs(&self, save_dir: &Path) -> Option<CategoryDirs> {
+        let config = self.db.inner.general_config.lock();
+        if !config.categorize {
+            return None;
+        }
+        let is_default = save_dir.as_os_str().is_empty()
+            || save_dir == self.db.inner.download_config.lock().save_dir;
+        is_default.then(|| config.category_dirs.clone())
+    }
+
+    /// 全局的完成后操作在前，任务单独的在后
+    fn post_actions(&self, gid: i32) -> Vec<PostAction> {
+        let mut actions = self.db.inner.general_config.lock().post_actions.clone();
+        if let Some(entry) = self.db.inner.data.get(&gid) {
+            actions.extend(entry.config.post_actions.iter().cloned());
+        }
+        actions
+    }
+
+    fn is_schedule_paused(&self, gid: i32) -> bool {
+        let schedule = self.schedule.lock();
+        schedule.action == ScheduleAction::Pause && schedule.paused.contains(&gid)
+    }
+
+    /// 按配置和当前计划设置并发数和全局限速
+    fn apply_limits(&self) {
+        let action = self.schedule.lock().action;
+        let (max_concurrency, speed_limit) = {
+            let config = self.db.inner.general_config.lock();
+            (config.max_concurrency, config.speed_limit)
+        };
+        let max_concurrency = match action {
+            ScheduleAction::Pause => 0,
+            _ => max_concurrency,
+        };
+        let speed_limit = match (action, speed_limit) {
+            (ScheduleAction::Limit(limit), 0) => limit,
+            (ScheduleAction::Limit(limit), speed_limit) => limit.min(speed_limit),
+            _ => speed_limit,
+        };
+        self.task_set.set_concurrency(max_concurrency);
+        if self.limiter.rate() != speed_limit {
+            self.limiter.set_rate(speed_limit);
+        }
+    }
+
+    /// 检查计划规则，进入暂停时段时暂停运行中的任务，离开后恢复这些任务，需要在 UI 线程调用
+    pub fn apply_schedule(&self, list_model: &VecModel<EntryData>) {
+        let rules = self.db.inner.general_config.lock().schedule.clone();
+        let action = current_action(&rules, &Local::now());
+        let (old_action, resumed) = {
+            let mut schedule = self.schedule.lock();
+            if schedule.action == action {
+                return;
+            }
+            let old_action = std::mem::replace(&mut schedule.action, action);
+            let resumed = if action == ScheduleAction::Pause {
+                schedule.paused.extend(self.task_set.running_ids());
+                HashSet::new()
+            } else {
+                std::mem::take(&mut schedule.paused)
+            };
+            (old_action, resumed)
+        };
+        info!(?old_action, ?action, "计划切换");
+        self.apply_limits();
+        if action == ScheduleAction::Pause {
+            let paused = self.schedule.lock().paused.clone();
+            for gid in paused {
+                self.task_set.cancel_task(&gid);
+            }
+            return;
+        }
+        for i in 0..list_model.row_count() {
+            let Some(mut entry) = list_model.row_data(i) else {
+                continue;
+            };
+            if resumed.contains(&entry.gid) && start_entry(self, &entry, list_model) {
+                entry.status = ui::Status::Waiting;
+                list_model.set_row_data(i, entry);
+            }
+        }
+    }
+
+    /// 磁盘剩余空间低于设置值时暂停该磁盘上运行中的任务
+    pub fn check_disk_space(&self) {
+        let min_free_space = self.db.inner.general_config.lock().min_free_space;
+        if min_free_space == 0 {
+            return;
+        }
+        for gid in self.disk_space.low_space_tasks(min_free_space) {
+            warn!(gid = gid, "磁盘剩余空间不足，暂停任务");
+            self.task_set.cancel_task(&gid);
+        }
     }
 
     pub fn exit(&self) {
         let db = self.db.clone();
         let fut = tokio::task::spawn_blocking(move || db.flush_force_sync());
         let task_set = self.task_set.clone();
+        self.exiting.store(true, Ordering::Relaxed);
         task_set.cancel_all();
         tokio::spawn(async move {
             task_set.join().await;
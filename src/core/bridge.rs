@@ -0,0 +1,44 @@
+use crate::{core::App, ui::EntryData, utils::spawn_local};
+use crossfire::{
+    MTx,
+    mpsc::{self, List},
+};
+use slint::VecModel;
+use std::rc::Rc;
+use tokio::sync::oneshot;
+
+type UiJob = Box<dyn FnOnce(&App, &VecModel<EntryData>) + Send>;
+
+/// 让其他线程在 UI 线程访问任务列表，供 RPC 等外部接口使用
+#[derive(Clone)]
+pub struct UiBridge {
+    tx: MTx<List<UiJob>>,
+}
+
+impl UiBridge {
+    /// 需要在 UI 线程调用
+    pub fn new(app: App, list_model: Rc<VecModel<EntryData>>) -> color_eyre::Result<Self> {
+        let (tx, rx) = mpsc::unbounded_async::<UiJob>();
+        spawn_local(async move {
+            while let Ok(job) = rx.recv().await {
+                job(&app, &list_model);
+            }
+        })?;
+        Ok(Self { tx })
+    }
+
+    /// 在 UI 线程执行 f 并等待结果，事件循环已退出时返回 None
+    pub async fn run<T, F>(&self, f: F) -> Option<T>
+    where
+        T: Send + 'static,
+        F: FnOnce(&App, &VecModel<EntryData>) -> T + Send + 'static,
+    {
+        let (reply_tx, reply_rx) = oneshot::channel();
+        self.tx
+            .send(Box::new(move |app, list| {
+                let _ = reply_tx.send(f(app, list));
+            }))
+            .ok()?;
+        reply_rx.await.ok()
+    }
+}
@@ -0,0 +1,171 @@
+use crate::{
+    core::DownloadError,
+    os::{free_space, volume_root},
+};
+use dashmap::DashMap;
+use parking_lot::Mutex;
+use std::{
+    collections::{HashMap, HashSet},
+    io,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+use tracing::warn;
+
+/// 运行中任务所在的磁盘和尚未写入的大小
+#[derive(Debug)]
+struct Reserved {
+    root: PathBuf,
+    remaining: u64,
+}
+
+/// 记录运行中任务占用的磁盘空间，用于下载前检查和下载中监控剩余空间
+#[derive(Debug)]
+pub struct DiskSpace {
+    tasks: DashMap<i32, Reserved>,
+    /// 因剩余空间不足被暂停的任务
+    paused: Mutex<HashSet<i32>>,
+    /// 获取磁盘剩余空间
+    free_space: fn(&Path) -> io::Result<u64>,
+}
+
+impl Default for DiskSpace {
+    fn default() -> Self {
+        Self::with_free_space(free_space)
+    }
+}
+
+impl DiskSpace {
+    /// 指定获取剩余空间的方法
+    pub fn with_free_space(free_space: fn(&Path) -> io::Result<u64>) -> Self {
+        Self {
+            tasks: DashMap::new(),
+            paused: Mutex::default(),
+            free_space,
+        }
+    }
+
+    /// 为任务创建空间预留，任务结束时自动释放
+    pub fn guard(self: &Arc<Self>, gid: i32, min_free: u64) -> SpaceGuard {
+        SpaceGuard {
+            disk: self.clone(),
+            gid,
+            min_free,
+        }
+    }
+
+    /// 更新任务尚未写入的大小
+    pub fn update(&self, gid: i32, remaining: u64) {
+        if let Some(mut r) = self.tasks.get_mut(&gid) {
+            r.remaining = remaining;
+        }
+    }
+
+    /// 找出剩余空间低于 min_free 的磁盘上运行中的任务，并标记为因空间不足暂停
+    pub fn low_space_tasks(&self, min_free: u64) -> Vec<i32> {
+        let mut free = HashMap::new();
+        let low: Vec<_> = self
+            .tasks
+            .iter()
+            .filter(|r| {
+                *free.entry(r.root.clone()).or_insert_with(|| {
+                    (self.free_space)(&r.root)
+                        .inspect_err(|e| warn!(err = ?e, root = ?r.root, "获取磁盘剩余空间失败"))
+                        .unwrap_or(u64::MAX)
+                }) < min_free
+            })
+            .map(|r| *r.key())
+            .collect();
+        self.paused.lock().extend(low.iter().copied());
+        low
+    }
+
+    /// 任务是否因空间不足被暂停，调用后清除标记
+    pub fn take_paused(&self, gid: i32) -> bool {
+        self.paused.lock().remove(&gid)
+    }
+
+    fn reserve(
+        &self,
+        gid: i32,
+        path: &Path,
+        remaining: u64,
+        min_free: u64,
+    ) -> Result<(), DownloadError> {
+        let root = match volume_root(path) {
+            Ok(root) => root,
+            Err(e) => {
+                warn!(err = ?e, path = ?path, "获取文件所在磁盘失败，跳过空间检查");
+                return Ok(());
+            }
+        };
+        let others: u64 = self
+            .tasks
+            .iter()
+            .filter(|r| *r.key() != gid && r.root == root)
+            .map(|r| r.remaining)
+            .sum();
+        match (self.free_space)(&root) {
+            Ok(free) if free < remaining.saturating_add(others).saturating_add(min_free) => {
+                warn!(free, remaining, others, min_free, root = ?root, "磁盘剩余空间不足");
+                return Err(DownloadError::DiskFull);
+            }
+            Ok(_) => {}
+            Err(e) => warn!(err = ?e, root = ?root, "获取磁盘剩余空间失败，跳过空间检查"),
+        }
+        self.tasks.insert(gid, Reserved { root, remaining });
+        Ok(())
+    }
+}
+
+/// 单个任务的空间预留，drop 时释放
+#[derive(Debug)]
+pub struct SpaceGuard {
+    disk: Arc<DiskSpace>,
+    gid: i32,
+    /// 磁盘上至少要保留的空间
+    min_free: u64,
+}
+
+impl SpaceGuard {
+    /// 检查保存路径所在磁盘能否放下尚未下载的部分，同一磁盘上其他任务尚未写入的部分也计算在内
+    pub fn reserve(&self, path: &Path, remaining: u64) -> Result<(), DownloadError> {
+        self.disk.reserve(self.gid, path, remaining, self.min_free)
+    }
+}
+
+impl Drop for SpaceGuard {
+    fn drop(&mut self) {
+        self.disk.tasks.remove(&self.gid);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reserve() {
+        let dir = std::env::temp_dir();
+        let free = 1000;
+        let disk = Arc::new(DiskSpace::with_free_space(|_| Ok(1000)));
+        let a = disk.guard(1, 0);
+        let b = disk.guard(2, 0);
+        a.reserve(&dir.join("a"), free / 2).unwrap();
+        assert!(matches!(
+            b.reserve(&dir.join("b"), free / 4 * 3),
+            Err(DownloadError::DiskFull)
+        ));
+        drop(a);
+        b.reserve(&dir.join("b"), free / 4 * 3).unwrap();
+        let c = disk.guard(3, free / 4 + 1);
+        assert!(matches!(
+            c.reserve(&dir.join("c"), 0),
+            Err(DownloadError::DiskFull)
+        ));
+        assert!(disk.low_space_tasks(free).is_empty());
+        assert_eq!(disk.low_space_tasks(free + 1), vec![2]);
+        assert!(disk.take_paused(2));
+        assert!(!disk.take_paused(2));
+    }
+}
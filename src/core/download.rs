@@ -1,39 +1,199 @@
 use crate::{
+    core::{
+        HostLimiter, HostPuller, MirrorPuller, SpaceGuard, Throttle, ThrottledPuller,
+        start_with_puller,
+    },
     fmt::{format_size, format_time},
-    persist::{self, DatabaseEntry, Status},
+    persist::{self, CategoryDirs, DatabaseEntry, ErrorKind, FileChangedPolicy, Priority, Status},
     ui::DownloadConfig,
-    utils::{auto_ext, sanitize, sanitize_path},
+    utils::{Category, ask_file_changed, auto_ext, hash_file, sanitize, sanitize_path},
 };
 use chrono::Local;
-use fast_down_ffi::{Event, Total, create_channel, prefetch, unique_path::gen_unique_path};
+use fast_down_ffi::{
+    DownloadTask, Event, FileId, Puller, RawEvent, Total, UrlInfo, create_channel,
+    fast_puller::{FastDownPuller, FastDownPullerOptions},
+    get_pusher,
+    http::HttpError,
+    prefetch,
+    reqwest_adapter::ReqwestResponseError,
+    unique_path::gen_unique_path,
+};
 use file_alloc::FileAlloc;
+use futures::TryStreamExt;
 use parking_lot::Mutex;
 use slint::SharedString;
 use soft_canonicalize::soft_canonicalize;
 use std::{
     borrow::Cow,
+    fmt, io,
     ops::Range,
     panic,
-    path::PathBuf,
-    sync::Arc,
+    path::{Path, PathBuf},
+    sync::{
+        Arc,
+        atomic::{AtomicU64, Ordering},
+    },
     time::{Duration, Instant},
 };
-use tokio::fs::{self, OpenOptions};
+use tokio::{
+    fs::{self, OpenOptions},
+    io::{AsyncReadExt, AsyncSeekExt},
+};
 use tokio_util::sync::CancellationToken;
 use tracing::{error, info, warn};
 use url::Url;
 
+const PART_EXT: &str = "fdpart";
+
 pub enum DownloadEvent {
     Info(Box<DatabaseEntry>),
     Progress(ProgressInfo),
     Flushing,
     FlushError(SharedString),
-    End { is_cancelled: bool },
+    /// 校验进度，0 ~ 1
+    Verifying(f64),
+    Verified(bool),
+    /// 检查更新时服务器上的文件没有变化
+    UpToDate,
+    /// 检查更新时服务器上的文件已改变，按设置保留了旧版本
+    KeptOld,
+    /// 临时文件已重命名为最终的文件
+    Renamed(PathBuf),
+    End {
+        is_cancelled: bool,
+    },
+}
+
+#[derive(Debug)]
+pub enum DownloadError {
+    /// 服务器返回了错误的状态码
+    Http(u16),
+    Network(String),
+    DiskFull,
+    PermissionDenied(String),
+    /// 服务器上的文件和已下载的部分不一致
+    FileChanged,
+    ChecksumMismatch {
+        expected: String,
+        actual: String,
+    },
+    Io(String),
+    Cancelled,
+    Other(String),
+}
+
+impl DownloadError {
+    pub fn kind(&self) -> ErrorKind {
+        match self {
+            DownloadError::Http(code) => ErrorKind::Http(*code),
+            DownloadError::Network(_) => ErrorKind::Network,
+            DownloadError::DiskFull => ErrorKind::DiskFull,
+            DownloadError::PermissionDenied(_) => ErrorKind::PermissionDenied,
+            DownloadError::FileChanged => ErrorKind::FileChanged,
+            DownloadError::ChecksumMismatch { .. } => ErrorKind::ChecksumMismatch,
+            DownloadError::Io(_) => ErrorKind::Io,
+            DownloadError::Cancelled => ErrorKind::Cancelled,
+            DownloadError::Other(_) => ErrorKind::Other,
+        }
+    }
+
+    /// 写入错误在下载库中只保留了 Debug 文本，只能从中识别 io 错误类型
+    fn from_io_debug(text: &str) -> Option<Self> {
+        if text.contains("StorageFull") || text.contains("QuotaExceeded") {
+            Some(DownloadError::DiskFull)
+        } else if text.contains("PermissionDenied") {
+            Some(DownloadError::PermissionDenied(text.to_string()))
+        } else {
+            None
+        }
+    }
+}
+
+impl fmt::Display for DownloadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let message = self.kind().message();
+        match self {
+            DownloadError::Network(e)
+            | DownloadError::PermissionDenied(e)
+            | DownloadError::Io(e) => write!(f, "{message}：{e}"),
+            DownloadError::ChecksumMismatch { expected, actual } => {
+                write!(f, "{message}，期望 {expected}，实际 {actual}")
+            }
+            DownloadError::Other(e) => f.write_str(e),
+            _ => f.write_str(&message),
+        }
+    }
+}
+
+impl std::error::Error for DownloadError {}
+
+impl From<&io::Error> for DownloadError {
+    fn from(e: &io::Error) -> Self {
+        match e.kind() {
+            io::ErrorKind::StorageFull | io::ErrorKind::QuotaExceeded => DownloadError::DiskFull,
+            io::ErrorKind::PermissionDenied => DownloadError::PermissionDenied(e.to_string()),
+            _ => DownloadError::Io(e.to_string()),
+        }
+    }
+}
+
+impl From<io::Error> for DownloadError {
+    fn from(e: io::Error) -> Self {
+        (&e).into()
+    }
+}
+
+impl From<&fast_down_ffi::Error> for DownloadError {
+    fn from(e: &fast_down_ffi::Error) -> Self {
+        match e {
+            fast_down_ffi::Error::Io(e) => {
+                DownloadError::from_io_debug(e).unwrap_or_else(|| DownloadError::Io(e.clone()))
+            }
+            fast_down_ffi::Error::Request(e) => match e.status() {
+                Some(status) if !status.is_success() => DownloadError::Http(status.as_u16()),
+                _ => DownloadError::Network(e.to_string()),
+            },
+            fast_down_ffi::Error::PrefetchTimeout(HttpError::Request(
+                ReqwestResponseError::StatusCode(status),
+            )) => DownloadError::Http(status.as_u16()),
+            fast_down_ffi::Error::PrefetchTimeout(HttpError::MismatchedBody(_)) => {
+                DownloadError::FileChanged
+            }
+            fast_down_ffi::Error::PrefetchTimeout(e) => DownloadError::Network(format!("{e:?}")),
+            fast_down_ffi::Error::Task(e) if e.is_cancelled() => DownloadError::Cancelled,
+            e => DownloadError::Other(e.to_string()),
+        }
+    }
+}
+
+impl From<fast_down_ffi::Error> for DownloadError {
+    fn from(e: fast_down_ffi::Error) -> Self {
+        (&e).into()
+    }
+}
+
+impl From<color_eyre::Report> for DownloadError {
+    fn from(e: color_eyre::Report) -> Self {
+        for cause in e.chain() {
+            if let Some(e) = cause.downcast_ref::<fast_down_ffi::Error>() {
+                return e.into();
+            }
+            if let Some(e) = cause.downcast_ref::<io::Error>() {
+                return e.into();
+            }
+        }
+        match e.downcast::<DownloadError>() {
+            Ok(e) => e,
+            Err(e) => DownloadError::Other(format!("{e:#}")),
+        }
+    }
 }
 
 pub struct ProgressInfo {
     pub downloaded: SharedString,
     pub speed: SharedString,
+    /// 平滑后的速度，字节每秒
+    pub bytes_per_sec: u64,
     pub avg_speed: SharedString,
     pub time: SharedString,
     pub remaining_time: SharedString,
@@ -43,19 +203,30 @@ pub struct ProgressInfo {
     pub progress: Vec<Range<u64>>,
 }
 
+#[allow(clippy::too_many_arguments)]
 pub async fn download(
     url: Url,
+    mirrors: Vec<Url>,
     config: &DownloadConfig,
     cancel_token: CancellationToken,
     mut entry: Option<DatabaseEntry>,
+    throttle: Arc<Throttle>,
+    space: SpaceGuard,
+    hosts: Arc<HostLimiter>,
+    file_changed: FileChangedPolicy,
+    category_dirs: Option<CategoryDirs>,
     mut on_event: impl FnMut(DownloadEvent) + Send + Sync + 'static,
-) -> color_eyre::Result<()> {
+) -> Result<(), DownloadError> {
     info!(url = url.as_str(), config = ?config, "启动下载");
     let result = async {
+        let attempts = entry.as_ref().map_or(0, |e| e.attempts);
         let file_exists = matches!(&entry, Some(entry) if fs::try_exists(&entry.file_path).await.unwrap_or(false));
         if !file_exists {
             entry = None
         }
+        // 对已完成的任务重新下载意味着检查更新
+        let is_update = matches!(&entry, Some(e) if e.status == Status::Completed);
+        let priority = Priority::from_ui(config.priority);
         let config: persist::DownloadConfig = config.into();
         let progress = Arc::new(Mutex::new(
             entry
@@ -82,14 +253,98 @@ pub async fn download(
             downloaded_chunk: progress.clone(),
             chunk_window: config.chunk_window,
         };
-        let elapsed = entry.as_ref().map(|e| e.elapsed).unwrap_or_default();
+        let mut elapsed = entry.as_ref().map(|e| e.elapsed).unwrap_or_default();
         let (tx, rx) = create_channel();
+        let mirror_config = download_config.clone();
         let task = prefetch(url.clone(), download_config, tx).await?;
         info!(info = ?task.info, "获取元数据成功");
         let total_size = task.info.size;
-        let (save_path, entry) = if let Some(entry) = entry
+        if let Some(expected) = config.expected_size
+            && !is_update
+            && total_size != expected
+        {
+            return Err(DownloadError::Other(format!(
+                "文件大小与预期不一致，预期 {expected} 字节，服务器返回 {total_size} 字节"
+            ))
+            .into());
+        }
+        let mut checksum = config.checksum.clone();
+        let mut appended = false;
+        // 检查更新时下载到临时文件，完成后替换的旧文件
+        let mut replace = None;
+        if let Some(e) = &entry
+            && is_update
+        {
+            if total_size == e.file_size && !is_file_changed(&e.file_id, &task.info.file_id) {
+                info!(size = total_size, "服务器上的文件没有更新");
+                on_event(DownloadEvent::UpToDate);
+                return Ok(None);
+            }
+            // 追加内容后 ETag 和 Last-Modified 通常也会改变，只能比较数据本身
+            appended = total_size > e.file_size
+                && task.info.fast_download
+                && is_appended(&task, &e.file_path, e.file_size).await;
+            if appended {
+                info!(
+                    old = e.file_size,
+                    new = total_size,
+                    "服务器上的文件有新增内容"
+                );
+                // 原来的校验值对应旧文件，不再适用
+                checksum = None;
+            }
+        }
+        if let Some(e) = &entry
+            && !appended
+            && (is_update || is_file_changed(&e.file_id, &task.info.file_id))
+        {
+            warn!(old = ?e.file_id, new = ?task.info.file_id, "服务器上的文件已改变");
+            let policy = match file_changed {
+                FileChangedPolicy::Ask => ask_file_changed(&e.file_name).await,
+                policy => policy,
+            };
+            match policy {
+                FileChangedPolicy::Restart if is_update => {
+                    info!(path = ?e.file_path, "下载新版本，完成后替换旧文件");
+                    replace = Some(e.file_path.clone());
+                    progress.lock().clear();
+                    elapsed = Duration::ZERO;
+                }
+                FileChangedPolicy::Restart => {
+                    info!(path = ?e.file_path, "删除已下载的部分，从头下载");
+                    fs::remove_file(&e.file_path).await?;
+                    progress.lock().clear();
+                    elapsed = Duration::ZERO;
+                    entry = None;
+                }
+                FileChangedPolicy::Keep if is_update => {
+                    info!("保留旧版本，不更新文件");
+                    on_event(DownloadEvent::KeptOld);
+                    return Ok(None);
+                }
+                FileChangedPolicy::Keep => info!("保留已下载的部分继续下载"),
+                _ => return Err(DownloadError::FileChanged.into()),
+            }
+        }
+        let (save_path, entry) = if let Some(mut entry) = entry
             && fs::try_exists(&entry.file_path).await.unwrap_or(false)
         {
+            entry.file_id = task.info.file_id.clone();
+            entry.file_size = total_size;
+            if appended {
+                entry.config.checksum = None;
+                entry.verified = None;
+            }
+            if let Some(old) = &replace {
+                entry.file_path = part_path(old);
+                entry.verified = None;
+                // 上次没有完成的新版本
+                if let Err(e) = fs::remove_file(&entry.file_path).await
+                    && e.kind() != io::ErrorKind::NotFound
+                {
+                    return Err(e.into());
+                }
+            }
             (entry.file_path.clone(), entry)
         } else {
             let mut save_dir =
@@ -120,9 +375,21 @@ pub async fn download(
                     save_dir = new_save_dir;
                 }
             }
+            if let Some(dirs) = &category_dirs
+                && let Some(category) =
+                    Category::detect(&file_name, task.info.content_type.as_deref())
+            {
+                info!(category = ?category, "按文件类型分类保存");
+                save_dir = save_dir.join(sanitize_path(dirs.get(category)));
+            }
             let _ = fs::create_dir_all(&save_dir).await;
-            let save_path = gen_unique_path(&save_dir.join(&file_name)).await?;
+            let save_path = gen_save_path(&save_dir.join(&file_name), config.part_file).await?;
             let file_name = save_path.file_name().unwrap().to_string_lossy().to_string();
+            let save_path = if config.part_file {
+                part_path(&save_path)
+            } else {
+                save_path
+            };
             (
                 save_path.clone(),
                 DatabaseEntry {
@@ -133,11 +400,23 @@ pub async fn download(
                     progress: Vec::new(),
                     elapsed: Duration::ZERO,
                     url,
-                    config,
+                    config: config.clone(),
                     status: Status::Paused,
+                    verified: None,
+                    start_at: None,
+                    priority,
+                    attempts,
+                    error_kind: None,
+                    mirrors: mirrors.clone(),
+                    action_result: None,
                 },
             )
         };
+        let mirrors = prefetch_mirrors(mirrors, mirror_config, &task.info).await;
+        space.reserve(
+            &save_path,
+            total_size.saturating_sub(progress.lock().total()),
+        )?;
         on_event(DownloadEvent::Info(Box::new(entry)));
         if pre_allocate && total_size > 1024 * 1024 && progress.lock().is_empty() {
             let mut file = OpenOptions::new()
@@ -148,24 +427,37 @@ pub async fn download(
                 .await?;
             file.allocate(total_size).await?;
         }
-        Ok::<_, color_eyre::Report>((
+        Ok::<_, color_eyre::Report>(Some((
             task,
             save_path,
             cancel_token.clone(),
             elapsed,
             total_size,
             rx,
-        ))
+            checksum,
+            mirrors,
+            replace,
+        )))
     };
-    let (task, save_path, cancel_token, elapsed, total_size, rx) = tokio::select! {
+    let (task, save_path, cancel_token, elapsed, total_size, rx, checksum, mirrors, replace) = tokio::select! {
         _ = cancel_token.cancelled() => {
             on_event(DownloadEvent::End { is_cancelled: true });
             return Ok(());
         },
-        res = result => res?,
+        res = result => match res? {
+            Some(res) => res,
+            None => return Ok(()),
+        },
     };
     tokio::pin! {
-        let fut = task.start(save_path, cancel_token.clone());
+        let fut = start_task(
+            &task,
+            &mirrors,
+            save_path.clone(),
+            cancel_token.clone(),
+            throttle,
+            hosts,
+        );
     };
 
     let progress = &task.config.downloaded_chunk;
@@ -192,6 +484,7 @@ pub async fn download(
             on_event(DownloadEvent::Progress(ProgressInfo {
                 downloaded: format_size(downloaded as f64).into(),
                 speed: format!("{}/s", format_size(smoothed_speed)).into(),
+                bytes_per_sec: smoothed_speed as u64,
                 avg_speed: format!("{}/s", format_size(avg_speed)).into(),
                 time: format_time($total_elapsed.as_secs()).into(),
                 remaining_time: format_time(remaining_time as u64).into(),
@@ -205,6 +498,7 @@ pub async fn download(
     }
 
     let mut is_first = true;
+    let mut flush_failed = false;
     loop {
         tokio::select! {
             res = &mut fut => {
@@ -237,6 +531,7 @@ pub async fn download(
                     },
                     Event::FlushError(e) => {
                         error!(err = e, "磁盘刷写失败");
+                        flush_failed = true;
                         on_event(DownloadEvent::FlushError(e.into()));
                     },
                     Event::Finished(id) => info!(id = id, "下载完成"),
@@ -259,12 +554,288 @@ pub async fn download(
             }
         }
     }
+    if !cancel_token.is_cancelled()
+        && let Some(checksum) = checksum
+    {
+        info!(checksum = %checksum, "开始校验文件");
+        on_event(DownloadEvent::Verifying(0.));
+        let file_size = fs::metadata(&save_path).await?.len();
+        let read = Arc::new(AtomicU64::new(0));
+        let mut handle = tokio::task::spawn_blocking({
+            let read = read.clone();
+            let token = cancel_token.clone();
+            let save_path = save_path.clone();
+            move || {
+                hash_file(&save_path, checksum.algorithm, &token, |n| {
+                    read.store(n, Ordering::Relaxed)
+                })
+            }
+        });
+        let mut interval = tokio::time::interval(Duration::from_millis(500));
+        let actual = loop {
+            tokio::select! {
+                res = &mut handle => break res.map_err(|e| DownloadError::Other(e.to_string()))??,
+                _ = interval.tick() => {
+                    let read = read.load(Ordering::Relaxed);
+                    on_event(DownloadEvent::Verifying(read as f64 / file_size.max(1) as f64));
+                }
+            }
+        };
+        if let Some(actual) = actual {
+            let matched = actual == checksum.value;
+            on_event(DownloadEvent::Verified(matched));
+            if !matched {
+                error!(expected = %checksum, actual = actual, "文件校验失败");
+                return Err(DownloadError::ChecksumMismatch {
+                    expected: checksum.to_string(),
+                    actual: format!("{}:{actual}", checksum.algorithm.name()),
+                });
+            }
+            info!("文件校验通过");
+        }
+    }
+    if !cancel_token.is_cancelled()
+        && !flush_failed
+        && let Some(path) = final_path(&save_path)
+    {
+        // 新版本下载完成后才替换旧文件
+        let path = match replace {
+            Some(old) if old == path => old,
+            _ => gen_unique_path(&path).await?,
+        };
+        fs::rename(&save_path, &path).await?;
+        info!(path = ?path, "临时文件已重命名");
+        on_event(DownloadEvent::Renamed(path));
+    }
     on_event(DownloadEvent::End {
         is_cancelled: cancel_token.is_cancelled(),
     });
     Ok(())
 }
 
+/// 创建经过镜像、连接数限制和限速器的 puller 并开始下载
+async fn start_task(
+    task: &DownloadTask,
+    mirrors: &[DownloadTask],
+    save_path: PathBuf,
+    cancel_token: CancellationToken,
+    throttle: Arc<Throttle>,
+    hosts: Arc<HostLimiter>,
+) -> Result<(), DownloadError> {
+    let config = &task.config;
+    let pusher = get_pusher(
+        &task.info,
+        config.write_method.clone(),
+        config.write_buffer_size,
+        &save_path,
+    );
+    let pusher = tokio::select! {
+        () = cancel_token.cancelled() => return Ok(()),
+        pusher = pusher => pusher.map_err(fast_down_ffi::Error::Io)?,
+    };
+    let headers = request_headers(config);
+    let pullers = std::iter::once(task)
+        .chain(mirrors)
+        .map(|t| {
+            let connections = hosts.host(t.info.final_url.host_str().unwrap_or_default());
+            FastDownPuller::new(FastDownPullerOptions {
+                url: t.info.final_url.clone(),
+                headers: headers.clone(),
+                proxy: config.proxy.as_deref(),
+                available_ips: config.local_address.clone().into(),
+                accept_invalid_certs: config.accept_invalid_certs,
+                accept_invalid_hostnames: config.accept_invalid_hostnames,
+                file_id: t.info.file_id.clone(),
+                resp: t.resp.clone(),
+            })
+            .map(|p| HostPuller::new(p, connections))
+        })
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(fast_down_ffi::Error::from)?;
+    let puller = ThrottledPuller::new(MirrorPuller::new(pullers), throttle.clone());
+    let max_threads = config.threads.max(1);
+    start_with_puller(
+        task,
+        puller,
+        pusher,
+        cancel_token,
+        || throttle.threads(max_threads),
+        |e| match e {
+            // 下载库会无限重试这两类错误，这里直接结束任务
+            RawEvent::PullError(_, HttpError::MismatchedBody(_)) => {
+                Some(DownloadError::FileChanged)
+            }
+            RawEvent::PushError(_, _, e) => DownloadError::from_io_debug(&format!("{e:?}")),
+            _ => None,
+        },
+    )
+    .await
+}
+
+fn request_headers(config: &fast_down_ffi::Config) -> Arc<http::HeaderMap> {
+    let headers = config
+        .headers
+        .iter()
+        .filter_map(|(k, v)| Some((k.parse().ok()?, v.parse().ok()?)))
+        .collect();
+    Arc::new(headers)
+}
+
+/// 获取镜像的元数据，只保留和主地址是同一个文件的镜像
+async fn prefetch_mirrors(
+    mirrors: Vec<Url>,
+    config: fast_down_ffi::Config,
+    info: &UrlInfo,
+) -> Vec<DownloadTask> {
+    if mirrors.is_empty() {
+        return Vec::new();
+    }
+    if !info.fast_download {
+        warn!("主地址不支持多线程下载，不使用镜像");
+        return Vec::new();
+    }
+    let tasks = mirrors.into_iter().map(|url| {
+        let config = config.clone();
+        async move {
+            let (tx, _rx) = create_channel();
+            (prefetch(url.clone(), config, tx).await, url)
+        }
+    });
+    futures::future::join_all(tasks)
+        .await
+        .into_iter()
+        .filter_map(|(res, url)| match res {
+            Ok(task)
+                if task.info.size == info.size
+                    && task.info.fast_download
+                    && !is_file_changed(&info.file_id, &task.info.file_id) =>
+            {
+                info!(url = url.as_str(), "使用镜像");
+                Some(task)
+            }
+            Ok(task) => {
+                warn!(url = url.as_str(), size = task.info.size, file_id = ?task.info.file_id, "镜像与主地址不是同一个文件，已忽略");
+                None
+            }
+            Err(e) => {
+                warn!(url = url.as_str(), err = ?e, "获取镜像元数据失败，已忽略");
+                None
+            }
+        })
+        .collect()
+}
+
+/// 下载中的临时文件路径
+fn part_path(path: &Path) -> PathBuf {
+    let mut path = path.as_os_str().to_owned();
+    path.push(".");
+    path.push(PART_EXT);
+    path.into()
+}
+
+/// 临时文件对应的最终路径，不是临时文件时返回 None
+fn final_path(path: &Path) -> Option<PathBuf> {
+    (path.extension()? == PART_EXT).then(|| path.with_extension(""))
+}
+
+/// 生成未被占用的保存路径，使用临时文件时对应的临时文件也不能已存在
+async fn gen_save_path(path: &Path, part_file: bool) -> io::Result<PathBuf> {
+    let stem = path.file_stem().unwrap_or_default();
+    let ext = path.extension();
+    let mut save_path = path.to_path_buf();
+    let mut i = 0;
+    while fs::try_exists(&save_path).await?
+        || (part_file && fs::try_exists(part_path(&save_path)).await?)
+    {
+        i += 1;
+        let mut new_name = stem.to_os_string();
+        new_name.push(format!(" ({i})"));
+        if let Some(ext) = ext {
+            new_name.push(".");
+            new_name.push(ext);
+        }
+        save_path = path.with_file_name(new_name);
+    }
+    Ok(save_path)
+}
+
+/// 两次获取的文件标识都存在且不同时，认为服务器上的文件已改变
+fn is_file_changed(old: &FileId, new: &FileId) -> bool {
+    match (&old.etag, &new.etag) {
+        (Some(old), Some(new)) => old != new,
+        _ => matches!(
+            (&old.last_modified, &new.last_modified),
+            (Some(old), Some(new)) if old != new
+        ),
+    }
+}
+
+/// 追加检查时比较的字节数
+const APPEND_CHECK_SIZE: u64 = 64 * 1024;
+
+/// 请求服务器上旧文件末尾的一段数据，与本地文件比较，一致时认为服务器只追加了内容，只需下载新增的部分
+async fn is_appended(task: &DownloadTask, path: &Path, old_size: u64) -> bool {
+    let range = tail_range(old_size);
+    let (local, remote) = tokio::join!(read_range(path, &range), pull_range(task, &range));
+    match (local, remote) {
+        (Ok(local), Some(remote)) => local == remote,
+        (Err(e), _) => {
+            warn!(err = ?e, path = ?path, "读取本地文件失败，无法检查是否只追加了内容");
+            false
+        }
+        (_, None) => false,
+    }
+}
+
+fn tail_range(size: u64) -> Range<u64> {
+    size - size.min(APPEND_CHECK_SIZE)..size
+}
+
+async fn read_range(path: &Path, range: &Range<u64>) -> io::Result<Vec<u8>> {
+    let mut file = fs::File::open(path).await?;
+    file.seek(io::SeekFrom::Start(range.start)).await?;
+    let mut buf = vec![0; (range.end - range.start) as usize];
+    file.read_exact(&mut buf).await?;
+    Ok(buf)
+}
+
+async fn pull_range(task: &DownloadTask, range: &Range<u64>) -> Option<Vec<u8>> {
+    let config = &task.config;
+    let mut puller = FastDownPuller::new(FastDownPullerOptions {
+        url: task.info.final_url.clone(),
+        headers: request_headers(config),
+        proxy: config.proxy.as_deref(),
+        available_ips: config.local_address.clone().into(),
+        accept_invalid_certs: config.accept_invalid_certs,
+        accept_invalid_hostnames: config.accept_invalid_hostnames,
+        file_id: task.info.file_id.clone(),
+        resp: None,
+    })
+    .ok()?;
+    let stream = match puller.pull(Some(range)).await {
+        Ok(stream) => stream,
+        Err((e, _)) => {
+            warn!(err = ?e, "请求文件末尾数据失败");
+            return None;
+        }
+    };
+    let mut stream = stream;
+    let len = (range.end - range.start) as usize;
+    let mut buf = Vec::with_capacity(len);
+    while buf.len() < len {
+        match tokio::time::timeout(config.pull_timeout, stream.try_next()).await {
+            Ok(Ok(Some(chunk))) => buf.extend_from_slice(&chunk),
+            Ok(Err((e, _))) => {
+                warn!(err = ?e, "请求文件末尾数据失败");
+                return None;
+            }
+            Ok(Ok(None)) | Err(_) => return None,
+        }
+    }
+    buf.truncate(len);
+    Some(buf)
+}
+
 fn parse_filename_template(template: &str, url: &Url, filename: &str) -> String {
     let template = panic::catch_unwind(|| Local::now().format(template).to_string())
         .unwrap_or_else(|_| template.to_string());
@@ -296,3 +867,53 @@ fn parse_filename_template(template: &str, url: &Url, filename: &str) -> String
         .replace("{file_stem}", file_stem)
         .replace("{file_ext}", file_ext)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_part_path() {
+        let path = Path::new("dir/a.tar.gz");
+        let part = part_path(path);
+        assert_eq!(part, Path::new("dir/a.tar.gz.fdpart"));
+        assert_eq!(final_path(&part).as_deref(), Some(path));
+        assert_eq!(final_path(path), None);
+    }
+
+    #[test]
+    fn test_is_file_changed() {
+        let id = |etag, last_modified| FileId::new(etag, last_modified);
+        assert!(!is_file_changed(&id(None, None), &id(Some("a"), None)));
+        assert!(!is_file_changed(
+            &id(Some("a"), Some("1")),
+            &id(Some("a"), Some("2"))
+        ));
+        assert!(is_file_changed(&id(Some("a"), None), &id(Some("b"), None)));
+        assert!(is_file_changed(
+            &id(None, Some("1")),
+            &id(Some("a"), Some("2"))
+        ));
+        assert!(!is_file_changed(&id(None, Some("1")), &id(None, Some("1"))));
+    }
+
+    #[test]
+    fn test_tail_range() {
+        assert_eq!(tail_range(10), 0..10);
+        assert_eq!(
+            tail_range(APPEND_CHECK_SIZE + 10),
+            10..APPEND_CHECK_SIZE + 10
+        );
+        assert_eq!(tail_range(0), 0..0);
+    }
+
+    #[tokio::test]
+    async fn test_read_range() {
+        let path = std::env::temp_dir().join(format!("fd-read-range-{}", std::process::id()));
+        fs::write(&path, b"0123456789").await.unwrap();
+        assert_eq!(read_range(&path, &(6..10)).await.unwrap(), b"6789");
+        // 本地文件比记录的小时读取失败
+        assert!(read_range(&path, &(6..12)).await.is_err());
+        fs::remove_file(&path).await.unwrap();
+    }
+}
@@ -0,0 +1,52 @@
+use crate::{ui::Status, utils::spawn_local};
+use std::{ops::Range, rc::Weak, time::Duration};
+use tokio::sync::broadcast::{self, error::RecvError};
+
+/// 任务的变化，RPC 等外部接口通过 [`App::events`](crate::core::App::events) 订阅
+#[derive(Debug, Clone, PartialEq)]
+pub enum TaskEvent {
+    /// 界面中的状态改变
+    Status { gid: i32, status: Status },
+    /// 任务被移除
+    Removed { gid: i32 },
+    /// 下载进度，都是原始数值
+    Progress {
+        gid: i32,
+        downloaded: u64,
+        total: u64,
+        /// 平滑后的速度，字节每秒
+        speed: u64,
+        elapsed: Duration,
+        /// 已下载的区间
+        progress: Vec<Range<u64>>,
+    },
+    /// 设置已修改，RPC 等服务据此重启
+    ConfigChanged,
+}
+
+/// 没有订阅者时事件直接丢弃，订阅者太慢时丢弃最旧的事件
+pub fn task_events() -> broadcast::Sender<TaskEvent> {
+    broadcast::channel(256).0
+}
+
+/// 在本线程等待设置修改后调用 f，错过事件时也调用一次，target 被释放后停止
+pub fn on_config_changed<T: 'static>(
+    events: &broadcast::Sender<TaskEvent>,
+    target: Weak<T>,
+    f: fn(&T),
+) -> color_eyre::Result<()> {
+    let mut events = events.subscribe();
+    spawn_local(async move {
+        loop {
+            match events.recv().await {
+                Ok(TaskEvent::ConfigChanged) | Err(RecvError::Lagged(_)) => {}
+                Ok(_) => continue,
+                Err(RecvError::Closed) => break,
+            }
+            let Some(target) = target.upgrade() else {
+                break;
+            };
+            f(&target);
+        }
+    })
+}
@@ -0,0 +1,102 @@
+use fast_down_ffi::{
+    BoxPusher, DownloadTask, Error, Merge, Puller, Pusher, RawEvent, invert,
+    multi::{self, download_multi},
+    single::{self, download_single},
+};
+use std::{fmt, time::Duration};
+use tokio_util::sync::CancellationToken;
+use tracing::info;
+
+pub type PushError = <BoxPusher as Pusher>::Error;
+
+/// 与 fast-down-ffi 的 `DownloadTask::start_with_pusher` 相同，但 puller 由调用方创建
+///
+/// 上游只能使用内部创建的 `FastDownPuller`，无法加入镜像、限速和连接数限制，
+/// 上游提供可传入 puller 的接口后删除这个模块，改为直接调用
+///
+/// 每秒调用一次 `threads`，线程数变化时调整；`on_event` 返回错误时结束下载并返回该错误
+pub async fn start_with_puller<R, E>(
+    task: &DownloadTask,
+    puller: R,
+    pusher: BoxPusher,
+    cancel_token: CancellationToken,
+    mut threads: impl FnMut() -> usize,
+    mut on_event: impl FnMut(&RawEvent<R::Error, PushError>) -> Option<E>,
+) -> Result<(), E>
+where
+    R: Puller,
+    R::Error: fmt::Debug,
+    E: From<Error>,
+{
+    let config = &task.config;
+    let progress = config.downloaded_chunk.clone();
+    let mut current_threads = threads();
+    let result = if task.info.fast_download {
+        download_multi(
+            puller,
+            pusher,
+            multi::DownloadOptions {
+                download_chunks: invert(
+                    progress.lock().iter().cloned(),
+                    task.info.size,
+                    config.chunk_window,
+                ),
+                retry_gap: config.retry_gap,
+                concurrent: current_threads,
+                pull_timeout: config.pull_timeout,
+                push_queue_cap: config.write_queue_cap,
+                min_chunk_size: config.min_chunk_size,
+                max_speculative: config.max_speculative,
+            },
+        )
+    } else {
+        download_single(
+            puller,
+            pusher,
+            single::DownloadOptions {
+                retry_gap: config.retry_gap,
+                push_queue_cap: config.write_queue_cap,
+            },
+        )
+    };
+    let mut interval = tokio::time::interval(Duration::from_secs(1));
+    let mut cancelled = false;
+    let mut fatal = None;
+    loop {
+        tokio::select! {
+            () = cancel_token.cancelled(), if !cancelled => {
+                result.abort();
+                cancelled = true;
+            }
+            _ = interval.tick(), if task.info.fast_download && !cancelled => {
+                let new_threads = threads();
+                if new_threads != current_threads {
+                    info!(threads = new_threads, "调整线程数");
+                    current_threads = new_threads;
+                    result.set_threads(current_threads, config.min_chunk_size);
+                }
+            }
+            e = result.event_chain.recv() => match e {
+                Ok(e) => {
+                    let _ = task.tx.send((&e).into());
+                    if let RawEvent::PushProgress(_, range) = &e {
+                        let mut p = progress.lock();
+                        if range.start == 0 && !task.info.fast_download {
+                            p.clear();
+                        }
+                        p.merge_progress(range.clone());
+                    }
+                    if fatal.is_none()
+                        && let Some(e) = on_event(&e)
+                    {
+                        fatal = Some(e);
+                        result.abort();
+                    }
+                }
+                Err(_) => break,
+            }
+        }
+    }
+    result.join().await.map_err(Error::Task)?;
+    fatal.map_or(Ok(()), Err)
+}
@@ -0,0 +1,181 @@
+use dashmap::DashMap;
+use fast_down_ffi::{ProgressEntry, PullResult, PullStream, Puller};
+use futures::TryStreamExt;
+use parking_lot::RwLock;
+use std::{
+    collections::HashMap,
+    sync::{
+        Arc,
+        atomic::{AtomicUsize, Ordering},
+    },
+};
+use tokio::sync::Notify;
+
+/// 按主机限制连接数，同一主机的连接数在所有任务之间共享
+#[derive(Debug, Default)]
+pub struct HostLimiter {
+    /// 默认的单主机连接数，0 为不限制
+    limit: AtomicUsize,
+    overrides: RwLock<HashMap<String, usize>>,
+    hosts: DashMap<String, Arc<HostSlot>>,
+}
+
+#[derive(Debug, Default)]
+struct HostSlot {
+    active: AtomicUsize,
+    notify: Notify,
+}
+
+impl HostLimiter {
+    pub fn new(limit: usize, overrides: HashMap<String, usize>) -> Self {
+        Self {
+            limit: AtomicUsize::new(limit),
+            overrides: RwLock::new(overrides),
+            hosts: DashMap::new(),
+        }
+    }
+
+    pub fn set_limits(&self, limit: usize, overrides: HashMap<String, usize>) {
+        self.limit.store(limit, Ordering::Relaxed);
+        *self.overrides.write() = overrides;
+        // 上限可能变大，唤醒等待中的连接重新检查
+        for slot in self.hosts.iter() {
+            slot.notify.notify_waiters();
+        }
+    }
+
+    /// 返回计数用的主机名和连接数上限，单独设置的主机同时匹配其子域名
+    fn limit_of(&self, host: &str) -> (String, usize) {
+        let overrides = self.overrides.read();
+        let mut domain = host;
+        loop {
+            if let Some(&limit) = overrides.get(domain) {
+                return (domain.to_string(), limit);
+            }
+            match domain.split_once('.') {
+                Some((_, parent)) => domain = parent,
+                None => break,
+            }
+        }
+        (host.to_string(), self.limit.load(Ordering::Relaxed))
+    }
+
+    pub fn host(self: &Arc<Self>, host: &str) -> HostConnections {
+        let host = host.to_lowercase();
+        let (key, _) = self.limit_of(&host);
+        let slot = self.hosts.entry(key).or_default().clone();
+        HostConnections {
+            limiter: self.clone(),
+            host,
+            slot,
+        }
+    }
+}
+
+/// 某个主机的连接计数
+#[derive(Debug, Clone)]
+pub struct HostConnections {
+    limiter: Arc<HostLimiter>,
+    host: String,
+    slot: Arc<HostSlot>,
+}
+
+impl HostConnections {
+    fn try_acquire(&self) -> Option<ConnectionPermit> {
+        let (_, limit) = self.limiter.limit_of(&self.host);
+        self.slot
+            .active
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |n| {
+                (limit == 0 || n < limit).then_some(n + 1)
+            })
+            .ok()
+            .map(|_| ConnectionPermit(self.slot.clone()))
+    }
+
+    /// 等待直到该主机有空闲的连接数
+    pub async fn acquire(&self) -> ConnectionPermit {
+        loop {
+            let notified = self.slot.notify.notified();
+            tokio::pin!(notified);
+            notified.as_mut().enable();
+            if let Some(permit) = self.try_acquire() {
+                return permit;
+            }
+            notified.await;
+        }
+    }
+}
+
+/// 释放时归还一个连接数
+#[derive(Debug)]
+pub struct ConnectionPermit(Arc<HostSlot>);
+
+impl Drop for ConnectionPermit {
+    fn drop(&mut self) {
+        self.0.active.fetch_sub(1, Ordering::AcqRel);
+        self.0.notify.notify_one();
+    }
+}
+
+/// 每次拉取前先占用一个主机连接数，直到数据流结束
+#[derive(Debug, Clone)]
+pub struct HostPuller<R> {
+    inner: R,
+    connections: HostConnections,
+}
+
+impl<R> HostPuller<R> {
+    pub fn new(inner: R, connections: HostConnections) -> Self {
+        Self { inner, connections }
+    }
+}
+
+impl<R: Puller> Puller for HostPuller<R> {
+    type Error = R::Error;
+
+    async fn pull(
+        &mut self,
+        range: Option<&ProgressEntry>,
+    ) -> PullResult<impl PullStream<Self::Error>, Self::Error> {
+        let permit = self.connections.acquire().await;
+        let stream = self.inner.pull(range).await?;
+        Ok(stream.inspect_ok(move |_| {
+            // 数据流持有连接数，流被丢弃时才释放
+            let _ = &permit;
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_limit_of() {
+        let overrides = HashMap::from([("example.com".to_string(), 4)]);
+        let limiter = HostLimiter::new(16, overrides);
+        assert_eq!(limiter.limit_of("example.com"), ("example.com".into(), 4));
+        assert_eq!(
+            limiter.limit_of("cdn.example.com"),
+            ("example.com".into(), 4)
+        );
+        assert_eq!(limiter.limit_of("example.org"), ("example.org".into(), 16));
+    }
+
+    #[test]
+    fn test_host_permits() {
+        let limiter = Arc::new(HostLimiter::new(2, HashMap::new()));
+        let a = limiter.host("a.com");
+        let also_a = limiter.host("A.com");
+        let first = a.try_acquire().unwrap();
+        let _second = also_a.try_acquire().unwrap();
+        assert!(a.try_acquire().is_none());
+        assert!(limiter.host("b.com").try_acquire().is_some());
+        drop(first);
+        assert!(also_a.try_acquire().is_some());
+
+        limiter.set_limits(0, HashMap::new());
+        let permits: Vec<_> = (0..10).filter_map(|_| a.try_acquire()).collect();
+        assert_eq!(permits.len(), 10);
+    }
+}
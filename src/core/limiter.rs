@@ -0,0 +1,202 @@
+use bytes::Bytes;
+use fast_down_ffi::{ProgressEntry, PullResult, PullStream, Puller};
+use futures::{TryStreamExt, stream};
+use parking_lot::Mutex;
+use std::{
+    sync::{
+        Arc,
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+    },
+    time::{Duration, Instant},
+};
+
+/// 令牌桶限速器，速度单位为字节每秒，0 为不限速
+#[derive(Debug)]
+pub struct RateLimiter {
+    rate: AtomicU64,
+    /// 下一段数据最早可以放行的时间
+    next: Mutex<Instant>,
+    /// 正在共享该限速器的任务数
+    active: AtomicUsize,
+}
+
+impl RateLimiter {
+    pub fn new(rate: u64) -> Self {
+        Self {
+            rate: AtomicU64::new(rate),
+            next: Mutex::new(Instant::now()),
+            active: AtomicUsize::new(0),
+        }
+    }
+
+    pub fn rate(&self) -> u64 {
+        self.rate.load(Ordering::Relaxed)
+    }
+
+    pub fn set_rate(&self, rate: u64) {
+        self.rate.store(rate, Ordering::Relaxed);
+        *self.next.lock() = Instant::now();
+    }
+
+    /// 预约 n 字节的放行时间，不限速时返回 None
+    fn reserve(&self, n: u64) -> Option<Instant> {
+        let rate = self.rate();
+        if rate == 0 {
+            return None;
+        }
+        let mut next = self.next.lock();
+        let at = (*next).max(Instant::now());
+        *next = at + Duration::from_secs_f64(n as f64 / rate as f64);
+        Some(at)
+    }
+}
+
+/// 单个任务的限速，同时受全局限速和任务限速约束
+///
+/// 每个任务同一时刻最多只有一段数据在全局限速器中排队，从而让任务之间轮流、公平地共享全局带宽
+#[derive(Debug)]
+pub struct Throttle {
+    global: Arc<RateLimiter>,
+    task: Arc<RateLimiter>,
+    gate: tokio::sync::Mutex<()>,
+}
+
+impl Throttle {
+    pub fn new(global: Arc<RateLimiter>, task: Arc<RateLimiter>) -> Self {
+        global.active.fetch_add(1, Ordering::Relaxed);
+        Self {
+            global,
+            task,
+            gate: tokio::sync::Mutex::new(()),
+        }
+    }
+
+    pub fn task_limiter(&self) -> &Arc<RateLimiter> {
+        &self.task
+    }
+
+    /// 估算当前任务能分到的速度，0 为不限速
+    pub fn rate(&self) -> u64 {
+        let global = match self.global.rate() {
+            0 => 0,
+            rate => (rate / self.global.active.load(Ordering::Relaxed).max(1) as u64).max(1),
+        };
+        match (global, self.task.rate()) {
+            (0, rate) | (rate, 0) => rate,
+            (a, b) => a.min(b),
+        }
+    }
+
+    /// 每次放行的最大字节数，不限速时为 None
+    fn quantum(&self) -> Option<usize> {
+        match self.rate() {
+            0 => None,
+            rate => Some((rate / 8).clamp(1024, 64 * 1024) as usize),
+        }
+    }
+
+    /// 限速时减少线程数，避免每个线程等待太久而触发拉取超时
+    pub fn threads(&self, max: usize) -> usize {
+        match self.rate() {
+            0 => max,
+            rate => ((rate / (64 * 1024)) as usize).clamp(1, max.max(1)),
+        }
+    }
+
+    async fn acquire(&self, n: u64) {
+        if let Some(at) = self.task.reserve(n) {
+            tokio::time::sleep_until(at.into()).await;
+        }
+        if self.global.rate() > 0 {
+            let _gate = self.gate.lock().await;
+            if let Some(at) = self.global.reserve(n) {
+                tokio::time::sleep_until(at.into()).await;
+            }
+        }
+    }
+}
+
+impl Drop for Throttle {
+    fn drop(&mut self) {
+        self.global.active.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ThrottledPuller<R> {
+    inner: R,
+    throttle: Arc<Throttle>,
+}
+
+impl<R> ThrottledPuller<R> {
+    pub fn new(inner: R, throttle: Arc<Throttle>) -> Self {
+        Self { inner, throttle }
+    }
+}
+
+impl<R: Puller> Puller for ThrottledPuller<R> {
+    type Error = R::Error;
+
+    async fn pull(
+        &mut self,
+        range: Option<&ProgressEntry>,
+    ) -> PullResult<impl PullStream<Self::Error>, Self::Error> {
+        let stream = self.inner.pull(range).await?;
+        let throttle = self.throttle.clone();
+        Ok(Box::pin(stream::unfold(
+            (stream, Bytes::new(), throttle),
+            |(mut stream, mut pending, throttle)| async move {
+                if pending.is_empty() {
+                    match stream.try_next().await {
+                        Ok(Some(chunk)) => pending = chunk,
+                        Ok(None) => return None,
+                        Err(e) => return Some((Err(e), (stream, pending, throttle))),
+                    }
+                }
+                let n = throttle
+                    .quantum()
+                    .map_or(pending.len(), |q| q.min(pending.len()));
+                let chunk = pending.split_to(n);
+                throttle.acquire(n as u64).await;
+                Some((Ok(chunk), (stream, pending, throttle)))
+            },
+        )))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reserve() {
+        let limiter = RateLimiter::new(0);
+        assert_eq!(limiter.reserve(1024), None);
+
+        limiter.set_rate(1024);
+        let first = limiter.reserve(512).unwrap();
+        let second = limiter.reserve(512).unwrap();
+        let third = limiter.reserve(512).unwrap();
+        assert_eq!(second - first, Duration::from_millis(500));
+        assert_eq!(third - second, Duration::from_millis(500));
+    }
+
+    #[test]
+    fn test_throttle_rate() {
+        let global = Arc::new(RateLimiter::new(1024 * 1024));
+        let a = Throttle::new(global.clone(), Arc::new(RateLimiter::new(0)));
+        assert_eq!(a.rate(), 1024 * 1024);
+        assert_eq!(a.threads(32), 16);
+
+        // 两个任务平分全局限速，任务限速更低时取任务限速
+        let b = Throttle::new(global.clone(), Arc::new(RateLimiter::new(100 * 1024)));
+        assert_eq!(a.rate(), 512 * 1024);
+        assert_eq!(b.rate(), 100 * 1024);
+        assert_eq!(b.threads(32), 1);
+
+        drop(b);
+        global.set_rate(0);
+        assert_eq!(a.rate(), 0);
+        assert_eq!(a.threads(32), 32);
+    }
+}
@@ -0,0 +1,81 @@
+use fast_down_ffi::{ProgressEntry, PullResult, PullStream, Puller};
+use futures::TryStreamExt;
+use std::sync::{
+    Arc,
+    atomic::{AtomicUsize, Ordering},
+};
+
+/// 从多个镜像拉取同一个文件，每次拉取时在出错最少的镜像之间轮流选择
+///
+/// 出错的镜像会被降级，直到其他镜像也出错或者它重新拉取成功
+#[derive(Debug, Clone)]
+pub struct MirrorPuller<R> {
+    pullers: Vec<R>,
+    /// 每个镜像连续出错的次数，在所有线程之间共享
+    failures: Arc<[AtomicUsize]>,
+    turn: Arc<AtomicUsize>,
+}
+
+impl<R> MirrorPuller<R> {
+    /// `pullers` 不能为空，第一个为主地址
+    pub fn new(pullers: Vec<R>) -> Self {
+        assert!(!pullers.is_empty(), "至少需要一个下载地址");
+        Self {
+            failures: pullers.iter().map(|_| AtomicUsize::new(0)).collect(),
+            pullers,
+            turn: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+
+    fn pick(&self) -> usize {
+        let failures: Vec<_> = self
+            .failures
+            .iter()
+            .map(|f| f.load(Ordering::Relaxed))
+            .collect();
+        let min = failures.iter().copied().min().unwrap_or_default();
+        let candidates: Vec<_> = (0..failures.len())
+            .filter(|&i| failures[i] == min)
+            .collect();
+        candidates[self.turn.fetch_add(1, Ordering::Relaxed) % candidates.len()]
+    }
+}
+
+impl<R: Puller> Puller for MirrorPuller<R> {
+    type Error = R::Error;
+
+    async fn pull(
+        &mut self,
+        range: Option<&ProgressEntry>,
+    ) -> PullResult<impl PullStream<Self::Error>, Self::Error> {
+        let i = self.pick();
+        let failures = self.failures.clone();
+        let stream = self.pullers[i].pull(range).await.inspect_err(|_| {
+            failures[i].fetch_add(1, Ordering::Relaxed);
+        })?;
+        let on_ok = failures.clone();
+        Ok(stream
+            .inspect_ok(move |_| on_ok[i].store(0, Ordering::Relaxed))
+            .inspect_err(move |_| {
+                failures[i].fetch_add(1, Ordering::Relaxed);
+            }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pick() {
+        let puller = MirrorPuller::new(vec![(), (), ()]);
+        let picked: Vec<_> = (0..3).map(|_| puller.pick()).collect();
+        assert_eq!(picked, [0, 1, 2]);
+        puller.failures[1].store(2, Ordering::Relaxed);
+        let picked: Vec<_> = (0..4).map(|_| puller.pick()).collect();
+        assert!(picked.iter().all(|&i| i != 1));
+        puller.failures[0].store(2, Ordering::Relaxed);
+        puller.failures[2].store(3, Ordering::Relaxed);
+        assert!((0..4).map(|_| puller.pick()).all(|i| i != 2));
+    }
+}
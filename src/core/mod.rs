@@ -1,11 +1,31 @@
 mod app;
+mod bridge;
+mod disk;
 mod download;
+mod event;
+mod ffi_ext;
+mod host;
+mod limiter;
+mod mirror;
+mod post_action;
 mod progress;
+mod retry;
+mod sink;
 mod start;
 mod task;
 
 pub use app::*;
+pub use bridge::*;
+pub use disk::*;
 pub use download::*;
+pub use event::*;
+pub use ffi_ext::*;
+pub use host::*;
+pub use limiter::*;
+pub use mirror::*;
+pub use post_action::*;
 pub use progress::*;
+pub use retry::*;
+pub use sink::*;
 pub use start::*;
 pub use task::*;
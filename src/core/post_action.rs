@@ -0,0 +1,203 @@
+use crate::{
+    core::App,
+    persist::ActionResult,
+    ui,
+    utils::{PostAction, extract, extract_dir},
+};
+use color_eyre::eyre::{Result, eyre};
+use fast_down_ffi::unique_path::gen_unique_path;
+use std::{
+    path::{Path, PathBuf},
+    sync::{
+        Arc,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::Duration,
+};
+use tokio::{fs, process::Command};
+use tracing::{error, info};
+use url::Url;
+
+/// 开启自动解压时先解压压缩包，再依次执行全局和任务的完成后操作，某一步失败时不再执行后面的操作
+pub async fn run_post_actions(
+    app: App,
+    gid: i32,
+    auto_extract: Option<bool>,
+    actions: Vec<PostAction>,
+) {
+    let Some((mut path, url)) = app
+        .db
+        .inner
+        .data
+        .get(&gid)
+        .map(|e| (e.file_path.clone(), e.url.clone()))
+    else {
+        return;
+    };
+    let mut messages = Vec::new();
+    let mut failed = false;
+    if let Some(delete_archive) = auto_extract
+        && extract_dir(&path).is_some()
+    {
+        match extract_entry(&app, gid, &path, delete_archive).await {
+            Ok(message) => messages.push(format!("自动解压：{message}")),
+            Err(e) => {
+                error!(gid = gid, err = ?e, "自动解压失败");
+                messages.push(format!("自动解压：失败，{e}"));
+                failed = true;
+            }
+        }
+    }
+    for action in actions.into_iter().take_while(|_| !failed) {
+        info!(gid = gid, action = %action, path = ?path, "执行完成后操作");
+        let text = format!("正在执行完成后操作：{action}");
+        app.update_ui_row(gid, move |_, data| {
+            data.action_result = text.into();
+            data.action_failed = false;
+        });
+        match run_action(&app, gid, &action, &path, &url).await {
+            Ok((message, new_path)) => {
+                info!(gid = gid, action = %action, message = message, "完成后操作成功");
+                messages.push(format!("{action}：{message}"));
+                if let Some(new_path) = new_path {
+                    app.db.update_file_path(gid, new_path.clone());
+                    app.update_ui_row(gid, {
+                        let new_path = new_path.clone();
+                        move |_, data| {
+                            data.path = new_path.to_string_lossy().as_ref().into();
+                        }
+                    });
+                    path = new_path;
+                }
+            }
+            Err(e) => {
+                error!(gid = gid, action = %action, err = ?e, "完成后操作失败");
+                messages.push(format!("{action}：失败，{e}"));
+                failed = true;
+                break;
+            }
+        }
+    }
+    let message = messages.join("\n");
+    app.db.update_action_result(
+        gid,
+        Some(ActionResult {
+            failed,
+            message: message.clone(),
+        }),
+    );
+    app.update_ui_row(gid, move |_, data| {
+        data.action_result = message.into();
+        data.action_failed = failed;
+    });
+}
+
+/// 返回结果说明，移动文件时同时返回新的路径
+async fn run_action(
+    app: &App,
+    gid: i32,
+    action: &PostAction,
+    path: &Path,
+    url: &Url,
+) -> Result<(String, Option<PathBuf>)> {
+    match action {
+        PostAction::Run(cmd) => {
+            let output = shell(cmd, path, url).output().await?;
+            let stdout = String::from_utf8_lossy(&output.stdout);
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            info!(cmd = cmd, status = %output.status, stdout = %stdout.trim(), stderr = %stderr.trim(), "命令已退出");
+            match output.status.code() {
+                Some(0) => Ok(("退出码 0".into(), None)),
+                Some(code) => Err(eyre!("退出码 {code}")),
+                None => Err(eyre!("命令被终止")),
+            }
+        }
+        PostAction::Move(dir) => {
+            fs::create_dir_all(dir).await?;
+            let file_name = path.file_name().ok_or_else(|| eyre!("文件路径无效"))?;
+            let target = gen_unique_path(dir.join(file_name)).await?;
+            if fs::rename(path, &target).await.is_err() {
+                // 跨磁盘时无法直接重命名
+                fs::copy(path, &target).await?;
+                fs::remove_file(path).await?;
+            }
+            Ok((format!("已移动到 {}", target.display()), Some(target)))
+        }
+        PostAction::Open => {
+            open::that_detached(path)?;
+            Ok(("已打开".into(), None))
+        }
+        PostAction::Extract => Ok((extract_entry(app, gid, path, false).await?, None)),
+    }
+}
+
+/// 解压到压缩包旁边的同名文件夹，解压时任务显示为解压中
+async fn extract_entry(app: &App, gid: i32, path: &Path, delete_archive: bool) -> Result<String> {
+    let (kind, dest) = extract_dir(path)
+        .ok_or_else(|| eyre!("不支持的压缩包格式，支持 zip、tar、tar.gz、tar.zst、7z"))?;
+    let dest = gen_unique_path(dest).await?;
+    info!(gid = gid, archive = ?path, dest = ?dest, "开始解压");
+    let set_progress = |p: f64| {
+        app.update_ui_row(gid, move |_, data| {
+            data.status = ui::Status::Extracting;
+            data.action_result = format!("正在解压 {:.2}%", p * 100.).into();
+        });
+    };
+    set_progress(0.);
+    let archive_size = fs::metadata(path).await?.len();
+    let read = Arc::new(AtomicU64::new(0));
+    let mut handle = tokio::task::spawn_blocking({
+        let read = read.clone();
+        let archive = path.to_path_buf();
+        let dest = dest.clone();
+        move || extract(&archive, kind, &dest, |n| read.store(n, Ordering::Relaxed))
+    });
+    let mut interval = tokio::time::interval(Duration::from_millis(500));
+    let res = loop {
+        tokio::select! {
+            res = &mut handle => break res,
+            _ = interval.tick() => {
+                let read = read.load(Ordering::Relaxed);
+                set_progress(read as f64 / archive_size.max(1) as f64);
+            }
+        }
+    };
+    app.update_ui_row(gid, |_, data| data.status = ui::Status::Completed);
+    let count = res??;
+    let mut message = format!("已解压 {count} 个文件到 {}", dest.display());
+    if delete_archive {
+        fs::remove_file(path).await?;
+        message.push_str("，已删除压缩包");
+    }
+    Ok(message)
+}
+
+/// 通过系统的 shell 运行命令，Unix 中还可以用 $1 和 $2 取得文件路径和链接
+fn shell(cmd: &str, path: &Path, url: &Url) -> Command {
+    #[cfg(windows)]
+    let mut command = {
+        const CREATE_NO_WINDOW: u32 = 0x0800_0000;
+        let mut command = Command::new("cmd");
+        command
+            .arg("/C")
+            .raw_arg(cmd)
+            .creation_flags(CREATE_NO_WINDOW);
+        command
+    };
+    #[cfg(not(windows))]
+    let mut command = {
+        let mut command = Command::new("sh");
+        command
+            .arg("-c")
+            .arg(cmd)
+            .arg("fast-down")
+            .arg(path)
+            .arg(url.as_str());
+        command
+    };
+    command
+        .env("FD_PATH", path)
+        .env("FD_URL", url.as_str())
+        .kill_on_drop(true);
+    command
+}
@@ -0,0 +1,64 @@
+use crate::persist::{ErrorKind, RetryPolicy};
+use std::time::Duration;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorClass {
+    Network,
+    Disk,
+    Other,
+}
+
+/// 按错误类型决定使用哪一项重试开关，超时、限流和服务器错误视为网络错误
+pub fn classify(kind: ErrorKind) -> ErrorClass {
+    match kind {
+        ErrorKind::Network => ErrorClass::Network,
+        ErrorKind::Http(code) if code == 408 || code == 429 || code >= 500 => ErrorClass::Network,
+        ErrorKind::DiskFull | ErrorKind::PermissionDenied | ErrorKind::Io => ErrorClass::Disk,
+        _ => ErrorClass::Other,
+    }
+}
+
+/// 第 attempt 次重试前需要等待的时间，不应重试时返回 None
+pub fn retry_delay(policy: &RetryPolicy, class: ErrorClass, attempt: u32) -> Option<Duration> {
+    let enabled = match class {
+        ErrorClass::Network => policy.retry_network,
+        ErrorClass::Disk => policy.retry_disk,
+        ErrorClass::Other => policy.retry_other,
+    };
+    (enabled && attempt <= policy.max_attempts).then(|| policy.delay(attempt))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_classify() {
+        assert_eq!(classify(ErrorKind::Network), ErrorClass::Network);
+        assert_eq!(classify(ErrorKind::Http(503)), ErrorClass::Network);
+        assert_eq!(classify(ErrorKind::Http(429)), ErrorClass::Network);
+        assert_eq!(classify(ErrorKind::Http(404)), ErrorClass::Other);
+        assert_eq!(classify(ErrorKind::DiskFull), ErrorClass::Disk);
+        assert_eq!(classify(ErrorKind::ChecksumMismatch), ErrorClass::Other);
+    }
+
+    #[test]
+    fn test_retry_delay() {
+        let policy = RetryPolicy::default();
+        let secs = |attempt| retry_delay(&policy, ErrorClass::Network, attempt);
+        assert_eq!(secs(1), Some(Duration::from_secs(10)));
+        assert_eq!(secs(2), Some(Duration::from_secs(20)));
+        assert_eq!(secs(5), Some(Duration::from_secs(160)));
+        assert_eq!(secs(6), None);
+        assert_eq!(retry_delay(&policy, ErrorClass::Disk, 1), None);
+
+        let policy = RetryPolicy {
+            max_attempts: 100,
+            ..Default::default()
+        };
+        assert_eq!(
+            retry_delay(&policy, ErrorClass::Network, 100),
+            Some(policy.max_delay)
+        );
+    }
+}
@@ -0,0 +1,176 @@
+use crate::{
+    core::TaskEvent,
+    fmt::format_size,
+    os::wakeup_window,
+    ui::{DownloadConfig, EntryData, GeneralConfig, MainWindow},
+    utils::spawn_local,
+};
+use crossfire::{
+    MTx,
+    mpsc::{self, List},
+};
+use slint::{Model, VecModel, Weak};
+use std::{collections::HashMap, rc::Rc, time::Duration};
+use tokio::{
+    sync::broadcast::{self, error::RecvError},
+    time::Instant,
+};
+use tracing::info;
+
+pub type RowMutator = Box<dyn FnOnce(usize, &mut EntryData) + Send>;
+
+/// 无界面时每个任务的进度最多多久写一次日志
+const PROGRESS_LOG_INTERVAL: Duration = Duration::from_secs(10);
+
+/// 核心逻辑对界面的操作，使 [`App`](crate::core::App) 不依赖主窗口
+pub trait EventSink: Send + Sync {
+    /// 修改任务列表中 gid 对应的行
+    fn update_row(&self, gid: i32, mutator: RowMutator);
+    /// 设置修改后刷新界面
+    fn config_changed(&self, _download_config: DownloadConfig, _general_config: GeneralConfig) {}
+    /// 显示并聚焦主窗口
+    fn wake_up(&self) {}
+    /// 没有窗口时不弹出任务对话框
+    fn has_window(&self) -> bool {
+        false
+    }
+}
+
+fn update_row(list_model: &impl Model<Data = EntryData>, gid: i32, mutator: RowMutator) {
+    if let Some((row, mut data)) = list_model.iter().enumerate().find(|(_, e)| e.gid == gid) {
+        mutator(row, &mut data);
+        list_model.set_row_data(row, data);
+    }
+}
+
+/// 在主窗口中显示
+pub struct WindowSink(pub Weak<MainWindow>);
+
+impl EventSink for WindowSink {
+    fn update_row(&self, gid: i32, mutator: RowMutator) {
+        let _ = self
+            .0
+            .upgrade_in_event_loop(move |ui| update_row(&ui.get_all_list(), gid, mutator));
+    }
+
+    fn config_changed(&self, download_config: DownloadConfig, general_config: GeneralConfig) {
+        let _ = self.0.upgrade_in_event_loop(move |ui| {
+            ui.set_download_config(download_config);
+            ui.set_general_config(general_config);
+        });
+    }
+
+    fn wake_up(&self) {
+        let _ = self.0.upgrade_in_event_loop(|ui| wakeup_window(&ui));
+    }
+
+    fn has_window(&self) -> bool {
+        true
+    }
+}
+
+/// 无界面模式，只更新本线程的任务列表，供 IPC 和 API 查询
+pub struct HeadlessSink {
+    tx: MTx<List<(i32, RowMutator)>>,
+}
+
+impl HeadlessSink {
+    /// 需要在任务列表所在线程的 `LocalSet` 中调用
+    pub fn new(list_model: Rc<VecModel<EntryData>>) -> color_eyre::Result<Self> {
+        let (tx, rx) = mpsc::unbounded_async::<(i32, RowMutator)>();
+        spawn_local(async move {
+            while let Ok((gid, mutator)) = rx.recv().await {
+                update_row(&*list_model, gid, mutator);
+            }
+        })?;
+        Ok(Self { tx })
+    }
+}
+
+impl EventSink for HeadlessSink {
+    fn update_row(&self, gid: i32, mutator: RowMutator) {
+        let _ = self.tx.send((gid, mutator));
+    }
+}
+
+/// 无界面时把任务事件写入日志
+pub async fn log_task_events(mut events: broadcast::Receiver<TaskEvent>) {
+    let mut last_logged = HashMap::new();
+    loop {
+        match events.recv().await {
+            Ok(TaskEvent::Status { gid, status }) => {
+                info!(gid = gid, status = ?status, "任务状态改变")
+            }
+            Ok(TaskEvent::Removed { gid }) => {
+                last_logged.remove(&gid);
+                info!(gid = gid, "任务已移除");
+            }
+            Ok(TaskEvent::Progress {
+                gid,
+                downloaded,
+                total,
+                speed,
+                ..
+            }) => {
+                let now = Instant::now();
+                if last_logged
+                    .get(&gid)
+                    .is_some_and(|&t| now - t < PROGRESS_LOG_INTERVAL)
+                {
+                    continue;
+                }
+                last_logged.insert(gid, now);
+                info!(
+                    gid = gid,
+                    downloaded = %format_size(downloaded as f64),
+                    total = %format_size(total as f64),
+                    speed = %format!("{}/s", format_size(speed as f64)),
+                    "下载进度"
+                );
+            }
+            Ok(TaskEvent::ConfigChanged) => info!("设置已修改"),
+            Err(RecvError::Lagged(_)) => continue,
+            Err(RecvError::Closed) => break,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ui::Status, utils::set_headless};
+    use tokio::task::LocalSet;
+
+    #[tokio::test]
+    async fn test_headless_sink() {
+        set_headless();
+        LocalSet::new()
+            .run_until(async {
+                let list_model = Rc::new(VecModel::from(vec![
+                    EntryData {
+                        gid: 1,
+                        ..Default::default()
+                    },
+                    EntryData {
+                        gid: 2,
+                        ..Default::default()
+                    },
+                ]));
+                let sink = HeadlessSink::new(list_model.clone()).unwrap();
+                sink.update_row(
+                    2,
+                    Box::new(|row, data| {
+                        assert_eq!(row, 1);
+                        data.status = Status::Completed;
+                    }),
+                );
+                sink.update_row(3, Box::new(|_, _| panic!("不存在的任务")));
+                let (tx, rx) = tokio::sync::oneshot::channel();
+                sink.update_row(1, Box::new(move |_, _| tx.send(()).unwrap()));
+                rx.await.unwrap();
+                assert_eq!(list_model.row_data(0).unwrap().status, Status::Running);
+                assert_eq!(list_model.row_data(1).unwrap().status, Status::Completed);
+            })
+            .await;
+    }
+}
@@ -1,60 +1,250 @@
 use crate::{
-    core::{App, download},
-    persist::{self, DatabaseEntry},
+    core::{App, DownloadError, classify, download, retry_delay},
+    persist::{self, DatabaseEntry, Priority},
     ui::{DownloadConfig, EntryData, Status},
-    utils::{ForceSendExt, LogErr},
+    utils::{
+        Checksum, ForceSendExt, LogErr, format_start_at, is_metalink, read_metalink,
+        reapply_site_rules, start_at_from_ui,
+    },
 };
 use fast_down_ffi::FileId;
-use slint::{SharedString, ToSharedString, VecModel};
-use std::{path::PathBuf, time::Duration};
+use slint::{Model, SharedString, ToSharedString, VecModel};
+use std::{
+    future::Future,
+    path::{Path, PathBuf},
+    sync::atomic::Ordering,
+    time::{Duration, Instant, SystemTime},
+};
 use tokio_util::sync::CancellationToken;
 use tracing::{error, info};
 use url::Url;
 
 /// 返回 false 意味任务没有成功添加到 task_set 中
 pub fn start_entry(app: &App, entry: &EntryData, list: &VecModel<EntryData>) -> bool {
-    if matches!(entry.status, Status::Running | Status::Waiting) {
+    if matches!(
+        entry.status,
+        Status::Running | Status::Verifying | Status::Extracting | Status::Waiting
+    ) {
         return false;
     }
     let gid = entry.gid;
-    let Some(db_entry) = app.db.inner.data.get(&gid).map(|e| e.clone()) else {
+    let Some(mut db_entry) = app.db.inner.data.get(&gid).map(|e| e.clone()) else {
         return false;
     };
-    let url = db_entry.url.clone();
-    let config = db_entry.config.to_ui_download_config();
     if db_entry.status == persist::Status::Completed {
-        start_new_entry(app, url, &config, list);
+        let mut config = db_entry.config.to_ui_download_config();
+        config.priority = db_entry.priority.to_ui();
+        config.as_mirrors = !db_entry.mirrors.is_empty();
+        start_new_entry(app, db_entry.url, db_entry.mirrors, &config, list);
+        return false;
+    }
+    // 手动开始时重新计算重试次数
+    db_entry.attempts = 0;
+    app.db.update_attempts(gid, 0);
+    let delay = start_at_delay(db_entry.start_at);
+    enqueue_entry(app, gid, db_entry, delay);
+    true
+}
+
+/// 按 gid 开始列表中的任务，返回 false 意味任务不存在或没有添加到 task_set 中
+pub fn start_entry_by_gid(app: &App, gid: i32, list: &VecModel<EntryData>) -> bool {
+    for i in (0..list.row_count()).rev() {
+        let Some(mut entry) = list.row_data(i) else {
+            break;
+        };
+        if entry.gid == gid {
+            let is_started = start_entry(app, &entry, list);
+            if is_started {
+                entry.status = Status::Waiting;
+                list.set_row_data(i, entry);
+            }
+            return is_started;
+        }
+    }
+    false
+}
+
+/// 检查已完成的任务在服务器上是否有更新，只追加了内容时仅下载新增的部分
+pub fn update_entry(app: &App, entry: &EntryData) -> bool {
+    if entry.status != Status::Completed {
+        return false;
+    }
+    let gid = entry.gid;
+    let Some(mut db_entry) = app.db.inner.data.get(&gid).map(|e| e.clone()) else {
+        return false;
+    };
+    if db_entry.status != persist::Status::Completed {
         return false;
     }
+    db_entry.attempts = 0;
+    app.db.update_attempts(gid, 0);
+    enqueue_entry(app, gid, db_entry, None);
+    true
+}
+
+/// 把已有条目加入任务队列，`delay` 为进入队列前的等待时间和提示文本
+fn enqueue_entry(app: &App, gid: i32, db_entry: DatabaseEntry, delay: Option<(Duration, String)>) {
+    let url = db_entry.url.clone();
+    let mirrors = db_entry.mirrors.clone();
+    let priority = db_entry.priority;
+    let mut config = db_entry.config.to_ui_download_config();
+    config.priority = priority.to_ui();
     let app_c = app.clone();
     let cancel_token = CancellationToken::new();
     let token = cancel_token.clone();
     let fut = async move {
-        app_c.update_ui_row(gid, move |_, data| data.error = SharedString::new());
+        app_c.db.update_error_kind(gid, None);
+        app_c.update_ui_row(gid, move |_, data| {
+            data.error = SharedString::new();
+            data.hint = SharedString::new();
+        });
         let handler = app_c.create_download_handler(gid);
-        match download(url, &config, token, Some(db_entry), handler).await {
-            Ok(()) => info!(gid = gid, "任务下载完成"),
-            Err(e) => {
-                error!(gid = gid, err = ?e, "下载任务出错");
-                app_c.db.update_status(gid, persist::Status::Error);
-                app_c.update_ui_row(gid, move |_, data| {
-                    data.status = Status::Error;
-                    data.error = e.to_shared_string();
-                });
-            }
-        }
+        let throttle = app_c.create_throttle(gid, db_entry.config.speed_limit);
+        let file_changed = app_c.file_changed_policy();
+        let min_free_space = app_c.db.inner.general_config.lock().min_free_space;
+        let space = app_c.disk_space.guard(gid, min_free_space);
+        let category_dirs = app_c.category_dirs(&db_entry.config.save_dir);
+        let res = download(
+            url,
+            mirrors,
+            &config,
+            token,
+            Some(db_entry),
+            throttle.clone(),
+            space,
+            app_c.hosts.clone(),
+            file_changed,
+            category_dirs,
+            handler,
+        )
+        .await;
+        app_c.release_throttle(gid, &throttle);
+        handle_result(&app_c, gid, res);
     }
     .force_send();
-    app.task_set.add_task(gid, cancel_token, fut);
-    true
+    add_task(app, gid, priority, cancel_token, fut, delay);
+}
+
+/// 解析每行一个的下载链接，忽略无效的行
+pub fn parse_urls(urls: &str) -> impl Iterator<Item = Url> + '_ {
+    urls.lines().filter_map(|s| {
+        Url::parse(s.trim())
+            .ok()
+            .filter(|u| matches!(u.scheme(), "http" | "https"))
+    })
+}
+
+/// 链接中的 Metalink 文件，支持本地路径和 file:// 链接
+pub fn parse_metalink_path(line: &str) -> Option<PathBuf> {
+    let line = line.trim().trim_matches('"');
+    let path = match Url::parse(line) {
+        Ok(url) if url.scheme() == "file" => url.to_file_path().ok()?,
+        Ok(url) if matches!(url.scheme(), "http" | "https") => return None,
+        _ => PathBuf::from(line),
+    };
+    is_metalink(&path).then_some(path)
+}
+
+/// 把 Metalink 中的每个文件添加为一个任务，镜像、大小和校验值来自 Metalink，返回新任务的 gid
+///
+/// config 来自全局设置时传入 `site_rules` 为 true，按每个文件的链接应用站点规则
+pub fn import_metalink(
+    app: &App,
+    path: &Path,
+    config: &DownloadConfig,
+    site_rules: bool,
+    list_model: &VecModel<EntryData>,
+) -> color_eyre::Result<Vec<i32>> {
+    let files = read_metalink(path).log_err("读取 Metalink 文件失败")?;
+    info!(path = ?path, count = files.len(), "导入 Metalink 文件");
+    let mut gids = Vec::new();
+    for file in files {
+        let mut config = config.clone();
+        if site_rules && let Some(url) = file.urls.first() {
+            app.db.apply_site_rules(url, &mut config);
+        }
+        config.file_name = file.name.into();
+        config.parse_filename = false;
+        config.checksum = file
+            .checksum
+            .map(|c| c.to_shared_string())
+            .unwrap_or_default();
+        config.expected_size = file.size.map(|s| s.to_shared_string()).unwrap_or_default();
+        let mut urls = file.urls.into_iter();
+        if let Some(url) = urls.next() {
+            gids.push(start_new_entry(
+                app,
+                url,
+                urls.collect(),
+                &config,
+                list_model,
+            ));
+        }
+    }
+    Ok(gids)
 }
 
+/// 按对话框中的链接添加任务，勾选镜像时所有链接作为同一个任务，Metalink 文件单独导入，返回新任务的 gid
+///
+/// `shown` 为对话框打开时显示的全局设置时，按每个链接应用站点规则，对话框中修改过的项不变
+pub fn start_new_entries(
+    app: &App,
+    urls: &str,
+    config: &DownloadConfig,
+    shown: Option<&DownloadConfig>,
+    list_model: &VecModel<EntryData>,
+) -> Vec<i32> {
+    let url_config = |url: &Url| match shown {
+        Some(shown) => reapply_site_rules(
+            &app.db.inner.site_rules.lock(),
+            url,
+            &app.db.get_ui_download_config(),
+            shown,
+            config,
+        ),
+        None => config.clone(),
+    };
+    let mut gids = Vec::new();
+    for path in urls.lines().filter_map(parse_metalink_path) {
+        if let Ok(g) = import_metalink(app, &path, config, false, list_model) {
+            gids.extend(g);
+        }
+    }
+    let mut urls = parse_urls(urls);
+    if config.as_mirrors {
+        if let Some(url) = urls.next() {
+            let config = url_config(&url);
+            gids.push(start_new_entry(
+                app,
+                url,
+                urls.collect(),
+                &config,
+                list_model,
+            ));
+        }
+    } else {
+        for url in urls {
+            let config = url_config(&url);
+            gids.push(start_new_entry(app, url, Vec::new(), &config, list_model));
+        }
+    }
+    gids
+}
+
+/// 返回新任务的 gid
 pub fn start_new_entry(
     app: &App,
     url: Url,
+    mirrors: Vec<Url>,
     config: &DownloadConfig,
     list_model: &VecModel<EntryData>,
-) {
+) -> i32 {
+    let mut config = config.clone();
+    if config.checksum.is_empty()
+        && let Some(checksum) = Checksum::from_url(&url)
+    {
+        config.checksum = checksum.to_shared_string();
+    }
     let gid = app.db.next_gid();
     let entry = DatabaseEntry {
         file_name: url.to_string(),
@@ -64,9 +254,19 @@ pub fn start_new_entry(
         progress: Vec::new(),
         elapsed: Duration::ZERO,
         url: url.clone(),
-        config: config.into(),
+        config: (&config).into(),
         status: persist::Status::Paused,
+        verified: None,
+        start_at: start_at_from_ui(&config.start_at),
+        priority: Priority::from_ui(config.priority),
+        attempts: 0,
+        error_kind: None,
+        mirrors: mirrors.clone(),
+        action_result: None,
     };
+    let delay = start_at_delay(entry.start_at);
+    let priority = entry.priority;
+    let speed_limit = entry.config.speed_limit;
     let mut ui_entry = entry.to_entry_data(gid);
     ui_entry.status = Status::Waiting;
     list_model.push(ui_entry);
@@ -75,22 +275,141 @@ pub fn start_new_entry(
     let app_c = app.clone();
     let cancel_token = CancellationToken::new();
     let token = cancel_token.clone();
-    let config = config.clone();
 
     let fut = async move {
+        app_c.db.update_error_kind(gid, None);
+        app_c.update_ui_row(gid, move |_, data| {
+            data.error = SharedString::new();
+            data.hint = SharedString::new();
+        });
         let handler = app_c.create_download_handler(gid);
-        match download(url, &config, token, None, handler).await {
-            Ok(()) => info!(gid = gid, "任务下载完成"),
-            Err(e) => {
-                error!(gid = gid, err = ?e, "下载任务出错");
-                app_c.db.update_status(gid, persist::Status::Error);
-                app_c.update_ui_row(gid, move |_, data| {
-                    data.status = Status::Error;
-                    data.error = e.to_shared_string();
-                });
+        let throttle = app_c.create_throttle(gid, speed_limit);
+        let file_changed = app_c.file_changed_policy();
+        let min_free_space = app_c.db.inner.general_config.lock().min_free_space;
+        let space = app_c.disk_space.guard(gid, min_free_space);
+        let category_dirs = app_c.category_dirs(Path::new(config.save_dir.as_str()));
+        let res = download(
+            url,
+            mirrors,
+            &config,
+            token,
+            None,
+            throttle.clone(),
+            space,
+            app_c.hosts.clone(),
+            file_changed,
+            category_dirs,
+            handler,
+        )
+        .await;
+        app_c.release_throttle(gid, &throttle);
+        handle_result(&app_c, gid, res);
+    }
+    .force_send();
+    add_task(app, gid, priority, cancel_token, fut, delay);
+    gid
+}
+
+/// 定时开始的时间未到时，返回剩余时间和提示文本
+fn start_at_delay(start_at: Option<SystemTime>) -> Option<(Duration, String)> {
+    let start_at = start_at?;
+    let delay = start_at.duration_since(SystemTime::now()).ok()?;
+    Some((delay, format!("将于 {} 开始", format_start_at(start_at))))
+}
+
+/// 处理下载结果，出错时按重试策略重新排队，否则标记为错误
+fn handle_result(app: &App, gid: i32, res: Result<(), DownloadError>) {
+    app.speeds.remove(&gid);
+    let e = match res {
+        Ok(()) => return info!(gid = gid, "任务下载完成"),
+        Err(e) => e,
+    };
+    error!(gid = gid, err = ?e, "下载任务出错");
+    let kind = e.kind();
+    app.db.update_error_kind(gid, Some(kind));
+    let policy = app.db.inner.general_config.lock().retry.clone();
+    if let Some(mut db_entry) = app.db.inner.data.get(&gid).map(|e| e.clone())
+        && !app.exiting.load(Ordering::Relaxed)
+    {
+        let attempts = db_entry.attempts + 1;
+        if let Some(delay) = retry_delay(&policy, classify(kind), attempts) {
+            info!(gid = gid, attempts = attempts, delay = ?delay, "任务将自动重试");
+            db_entry.attempts = attempts;
+            app.db.update_attempts(gid, attempts);
+            let text = format!(
+                "第 {attempts}/{} 次重试将在 {} 秒后开始：{e}",
+                policy.max_attempts,
+                delay.as_secs()
+            );
+            app.update_ui_row(gid, |_, data| data.status = Status::Waiting);
+            enqueue_entry(app, gid, db_entry, Some((delay, text)));
+            return;
+        }
+    }
+    app.db.update_status(gid, persist::Status::Error);
+    app.update_ui_row(gid, move |_, data| {
+        data.status = Status::Error;
+        data.error = e.to_shared_string();
+        data.hint = kind.suggestion().into();
+    });
+}
+
+/// 加入任务队列，`delay` 不为空时等待对应时间后再进入队列，期间显示提示文本
+fn add_task<F>(
+    app: &App,
+    gid: i32,
+    priority: Priority,
+    cancel_token: CancellationToken,
+    fut: F,
+    delay: Option<(Duration, String)>,
+) where
+    F: Future<Output = ()> + Send + 'static,
+{
+    match delay {
+        Some((delay, text)) => {
+            app.update_ui_row(gid, move |_, data| data.error = text.into());
+            app.task_set
+                .add_task_at(gid, priority, cancel_token, fut, Instant::now() + delay);
+        }
+        None => app.task_set.add_task(gid, priority, cancel_token, fut),
+    }
+    app.db.update_status(gid, persist::Status::Waiting);
+    app.save_queue();
+}
+
+/// 恢复上次退出时仍在下载和排队的任务，关闭自动恢复时将它们标记为暂停
+pub fn resume_entries(app: &App, list_model: &VecModel<EntryData>) {
+    let mut active: Vec<_> = app
+        .db
+        .inner
+        .data
+        .iter()
+        .filter(|e| e.status.is_active())
+        .map(|e| (*e.key(), e.status == persist::Status::Running))
+        .collect();
+    if !app.db.is_auto_resume() {
+        for (gid, _) in active {
+            app.db.update_status(gid, persist::Status::Paused);
+        }
+        return;
+    }
+    // 先恢复正在下载的，再按原来的队列顺序恢复排队的
+    active.sort_by_key(|(gid, _)| *gid);
+    app.db.sort_by_queue(&mut active, |(gid, _)| *gid);
+    active.sort_by_key(|(_, is_running)| !is_running);
+    info!(count = active.len(), "恢复未完成的任务");
+    for (gid, _) in active {
+        for i in 0..list_model.row_count() {
+            let Some(mut entry) = list_model.row_data(i) else {
+                continue;
+            };
+            if entry.gid == gid {
+                if start_entry(app, &entry, list_model) {
+                    entry.status = Status::Waiting;
+                    list_model.set_row_data(i, entry);
+                }
+                break;
             }
         }
     }
-    .force_send();
-    app.task_set.add_task(gid, cancel_token, fut);
 }
@@ -1,15 +1,18 @@
+use crate::persist::Priority;
 use parking_lot::Mutex;
 use std::{
-    collections::{HashMap, VecDeque},
+    collections::{HashMap, HashSet, VecDeque},
     future::Future,
     hash::Hash,
     sync::Arc,
+    time::Instant,
 };
 use tokio::sync::watch;
 use tokio_util::sync::CancellationToken;
 
 struct QueuedTask<K> {
     id: K,
+    priority: Priority,
     task: Box<dyn FnOnce() + Send>,
 }
 
@@ -19,6 +22,20 @@ struct State<K> {
     pending_queue: VecDeque<QueuedTask<K>>,
     tasks: HashMap<K, (u64, CancellationToken)>,
     next_tag: u64,
+    /// 等待定时开始的任务的 tag
+    delayed: HashSet<u64>,
+}
+
+impl<K> State<K> {
+    fn is_idle(&self) -> bool {
+        self.current_running == 0 && self.pending_queue.is_empty() && self.delayed.is_empty()
+    }
+}
+
+impl<K: PartialEq> State<K> {
+    fn queue_pos(&self, id: &K) -> Option<usize> {
+        self.pending_queue.iter().position(|q| q.id == *id)
+    }
 }
 
 impl<K> Drop for State<K> {
@@ -57,23 +74,72 @@ impl<K: Clone + Eq + Hash + Send + 'static> TaskSet<K> {
                 pending_queue: VecDeque::new(),
                 tasks: HashMap::new(),
                 next_tag: 0,
+                delayed: HashSet::new(),
             })),
             idle_tx: Arc::new(tx),
         }
     }
 
     /// 添加任务
-    pub fn add_task<F>(&self, id: K, cancel_token: CancellationToken, fut: F)
+    pub fn add_task<F>(&self, id: K, priority: Priority, cancel_token: CancellationToken, fut: F)
     where
         F: Future<Output = ()> + Send + 'static,
     {
         let mut state = self.state.lock();
+        let (_, wrapped_fn) = self.register(&mut state, id.clone(), cancel_token, fut);
+        self.enqueue(&mut state, id, priority, wrapped_fn);
+    }
+
+    /// 添加定时任务，在 `at` 之前任务不会进入队列，期间取消会立即执行任务以便其感知取消
+    pub fn add_task_at<F>(
+        &self,
+        id: K,
+        priority: Priority,
+        cancel_token: CancellationToken,
+        fut: F,
+        at: Instant,
+    ) where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        let mut state = self.state.lock();
+        let (tag, wrapped_fn) = self.register(&mut state, id.clone(), cancel_token.clone(), fut);
+        state.delayed.insert(tag);
+        drop(state);
+        let this = self.clone();
+        tokio::spawn(async move {
+            tokio::select! {
+                _ = tokio::time::sleep_until(at.into()) => {}
+                _ = cancel_token.cancelled() => {}
+            }
+            let mut state = this.state.lock();
+            state.delayed.remove(&tag);
+            if cancel_token.is_cancelled() {
+                state.current_running += 1;
+                wrapped_fn();
+            } else {
+                this.enqueue(&mut state, id, priority, wrapped_fn);
+            }
+            this.try_spawn_next(&mut state);
+        });
+    }
+
+    /// 登记任务并包装成可执行的闭包，返回任务的 tag，同 id 的旧任务会被取消
+    fn register<F>(
+        &self,
+        state: &mut State<K>,
+        id: K,
+        cancel_token: CancellationToken,
+        fut: F,
+    ) -> (u64, impl FnOnce() + Send + 'static)
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
         let tag = {
             state.next_tag += 1;
             state.next_tag - 1
         };
 
-        if let Some((_, old_token)) = state.tasks.insert(id.clone(), (tag, cancel_token.clone())) {
+        if let Some((_, old_token)) = state.tasks.insert(id.clone(), (tag, cancel_token)) {
             old_token.cancel();
             if let Some(pos) = state.pending_queue.iter().position(|q| q.id == id) {
                 let queued = state.pending_queue.remove(pos).unwrap();
@@ -82,35 +148,117 @@ impl<K: Clone + Eq + Hash + Send + 'static> TaskSet<K> {
             }
         };
 
-        let wrapped_fn = {
-            let weak_state = Arc::downgrade(&self.state);
-            let weak_tx = Arc::downgrade(&self.idle_tx);
-            let id = id.clone();
-            move || match (weak_state.upgrade(), weak_tx.upgrade()) {
-                (Some(state), Some(idle_tx)) => {
-                    let this = TaskSet { state, idle_tx };
-                    tokio::spawn(async move {
-                        let _guard = TaskGuard { this, id, tag };
-                        fut.await;
-                    });
-                }
-                _ => {
-                    tokio::spawn(fut);
-                }
+        let weak_state = Arc::downgrade(&self.state);
+        let weak_tx = Arc::downgrade(&self.idle_tx);
+        let wrapped_fn = move || match (weak_state.upgrade(), weak_tx.upgrade()) {
+            (Some(state), Some(idle_tx)) => {
+                let this = TaskSet { state, idle_tx };
+                tokio::spawn(async move {
+                    let _guard = TaskGuard { this, id, tag };
+                    fut.await;
+                });
+            }
+            _ => {
+                tokio::spawn(fut);
             }
         };
+        (tag, wrapped_fn)
+    }
 
+    /// 排在所有优先级不低于它的任务之后
+    fn enqueue(
+        &self,
+        state: &mut State<K>,
+        id: K,
+        priority: Priority,
+        wrapped_fn: impl FnOnce() + Send + 'static,
+    ) {
         if state.current_running < state.max_concurrency {
             state.current_running += 1;
             wrapped_fn();
         } else {
-            state.pending_queue.push_back(QueuedTask {
-                id,
-                task: Box::new(wrapped_fn),
-            });
+            let pos = state
+                .pending_queue
+                .iter()
+                .position(|q| q.priority < priority)
+                .unwrap_or(state.pending_queue.len());
+            state.pending_queue.insert(
+                pos,
+                QueuedTask {
+                    id,
+                    priority,
+                    task: Box::new(wrapped_fn),
+                },
+            );
+        }
+    }
+
+    /// 修改排队中任务的优先级，并重新排队
+    pub fn set_priority(&self, id: &K, priority: Priority) {
+        let mut state = self.state.lock();
+        if let Some(pos) = state.queue_pos(id) {
+            let queued = state.pending_queue.remove(pos).unwrap();
+            self.enqueue(&mut state, queued.id, priority, queued.task);
+        }
+    }
+
+    /// 在队列中前移一位，返回是否移动
+    pub fn move_up(&self, id: &K) -> bool {
+        let mut state = self.state.lock();
+        match state.queue_pos(id) {
+            Some(pos) if pos > 0 => {
+                state.pending_queue.swap(pos, pos - 1);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// 在队列中后移一位，返回是否移动
+    pub fn move_down(&self, id: &K) -> bool {
+        let mut state = self.state.lock();
+        match state.queue_pos(id) {
+            Some(pos) if pos + 1 < state.pending_queue.len() => {
+                state.pending_queue.swap(pos, pos + 1);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// 移到队列最前面，返回是否移动
+    pub fn move_to_top(&self, id: &K) -> bool {
+        let mut state = self.state.lock();
+        match state.queue_pos(id) {
+            Some(pos) if pos > 0 => {
+                let queued = state.pending_queue.remove(pos).unwrap();
+                state.pending_queue.push_front(queued);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// 无视并发数限制立即开始排队中的任务，返回是否开始
+    pub fn start_now(&self, id: &K) -> bool {
+        let mut state = self.state.lock();
+        match state.queue_pos(id) {
+            Some(pos) => {
+                let queued = state.pending_queue.remove(pos).unwrap();
+                state.current_running += 1;
+                (queued.task)();
+                true
+            }
+            None => false,
         }
     }
 
+    /// 排队中的任务，按开始顺序
+    pub fn queued_ids(&self) -> Vec<K> {
+        let state = self.state.lock();
+        state.pending_queue.iter().map(|q| q.id.clone()).collect()
+    }
+
     /// 取消指定任务
     pub fn cancel_task(&self, id: &K) {
         let mut state = self.state.lock();
@@ -146,7 +294,7 @@ impl<K: Clone + Eq + Hash + Send + 'static> TaskSet<K> {
             loop {
                 {
                     let s = state.lock();
-                    if s.current_running == 0 && s.pending_queue.is_empty() {
+                    if s.is_idle() {
                         return;
                     }
                 }
@@ -162,16 +310,12 @@ impl<K: Clone + Eq + Hash + Send + 'static> TaskSet<K> {
         async move {
             let baseline = {
                 let s = state.lock();
-                if s.current_running == 0 && s.pending_queue.is_empty() {
-                    Some(s.next_tag)
-                } else {
-                    None
-                }
+                if s.is_idle() { Some(s.next_tag) } else { None }
             };
             loop {
                 {
                     let s = state.lock();
-                    if s.current_running == 0 && s.pending_queue.is_empty() {
+                    if s.is_idle() {
                         match baseline {
                             Some(tag) => {
                                 if s.next_tag > tag {
@@ -197,7 +341,25 @@ impl<K: Clone + Eq + Hash + Send + 'static> TaskSet<K> {
     /// 状态统计
     pub fn stats(&self) -> (usize, usize) {
         let state = self.state.lock();
-        (state.current_running, state.pending_queue.len())
+        (
+            state.current_running,
+            state.pending_queue.len() + state.delayed.len(),
+        )
+    }
+
+    /// 正在运行（不在队列中，也不在等待定时开始）的任务
+    pub fn running_ids(&self) -> Vec<K> {
+        let state = self.state.lock();
+        state
+            .tasks
+            .iter()
+            .filter(|(id, (tag, token))| {
+                !token.is_cancelled()
+                    && !state.delayed.contains(tag)
+                    && !state.pending_queue.iter().any(|q| q.id == **id)
+            })
+            .map(|(id, _)| id.clone())
+            .collect()
     }
 
     fn on_task_finished(&self, id: &K, task_tag: u64) {
@@ -218,8 +380,57 @@ impl<K: Clone + Eq + Hash + Send + 'static> TaskSet<K> {
             state.current_running += 1;
             (queued.task)();
         }
-        if state.current_running == 0 && state.pending_queue.is_empty() {
+        if state.is_idle() {
             self.idle_tx.send_modify(|v| *v = v.wrapping_add(1));
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_queue_order() {
+        let task_set = TaskSet::new(0);
+        let add =
+            |id, priority| task_set.add_task(id, priority, CancellationToken::new(), async {});
+        add(1, Priority::Normal);
+        add(2, Priority::Low);
+        add(3, Priority::High);
+        add(4, Priority::Normal);
+        assert_eq!(task_set.queued_ids(), [3, 1, 4, 2]);
+
+        assert!(task_set.move_to_top(&2));
+        assert!(task_set.move_down(&3));
+        assert!(!task_set.move_up(&2));
+        assert_eq!(task_set.queued_ids(), [2, 1, 3, 4]);
+
+        task_set.set_priority(&4, Priority::High);
+        assert_eq!(task_set.queued_ids(), [4, 2, 1, 3]);
+
+        assert!(task_set.start_now(&1));
+        assert_eq!(task_set.queued_ids(), [4, 2, 3]);
+        assert_eq!(task_set.stats(), (1, 3));
+    }
+
+    #[tokio::test]
+    async fn test_running_ids() {
+        let task_set = TaskSet::new(1);
+        let (tx, rx) = tokio::sync::oneshot::channel::<()>();
+        task_set.add_task(1, Priority::Normal, CancellationToken::new(), async {
+            let _ = rx.await;
+        });
+        task_set.add_task(2, Priority::Normal, CancellationToken::new(), async {});
+        task_set.add_task_at(
+            3,
+            Priority::Normal,
+            CancellationToken::new(),
+            async {},
+            Instant::now() + std::time::Duration::from_secs(3600),
+        );
+        assert_eq!(task_set.running_ids(), [1]);
+        assert_eq!(task_set.stats(), (1, 2));
+        drop(tx);
+    }
+}
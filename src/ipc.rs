@@ -1,28 +1,64 @@
 use crate::{
-    core::{App, start_new_entry},
-    os::wakeup_window,
-    ui::{DialogType, EntryData},
-    utils::{LogErr, parse_header, show_task_dialog},
+    core::{App, import_metalink, start_entry_by_gid, start_new_entries, start_new_entry},
+    persist::DatabaseEntry,
+    ui::{self, DialogType, EntryData},
+    utils::{LogErr, SiteRule, apply_site_rules, parse_header, show_task_dialog, spawn_local},
 };
 use crossfire::mpsc;
+use fast_down_ffi::Total;
 use interprocess::local_socket::{
-    GenericNamespaced, ListenerOptions,
+    ListenerOptions, Name,
     tokio::{Stream, prelude::*},
 };
 use itertools::Itertools;
 use serde::{Deserialize, Serialize};
-use slint::{ToSharedString, VecModel};
-use std::{process::exit, rc::Rc};
-use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use serde_json::Value;
+use slint::{Model, ToSharedString, VecModel};
+use std::{collections::HashSet, fmt, path::PathBuf, process::exit, rc::Rc};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+    sync::oneshot,
+};
 use url::Url;
 
 pub const NS_NAME: &str = "top.s121.fd.sock";
 
+/// 只有当前用户能连接的 IPC 地址
+///
+/// Unix 上为数据文件夹中权限为 0700 的文件夹里的套接字文件，Windows 上为命名管道，
+/// 权限在 [`init_ipc`] 中限制
+pub fn socket_name() -> std::io::Result<Name<'static>> {
+    #[cfg(unix)]
+    {
+        use interprocess::local_socket::GenericFilePath;
+        use std::os::unix::fs::{DirBuilderExt, PermissionsExt};
+        let dir = crate::persist::DB_DIR.join("ipc");
+        std::fs::DirBuilder::new()
+            .recursive(true)
+            .mode(0o700)
+            .create(&dir)?;
+        std::fs::set_permissions(&dir, std::fs::Permissions::from_mode(0o700))?;
+        dir.join(NS_NAME).to_fs_name::<GenericFilePath>()
+    }
+    #[cfg(not(unix))]
+    {
+        use interprocess::local_socket::GenericNamespaced;
+        NS_NAME.to_ns_name::<GenericNamespaced>()
+    }
+}
+/// IPC 协议版本，不兼容的修改时增加
+pub const IPC_VERSION: u32 = 1;
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct DownloadOptions {
     pub url: Url,
     pub headers: Option<String>,
+    /// 例如 `sha256:abcd`
+    pub checksum: Option<String>,
+    /// 命令行中指定的保存文件夹，为绝对路径
+    pub save_dir: Option<PathBuf>,
+    pub file_name: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -30,25 +66,478 @@ pub struct DownloadOptions {
 pub enum IpcMessage {
     WakeUp,
     Download(DownloadOptions),
+    /// 导入 Metalink 文件，路径为绝对路径
+    Metalink {
+        path: PathBuf,
+    },
+    List,
+    Pause {
+        gids: Vec<i32>,
+    },
+    Resume {
+        gids: Vec<i32>,
+    },
+    Remove {
+        gids: Vec<i32>,
+    },
+    /// 修改常规设置，为空的项不修改
+    SetConfig {
+        max_concurrency: Option<usize>,
+        /// 全局限速，KiB/s，0 为不限速
+        speed_limit: Option<u64>,
+    },
+}
+
+/// 每个连接发送一行 JSON 请求，旧的客户端直接发送 [`IpcMessage`]，视为版本 1
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IpcRequest {
+    #[serde(default = "default_version")]
+    pub version: u32,
+    /// 原样放回回复中，用于对应请求和回复
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<Value>,
+    #[serde(flatten)]
+    pub message: IpcMessage,
+}
+
+fn default_version() -> u32 {
+    1
+}
+
+impl IpcRequest {
+    pub fn new(message: IpcMessage) -> Self {
+        Self {
+            version: IPC_VERSION,
+            id: None,
+            message,
+        }
+    }
+}
+
+/// 对每个请求回复一行 JSON，例如 `{"version":1,"id":3,"result":{"type":"Added","gids":[5]}}`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct IpcResponse {
+    pub version: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<Value>,
+    #[serde(flatten)]
+    pub outcome: IpcOutcome,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum IpcOutcome {
+    #[serde(rename = "result")]
+    Ok(IpcReply),
+    #[serde(rename = "error")]
+    Err(IpcError),
+}
+
+impl IpcResponse {
+    pub fn new(id: Option<Value>, result: Result<IpcReply, IpcError>) -> Self {
+        Self {
+            version: IPC_VERSION,
+            id,
+            outcome: match result {
+                Ok(reply) => IpcOutcome::Ok(reply),
+                Err(e) => IpcOutcome::Err(e),
+            },
+        }
+    }
+
+    pub fn into_result(self) -> Result<IpcReply, IpcError> {
+        match self.outcome {
+            IpcOutcome::Ok(reply) => Ok(reply),
+            IpcOutcome::Err(e) => Err(e),
+        }
+    }
+}
+
+/// 请求成功时的结果
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type")]
+pub enum IpcReply {
+    Ok,
+    /// 新任务的 gid
+    Added {
+        gids: Vec<i32>,
+    },
+    Tasks {
+        tasks: Vec<TaskInfo>,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum IpcErrorCode {
+    /// 无法解析的请求
+    InvalidRequest,
+    /// 请求的协议版本比当前实例新
+    UnsupportedVersion,
+    /// 不支持的链接，例如非 http(s) 链接
+    InvalidUrl,
+    /// 任务不存在
+    NotFound,
+    /// 用户取消了任务对话框
+    Cancelled,
+    /// 参数超出范围
+    InvalidParams,
+    /// 其他错误
+    Failed,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct IpcError {
+    pub code: IpcErrorCode,
+    pub message: String,
+    /// 不存在的任务的 gid
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub gids: Vec<i32>,
+}
+
+impl IpcError {
+    pub fn new(code: IpcErrorCode, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+            gids: Vec::new(),
+        }
+    }
+
+    fn not_found(gids: Vec<i32>) -> Self {
+        Self {
+            code: IpcErrorCode::NotFound,
+            message: format!("任务不存在：{}", gids.iter().join(", ")),
+            gids,
+        }
+    }
+}
+
+impl fmt::Display for IpcError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl std::error::Error for IpcError {}
+
+/// 解析一行请求，失败时的错误中尽量带上请求的 id
+pub fn parse_request(line: &str) -> Result<IpcRequest, (Option<Value>, IpcError)> {
+    let value: Value = serde_json::from_str(line).map_err(|e| {
+        (
+            None,
+            IpcError::new(IpcErrorCode::InvalidRequest, format!("无法解析请求：{e}")),
+        )
+    })?;
+    let id = value.get("id").cloned();
+    let version = value.get("version").and_then(Value::as_u64).unwrap_or(1);
+    if version > IPC_VERSION as u64 {
+        return Err((
+            id,
+            IpcError::new(
+                IpcErrorCode::UnsupportedVersion,
+                format!("不支持的协议版本 {version}，当前版本为 {IPC_VERSION}"),
+            ),
+        ));
+    }
+    serde_json::from_value(value).map_err(|e| {
+        (
+            id,
+            IpcError::new(IpcErrorCode::InvalidRequest, format!("无法解析请求：{e}")),
+        )
+    })
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TaskStatus {
+    Running,
+    Verifying,
+    Extracting,
+    Waiting,
+    Paused,
+    Completed,
+    Error,
+}
+
+impl From<ui::Status> for TaskStatus {
+    fn from(value: ui::Status) -> Self {
+        match value {
+            ui::Status::Running => Self::Running,
+            ui::Status::Verifying => Self::Verifying,
+            ui::Status::Extracting => Self::Extracting,
+            ui::Status::Waiting => Self::Waiting,
+            ui::Status::Paused => Self::Paused,
+            ui::Status::Completed => Self::Completed,
+            ui::Status::Error => Self::Error,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TaskInfo {
+    pub gid: i32,
+    pub url: Url,
+    pub file_name: String,
+    pub file_path: PathBuf,
+    pub status: TaskStatus,
+    pub downloaded: u64,
+    pub total: u64,
+    /// 界面中显示的速度，例如 `1.00 MiB/s`
+    pub speed: String,
+    pub error: String,
+}
+
+impl TaskInfo {
+    fn new(entry: &DatabaseEntry, row: &EntryData) -> Self {
+        Self {
+            gid: row.gid,
+            url: entry.url.clone(),
+            file_name: entry.file_name.clone(),
+            file_path: entry.file_path.clone(),
+            status: row.status.into(),
+            downloaded: entry.progress.total(),
+            total: entry.file_size,
+            speed: row.speed.to_string(),
+            error: row.error.to_string(),
+        }
+    }
 }
 
 /// 这是用户正常双击运行软件时，检查是否已经有在运行的实例
-pub async fn check_ipc_and_wake() -> color_eyre::Result<()> {
-    let ns_name = NS_NAME.to_ns_name::<GenericNamespaced>()?;
-    if let Ok(mut stream) = Stream::connect(ns_name).await {
-        tracing::info!("发现已有实例，正在发送唤醒信号...");
-        let msg = IpcMessage::WakeUp;
-        if let Ok(json) = serde_json::to_string(&msg) {
-            let _ = stream.write_all(format!("{json}\n").as_bytes()).await;
+///
+/// 命令行中带有链接或 Metalink 文件时，交给已有实例下载
+pub async fn check_ipc_and_wake(msgs: Vec<IpcMessage>) -> color_eyre::Result<()> {
+    let ns_name = socket_name()?;
+    if let Ok(stream) = Stream::connect(ns_name.clone()).await {
+        tracing::info!(msgs = msgs.len(), "发现已有实例，正在发送唤醒信号...");
+        let mut msgs = msgs.into_iter();
+        send_ipc(stream, &msgs.next().unwrap_or(IpcMessage::WakeUp)).await;
+        // 每个连接只处理一条消息
+        for msg in msgs {
+            if let Ok(stream) = Stream::connect(ns_name.clone()).await {
+                send_ipc(stream, &msg).await;
+            }
         }
         exit(0);
     }
     Ok(())
 }
 
+async fn send_ipc(mut stream: Stream, msg: &IpcMessage) {
+    if let Ok(json) = serde_json::to_string(&IpcRequest::new(msg.clone())) {
+        let _ = stream.write_all(format!("{json}\n").as_bytes()).await;
+    }
+}
+
+/// 向已有实例发送一条消息并等待回复，没有运行中的实例时返回 None
+pub async fn request(msg: &IpcMessage) -> color_eyre::Result<Option<Result<IpcReply, IpcError>>> {
+    let ns_name = socket_name()?;
+    let Ok(stream) = Stream::connect(ns_name).await else {
+        return Ok(None);
+    };
+    Ok(Some(
+        send_request(stream, &IpcRequest::new(msg.clone()))
+            .await?
+            .into_result(),
+    ))
+}
+
+/// 在已建立的连接上发送请求并读取回复
+pub async fn send_request(stream: Stream, req: &IpcRequest) -> color_eyre::Result<IpcResponse> {
+    let mut reader = BufReader::new(stream);
+    let json = serde_json::to_string(req)?;
+    reader
+        .get_mut()
+        .write_all(format!("{json}\n").as_bytes())
+        .await?;
+    let mut buffer = String::new();
+    reader.read_line(&mut buffer).await?;
+    Ok(serde_json::from_str(&buffer)?)
+}
+
+/// 在全局设置上依次应用请求头、站点规则和命令行中明确指定的设置，站点规则只在这里应用一次
+fn download_config(
+    mut config: ui::DownloadConfig,
+    site_rules: &[SiteRule],
+    skip_headers: &HashSet<String>,
+    e: &DownloadOptions,
+) -> ui::DownloadConfig {
+    if let Some(s) = &e.headers {
+        config.headers = parse_header(s)
+            .filter(|x| !skip_headers.contains(&x.0.to_lowercase()))
+            .map(|x| format!("{}:{}", x.0, x.1))
+            .join("\n")
+            .into();
+    }
+    if let Some(checksum) = &e.checksum {
+        config.checksum = checksum.into();
+    }
+    apply_site_rules(site_rules, &e.url, &mut config);
+    // 命令行中明确指定的优先于站点规则
+    if let Some(save_dir) = &e.save_dir {
+        config.save_dir = save_dir.to_string_lossy().as_ref().into();
+    }
+    if let Some(file_name) = &e.file_name {
+        config.file_name = file_name.into();
+        config.parse_filename = false;
+    }
+    config
+}
+
+/// 添加外部发来的下载任务，开启下载前询问时先弹出任务对话框
+///
+/// 添加后通过返回的接收端发送新任务的 gid，对话框被取消时发送端被丢弃
+pub fn handle_download(
+    app: &App,
+    list_model: &Rc<VecModel<EntryData>>,
+    e: DownloadOptions,
+) -> oneshot::Receiver<Vec<i32>> {
+    let (tx, rx) = oneshot::channel();
+    let config = download_config(
+        app.db.get_ui_download_config(),
+        &app.db.inner.site_rules.lock(),
+        &app.db.inner.general_config.lock().skip_headers,
+        &e,
+    );
+    if app.db.is_ask_before_download() && app.sink.has_window() {
+        let app = app.clone();
+        let list_model = list_model.clone();
+        let _ = show_task_dialog(
+            e.url.to_shared_string(),
+            DialogType::AddTask,
+            config,
+            true,
+            move |urls, config, bg_download| {
+                let _ = tx.send(start_new_entries(&app, &urls, &config, None, &list_model));
+                if !bg_download {
+                    app.sink.wake_up();
+                }
+            },
+        )
+        .log_err("任务对话框失败");
+    } else {
+        let gid = start_new_entry(app, e.url, Vec::new(), &config, list_model);
+        let _ = tx.send(vec![gid]);
+    }
+    rx
+}
+
+/// 在 UI 线程处理一条消息，添加任务时会等待任务对话框关闭
+async fn handle_message(
+    app: &App,
+    list_model: &Rc<VecModel<EntryData>>,
+    msg: IpcMessage,
+) -> Result<IpcReply, IpcError> {
+    let result = |missing_gids: Vec<i32>| {
+        if missing_gids.is_empty() {
+            Ok(IpcReply::Ok)
+        } else {
+            Err(IpcError::not_found(missing_gids))
+        }
+    };
+    let exists = |gid: &i32| list_model.iter().any(|e| e.gid == *gid);
+    match msg {
+        IpcMessage::WakeUp => {
+            tracing::info!("收到唤醒信号");
+            app.sink.wake_up();
+            Ok(IpcReply::Ok)
+        }
+        IpcMessage::Download(e) => {
+            tracing::info!("收到外部下载请求: {}", e.url);
+            if !matches!(e.url.scheme(), "http" | "https") {
+                return Err(IpcError::new(
+                    IpcErrorCode::InvalidUrl,
+                    format!("不支持的链接：{}", e.url),
+                ));
+            }
+            match handle_download(app, list_model, e).await {
+                Ok(gids) => Ok(IpcReply::Added { gids }),
+                Err(_) => Err(IpcError::new(IpcErrorCode::Cancelled, "已取消添加任务")),
+            }
+        }
+        IpcMessage::Metalink { path } => {
+            tracing::info!(path = ?path, "收到 Metalink 文件");
+            let config = app.db.get_ui_download_config();
+            let gids = import_metalink(app, &path, &config, true, list_model).map_err(|e| {
+                IpcError::new(IpcErrorCode::Failed, format!("读取 Metalink 文件失败：{e}"))
+            })?;
+            app.sink.wake_up();
+            Ok(IpcReply::Added { gids })
+        }
+        IpcMessage::List => Ok(IpcReply::Tasks {
+            tasks: list_model
+                .iter()
+                .filter_map(|row| {
+                    let entry = app.db.inner.data.get(&row.gid)?;
+                    Some(TaskInfo::new(&entry, &row))
+                })
+                .sorted_by_key(|t| t.gid)
+                .collect(),
+        }),
+        IpcMessage::Pause { gids } => {
+            tracing::info!(gids = ?gids, "收到暂停请求");
+            let (found, missing_gids): (Vec<_>, Vec<_>) = gids.into_iter().partition(exists);
+            for gid in found {
+                app.task_set.cancel_task(&gid);
+            }
+            result(missing_gids)
+        }
+        IpcMessage::Resume { gids } => {
+            tracing::info!(gids = ?gids, "收到继续请求");
+            let (found, missing_gids): (Vec<_>, Vec<_>) = gids.into_iter().partition(exists);
+            for gid in found {
+                start_entry_by_gid(app, gid, list_model);
+            }
+            result(missing_gids)
+        }
+        IpcMessage::Remove { gids } => {
+            tracing::info!(gids = ?gids, "收到移除请求");
+            let missing_gids: Vec<_> = gids
+                .into_iter()
+                .filter(|&gid| !app.remove_entry(gid, list_model))
+                .collect();
+            result(missing_gids)
+        }
+        IpcMessage::SetConfig {
+            max_concurrency,
+            speed_limit,
+        } => {
+            tracing::info!(?max_concurrency, ?speed_limit, "收到修改设置请求");
+            let mut general_config = app.db.get_ui_general_config();
+            apply_general_options(&mut general_config, max_concurrency, speed_limit)?;
+            app.set_config(app.db.get_ui_download_config(), general_config, None);
+            Ok(IpcReply::Ok)
+        }
+    }
+}
+
+/// 修改常规设置中的并发数和全局限速，超出范围时不修改
+fn apply_general_options(
+    config: &mut ui::GeneralConfig,
+    max_concurrency: Option<usize>,
+    speed_limit: Option<u64>,
+) -> Result<(), IpcError> {
+    let out_of_range =
+        |name: &str| IpcError::new(IpcErrorCode::InvalidParams, format!("{name}超出范围"));
+    let max_concurrency = max_concurrency
+        .map(|n| i32::try_from(n.max(1)).map_err(|_| out_of_range("最大并发数")))
+        .transpose()?;
+    let speed_limit = speed_limit
+        .map(|kib| i32::try_from(kib).map_err(|_| out_of_range("全局限速")))
+        .transpose()?;
+    if let Some(n) = max_concurrency {
+        config.max_concurrency = n;
+    }
+    if let Some(kib) = speed_limit {
+        config.speed_limit = kib;
+    }
+    Ok(())
+}
+
 /// 监听其他实例（或浏览器代理进程）发来的 IPC 请求
 pub async fn init_ipc(app: App, list_model: Rc<VecModel<EntryData>>) -> color_eyre::Result<()> {
-    let ns_name = NS_NAME.to_ns_name::<GenericNamespaced>()?;
+    let ns_name = socket_name()?;
     #[allow(unused_mut)]
     let mut options = ListenerOptions::new().name(ns_name).try_overwrite(true);
     #[cfg(windows)]
@@ -57,61 +546,27 @@ pub async fn init_ipc(app: App, list_model: Rc<VecModel<EntryData>>) -> color_ey
             local_socket::ListenerOptionsExt, security_descriptor::SecurityDescriptor,
         };
         use widestring::U16CString;
-        let sddl = U16CString::from_str("D:(A;;GA;;;WD)")?;
+        // 只允许当前用户和系统连接，以管理员身份运行时普通权限的同一用户仍可连接
+        let sid = crate::os::current_user_sid()?;
+        let sddl = U16CString::from_str(format!("D:P(A;;GA;;;{sid})(A;;GA;;;SY)"))?;
         let sd = SecurityDescriptor::deserialize(&sddl)?;
         options = options.security_descriptor(sd);
     }
     let listener = options.create_tokio()?;
 
-    let (tx, rx) = mpsc::unbounded_async::<IpcMessage>();
+    type Reply = Result<IpcReply, IpcError>;
+    let (tx, rx) = mpsc::unbounded_async::<(IpcMessage, oneshot::Sender<Reply>)>();
 
-    let ui_weak = app.ui.clone();
-    slint::spawn_local(async move {
-        while let Ok(msg) = rx.recv().await {
-            match msg {
-                IpcMessage::WakeUp => {
-                    tracing::info!("收到唤醒信号");
-                    let _ = ui_weak.upgrade_in_event_loop(|ui| wakeup_window(&ui));
-                }
-                IpcMessage::Download(e) => {
-                    tracing::info!("收到外部下载请求: {}", e.url);
-                    let mut config = app.db.get_ui_download_config();
-                    if let Some(s) = e.headers {
-                        let skip_headers = &app.db.inner.general_config.lock().skip_headers;
-                        config.headers = parse_header(&s)
-                            .filter(|x| !skip_headers.contains(&x.0.to_lowercase()))
-                            .map(|x| format!("{}:{}", x.0, x.1))
-                            .join("\n")
-                            .into();
-                    }
-                    if app.db.is_ask_before_download() {
-                        let app = app.clone();
-                        let list_model = list_model.clone();
-                        let _ = show_task_dialog(
-                            e.url.to_shared_string(),
-                            DialogType::AddTask,
-                            config,
-                            true,
-                            move |urls, config, bg_download| {
-                                let valid_urls = urls.lines().filter_map(|s| {
-                                    Url::parse(s)
-                                        .ok()
-                                        .filter(|u| matches!(u.scheme(), "http" | "https"))
-                                });
-                                for url in valid_urls {
-                                    start_new_entry(&app, url, &config, &list_model);
-                                }
-                                if !bg_download && let Some(ui) = app.ui.upgrade() {
-                                    wakeup_window(&ui);
-                                }
-                            },
-                        )
-                        .log_err("任务对话框失败");
-                    } else {
-                        start_new_entry(&app, e.url, &config, &list_model);
-                    }
-                }
-            }
+    spawn_local(async move {
+        while let Ok((msg, reply_tx)) = rx.recv().await {
+            // 任务对话框打开时不阻塞其他消息
+            let app = app.clone();
+            let list_model = list_model.clone();
+            let _ = spawn_local(async move {
+                let reply = handle_message(&app, &list_model, msg).await;
+                let _ = reply_tx.send(reply);
+            })
+            .log_err("处理 IPC 消息失败");
         }
     })
     .log_err("IPC 消息处理任务失败")?;
@@ -124,10 +579,29 @@ pub async fn init_ipc(app: App, list_model: Rc<VecModel<EntryData>>) -> color_ey
                     tokio::spawn(async move {
                         let mut reader = BufReader::new(conn);
                         let mut buffer = String::new();
-                        if reader.read_line(&mut buffer).await.is_ok()
-                            && let Ok(msg) = serde_json::from_str::<IpcMessage>(&buffer)
-                        {
-                            let _ = tx.send(msg);
+                        if reader.read_line(&mut buffer).await.is_err() {
+                            return;
+                        }
+                        let response = match parse_request(&buffer) {
+                            Ok(req) => {
+                                let (reply_tx, reply_rx) = oneshot::channel();
+                                let _ = tx.send((req.message, reply_tx));
+                                let reply = reply_rx.await.unwrap_or_else(|_| {
+                                    Err(IpcError::new(IpcErrorCode::Failed, "请求没有被处理"))
+                                });
+                                IpcResponse::new(req.id, reply)
+                            }
+                            Err((id, e)) => {
+                                tracing::warn!(err = %e, line = buffer.trim(), "无法解析 IPC 请求");
+                                IpcResponse::new(id, Err(e))
+                            }
+                        };
+                        // 旧的客户端不读取回复，写入失败时忽略
+                        if let Ok(json) = serde_json::to_string(&response) {
+                            let _ = reader
+                                .get_mut()
+                                .write_all(format!("{json}\n").as_bytes())
+                                .await;
                         }
                     });
                 }
@@ -137,3 +611,87 @@ pub async fn init_ipc(app: App, list_model: Rc<VecModel<EntryData>>) -> color_ey
     });
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::SitePattern;
+    use serde_json::json;
+
+    #[test]
+    fn test_parse_request() {
+        // 旧的客户端不带版本号和 id
+        let req = parse_request(r#"{"type":"WakeUp"}"#).unwrap();
+        assert_eq!((req.version, req.id), (1, None));
+        let req = parse_request(r#"{"version":1,"id":7,"type":"Pause","gids":[1,2]}"#).unwrap();
+        assert_eq!(req.id, Some(json!(7)));
+        assert!(matches!(req.message, IpcMessage::Pause { gids } if gids == [1, 2]));
+
+        let (id, e) = parse_request(r#"{"version":99,"id":"a","type":"List"}"#).unwrap_err();
+        assert_eq!(
+            (id, e.code),
+            (Some(json!("a")), IpcErrorCode::UnsupportedVersion)
+        );
+        let (id, e) = parse_request(r#"{"id":3,"type":"Unknown"}"#).unwrap_err();
+        assert_eq!((id, e.code), (Some(json!(3)), IpcErrorCode::InvalidRequest));
+        let (id, e) = parse_request("not json").unwrap_err();
+        assert_eq!((id, e.code), (None, IpcErrorCode::InvalidRequest));
+    }
+
+    #[test]
+    fn test_download_config() {
+        let rules = [SiteRule {
+            pattern: SitePattern::Host("*.example.com".into()),
+            headers: [("Referer".into(), "https://example.com".into())].into(),
+            proxy: None,
+            threads: Some(4),
+            save_dir: Some("/rule".into()),
+            file_name: None,
+        }];
+        let mut e = DownloadOptions {
+            url: Url::parse("https://dl.example.com/a.zip").unwrap(),
+            headers: None,
+            checksum: None,
+            save_dir: None,
+            file_name: None,
+        };
+        let config = download_config(Default::default(), &rules, &HashSet::new(), &e);
+        assert_eq!((config.save_dir.as_str(), config.threads), ("/rule", 4));
+
+        // 命令行中明确指定的保存文件夹不会被站点规则覆盖
+        e.save_dir = Some("/cli".into());
+        let config = download_config(Default::default(), &rules, &HashSet::new(), &e);
+        assert_eq!((config.save_dir.as_str(), config.threads), ("/cli", 4));
+        assert_eq!(config.headers.as_str(), "Referer: https://example.com");
+    }
+
+    #[test]
+    fn test_apply_general_options() {
+        let mut config = ui::GeneralConfig::default();
+        apply_general_options(&mut config, Some(0), Some(1024)).unwrap();
+        assert_eq!((config.max_concurrency, config.speed_limit), (1, 1024));
+        let e = apply_general_options(&mut config, Some(2), Some(u64::MAX)).unwrap_err();
+        assert_eq!(e.code, IpcErrorCode::InvalidParams);
+        // 出错时其他项也不修改
+        assert_eq!(config.max_concurrency, 1);
+        let e = apply_general_options(&mut config, Some(usize::MAX), None).unwrap_err();
+        assert_eq!(e.code, IpcErrorCode::InvalidParams);
+    }
+
+    #[test]
+    fn test_response_json() {
+        let ok = IpcResponse::new(Some(json!(3)), Ok(IpcReply::Added { gids: vec![5] }));
+        assert_eq!(
+            serde_json::to_value(&ok).unwrap(),
+            json!({"version": 1, "id": 3, "result": {"type": "Added", "gids": [5]}})
+        );
+        let err = IpcResponse::new(None, Err(IpcError::not_found(vec![4])));
+        let value = serde_json::to_value(&err).unwrap();
+        assert_eq!(
+            value,
+            json!({"version": 1, "error": {"code": "not_found", "message": "任务不存在：4", "gids": [4]}})
+        );
+        let parsed: IpcResponse = serde_json::from_value(value).unwrap();
+        assert_eq!(parsed.into_result(), Err(IpcError::not_found(vec![4])));
+    }
+}
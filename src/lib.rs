@@ -1,9 +1,12 @@
 pub mod addons;
+pub mod api;
+pub mod cli;
 pub mod core;
 pub mod fmt;
 pub mod ipc;
 pub mod os;
 pub mod persist;
+pub mod rpc;
 pub mod utils;
 pub mod ui {
     slint::include_modules!();
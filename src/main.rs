@@ -3,18 +3,28 @@
 use arboard::Clipboard;
 use fast_down_gui::{
     addons::{CHROME_EXT_IDS, FIREFOX_EXT_ID, auto_register, handle_browser_request},
-    core::{App, TaskSet, start_entry, start_new_entry},
-    ipc::{check_ipc_and_wake, init_ipc},
+    api::ApiServer,
+    cli::CliArgs,
+    core::{
+        App, HeadlessSink, WindowSink, import_metalink, log_task_events, parse_metalink_path,
+        parse_urls, resume_entries, start_entry, start_entry_by_gid, start_new_entries,
+        update_entry,
+    },
+    ipc::{IpcMessage, check_ipc_and_wake, handle_download, init_ipc},
     os::{attach_console, get_auto_start, is_admin, setup_tray, try_restart_as_admin},
-    persist::{DB_DIR, Database},
+    persist::{DB_DIR, Database, Priority},
+    rpc::RpcServer,
     ui::*,
-    utils::{LogErr, show_task_dialog},
+    utils::{LogErr, format_start_at, set_headless, show_task_dialog, start_at_from_ui},
 };
 use file_alloc::init_fast_alloc;
+use i_slint_backend_winit::{EventResult, WinitWindowAccessor, winit::event::WindowEvent};
+use itertools::Itertools;
 use rfd::FileDialog;
 use slint::{Model, ModelRc, ToSharedString, VecModel};
-use std::{collections::HashSet, rc::Rc, sync::Arc};
-use tracing::{info, level_filters::LevelFilter};
+use std::{collections::HashSet, future::pending, pin::pin, rc::Rc, sync::Arc, time::Duration};
+use tokio::task::LocalSet;
+use tracing::{info, level_filters::LevelFilter, warn};
 use tracing_appender::{
     non_blocking::WorkerGuard,
     rolling::{RollingFileAppender, Rotation},
@@ -62,14 +72,38 @@ async fn main() -> color_eyre::Result<()> {
         return handle_browser_request().await;
     }
     attach_console();
+    let cli = CliArgs::parse(args.iter().skip(1).cloned());
+    if let Some(command) = &cli.command
+        && let Some(code) = cli.run_command(command).await
+    {
+        std::process::exit(code);
+    }
     let _guard = init_tracing();
+    for arg in &cli.ignored {
+        warn!(arg = arg, "无法识别的命令行参数");
+    }
+    // 命令行中的链接和 Metalink 文件，已有实例时交给它下载
+    let _ = check_ipc_and_wake(cli.ipc_messages())
+        .await
+        .log_err("检查 ipc 通道错误");
+    let _ = auto_register().log_err("写入浏览器扩展通信配置失败");
+    let db = Database::new().await;
+    let run_as_admin = db.inner.general_config.lock().run_as_admin;
+    let _ = try_restart_as_admin(run_as_admin).log_err("以管理员身份重启失败");
+    init_fast_alloc();
+    let entries = db.inner.data.iter().map(|e| e.to_entry_data(*e.key()));
+    let list_model = Rc::new(VecModel::from_iter(entries));
+    if cli.daemon {
+        return run_daemon(db, list_model, cli.ipc_messages()).await;
+    }
+
     #[cfg(target_os = "linux")]
     let _gtk_timer = {
         let _ = gtk::init().log_err("初始化 gtk 错误");
         let timer = slint::Timer::default();
         timer.start(
             slint::TimerMode::Repeated,
-            std::time::Duration::from_millis(50),
+            Duration::from_millis(50),
             move || {
                 while gtk::events_pending() {
                     gtk::main_iteration_do(false);
@@ -79,14 +113,7 @@ async fn main() -> color_eyre::Result<()> {
         timer
     };
 
-    let _ = check_ipc_and_wake().await.log_err("检查 ipc 通道错误");
-    let _ = auto_register().log_err("写入浏览器扩展通信配置失败");
     let ui = MainWindow::new()?;
-    let db = Database::new().await;
-    let run_as_admin = db.inner.general_config.lock().run_as_admin;
-    let _ = try_restart_as_admin(run_as_admin).log_err("以管理员身份重启失败");
-    init_fast_alloc();
-    let task_set = TaskSet::new(db.inner.general_config.lock().max_concurrency);
     let auto = get_auto_start()
         .log_err("初始化开机自启错误")
         .ok()
@@ -96,16 +123,22 @@ async fn main() -> color_eyre::Result<()> {
     {
         let _ = auto.enable().log_err("启用开机自启失败");
     }
-    let entries = db.inner.data.iter().map(|e| e.to_entry_data(*e.key()));
-    let list_model = Rc::new(VecModel::from_iter(entries));
-    let app = App {
-        db: db.clone(),
-        task_set: task_set.clone(),
-        ui: ui.as_weak(),
+    let app = App::new(db.clone(), Arc::new(WindowSink(ui.as_weak())));
+    let task_set = app.task_set.clone();
+    app.apply_schedule(&list_model);
+    resume_entries(&app, &list_model);
+    let _schedule_timer = {
+        let app = app.clone();
+        let list_model = list_model.clone();
+        let timer = slint::Timer::default();
+        timer.start(
+            slint::TimerMode::Repeated,
+            Duration::from_secs(10),
+            move || app.apply_schedule(&list_model),
+        );
+        timer
     };
-    let _ = init_ipc(app.clone(), list_model.clone())
-        .await
-        .log_err("初始化 ipc 通道错误");
+    let _servers = start_services(&app, &list_model).await;
 
     let _tray = setup_tray(app.clone()).log_err("初始化托盘错误");
     setup_ui_lists(&ui, list_model.clone());
@@ -113,6 +146,23 @@ async fn main() -> color_eyre::Result<()> {
     ui.set_general_config(db.get_ui_general_config());
     ui.set_version(VERSION.into());
     ui.set_admin(is_admin());
+    add_cli_tasks(&app, &list_model, cli.ipc_messages());
+
+    // 拖入 Metalink 文件时直接导入
+    ui.window().on_winit_window_event({
+        let app = app.clone();
+        let list_model = list_model.clone();
+        move |_, event| {
+            if let WindowEvent::DroppedFile(path) = event
+                && let Some(path) = parse_metalink_path(&path.to_string_lossy())
+            {
+                let config = app.db.get_ui_download_config();
+                let _ = import_metalink(&app, &path, &config, true, &list_model);
+                return EventResult::PreventDefault;
+            }
+            EventResult::Propagate
+        }
+    });
 
     ui.global::<Logic>().on_exit({
         let app = app.clone();
@@ -136,9 +186,34 @@ async fn main() -> color_eyre::Result<()> {
     ui.global::<Logic>().on_config_change({
         let app = app.clone();
         let auto = auto.clone();
+        let list_model = list_model.clone();
         move |download_config, general_config| {
             info!(download_config = ?download_config, general_config = ?general_config, "配置已更新");
             app.set_config(download_config, general_config, auto.as_deref());
+            app.apply_schedule(&list_model);
+        }
+    });
+
+    let site_rules = Rc::new(VecModel::from(app.db.get_ui_site_rules()));
+    ui.set_site_rules(ModelRc::from(site_rules.clone()));
+    ui.global::<Logic>().on_add_site_rule({
+        let site_rules = site_rules.clone();
+        move || site_rules.push(SiteRuleData::default())
+    });
+    ui.global::<Logic>().on_remove_site_rule({
+        let site_rules = site_rules.clone();
+        move |i| {
+            if (i as usize) < site_rules.row_count() {
+                site_rules.remove(i as usize);
+            }
+        }
+    });
+    ui.global::<Logic>().on_save_site_rules({
+        let app = app.clone();
+        move |list| {
+            let rules: Vec<_> = list.iter().map(|r| (&r).into()).collect();
+            info!(rules = ?rules, "站点规则已更新");
+            app.db.set_site_rules(rules);
         }
     });
 
@@ -146,7 +221,9 @@ async fn main() -> color_eyre::Result<()> {
         let app = app.clone();
         let list_model = list_model.clone();
         move |list| {
-            for (i, mut entry) in list.iter().enumerate() {
+            let mut entries: Vec<_> = list.iter().enumerate().collect();
+            app.db.sort_by_queue(&mut entries, |(_, e)| e.gid);
+            for (i, mut entry) in entries {
                 if entry.status == Status::Completed {
                     continue;
                 }
@@ -159,6 +236,14 @@ async fn main() -> color_eyre::Result<()> {
         }
     });
     ui.global::<Logic>().on_start_entry({
+        let app = app.clone();
+        let list_model = list_model.clone();
+        move |gid| {
+            start_entry_by_gid(&app, gid, &list_model);
+        }
+    });
+
+    ui.global::<Logic>().on_update_entry({
         let app = app.clone();
         let list_model = list_model.clone();
         move |gid| {
@@ -167,8 +252,7 @@ async fn main() -> color_eyre::Result<()> {
                     break;
                 };
                 if entry.gid == gid {
-                    let is_started = start_entry(&app, &entry, &list_model);
-                    if is_started {
+                    if update_entry(&app, &entry) {
                         entry.status = Status::Waiting;
                         list_model.set_row_data(i, entry);
                     }
@@ -178,6 +262,39 @@ async fn main() -> color_eyre::Result<()> {
         }
     });
 
+    ui.global::<Logic>().on_start_now_entry({
+        let app = app.clone();
+        move |gid| {
+            if app.task_set.start_now(&gid) {
+                app.save_queue();
+            }
+        }
+    });
+    ui.global::<Logic>().on_move_top_entry({
+        let app = app.clone();
+        move |gid| {
+            if app.task_set.move_to_top(&gid) {
+                app.save_queue();
+            }
+        }
+    });
+    ui.global::<Logic>().on_move_up_entry({
+        let app = app.clone();
+        move |gid| {
+            if app.task_set.move_up(&gid) {
+                app.save_queue();
+            }
+        }
+    });
+    ui.global::<Logic>().on_move_down_entry({
+        let app = app.clone();
+        move |gid| {
+            if app.task_set.move_down(&gid) {
+                app.save_queue();
+            }
+        }
+    });
+
     ui.global::<Logic>().on_pause_all({
         let task_set = task_set.clone();
         move |list| {
@@ -192,39 +309,18 @@ async fn main() -> color_eyre::Result<()> {
     });
 
     ui.global::<Logic>().on_remove_all({
-        let task_set = task_set.clone();
+        let app = app.clone();
         let list_model = list_model.clone();
-        let db = db.clone();
         move |list| {
             let ids_to_remove: HashSet<_> = list.iter().map(|e| e.gid).collect();
-            let mut kept_items = Vec::new();
-            for item in list_model.iter() {
-                if !ids_to_remove.contains(&item.gid) {
-                    kept_items.push(item);
-                }
-            }
-            list_model.set_vec(kept_items);
-            for gid in ids_to_remove.iter() {
-                task_set.cancel_task(gid);
-                let _ = db.remove_entry(*gid).log_err("数据库移除条目失败");
-            }
+            app.remove_entries(&ids_to_remove, &list_model);
         }
     });
     ui.global::<Logic>().on_remove_entry({
-        let task_set = task_set.clone();
+        let app = app.clone();
         let list_model = list_model.clone();
         move |gid| {
-            for i in (0..list_model.row_count()).rev() {
-                let Some(item) = list_model.row_data(i) else {
-                    break;
-                };
-                if item.gid == gid {
-                    list_model.remove(i);
-                    break;
-                }
-            }
-            task_set.cancel_task(&gid);
-            let _ = db.remove_entry(gid).log_err("数据库移除条目失败");
+            app.remove_entry(gid, &list_model);
         }
     });
 
@@ -236,24 +332,22 @@ async fn main() -> color_eyre::Result<()> {
             let url = Clipboard::new()
                 .ok()
                 .and_then(|mut c| c.get_text().ok())
-                .filter(|s| Url::parse(s).is_ok_and(|u| matches!(u.scheme(), "http" | "https")))
-                .unwrap_or_default();
+                .and_then(|s| Url::parse(&s).ok())
+                .filter(|u| matches!(u.scheme(), "http" | "https"));
+            // 先显示剪贴板中链接的站点规则，确认后再按每个链接应用
+            let mut shown = db.get_ui_download_config();
+            if let Some(url) = &url {
+                db.apply_site_rules(url, &mut shown);
+            }
             let app = app.clone();
             let list_model = list_model.clone();
             let _ = show_task_dialog(
-                url.into(),
+                url.map(|u| u.to_shared_string()).unwrap_or_default(),
                 DialogType::AddTask,
-                db.get_ui_download_config(),
+                shown.clone(),
                 false,
                 move |urls, config, _| {
-                    let valid_urls = urls.lines().filter_map(|s| {
-                        Url::parse(s)
-                            .ok()
-                            .filter(|u| matches!(u.scheme(), "http" | "https"))
-                    });
-                    for url in valid_urls {
-                        start_new_entry(&app, url, &config, &list_model);
-                    }
+                    start_new_entries(&app, &urls, &config, Some(&shown), &list_model);
                 },
             )
             .log_err("添加任务对话框启动失败");
@@ -274,27 +368,42 @@ async fn main() -> color_eyre::Result<()> {
     });
 
     ui.global::<Logic>().on_detail_entry({
-        let db = app.db.clone();
+        let app = app.clone();
         move |gid| {
-            let Some(mut entry) = db.inner.data.get(&gid).map(|e| e.clone()) else {
+            let Some(mut entry) = app.db.inner.data.get(&gid).map(|e| e.clone()) else {
                 return;
             };
-            let db = db.clone();
+            let app = app.clone();
+            let mut config = entry.config.to_ui_download_config();
+            config.start_at = entry
+                .start_at
+                .map(format_start_at)
+                .unwrap_or_default()
+                .into();
+            config.priority = entry.priority.to_ui();
+            config.as_mirrors = !entry.mirrors.is_empty();
+            let urls = std::iter::once(&entry.url).chain(&entry.mirrors).join("\n");
             let _ = show_task_dialog(
-                entry.url.to_shared_string(),
+                urls.into(),
                 DialogType::EditTask,
-                entry.config.to_ui_download_config(),
+                config,
                 false,
                 move |urls, config, _| {
-                    let mut valid_urls = urls.lines().filter_map(|s| {
-                        Url::parse(s)
-                            .ok()
-                            .filter(|u| matches!(u.scheme(), "http" | "https"))
-                    });
+                    let mut valid_urls = parse_urls(&urls);
                     if let Some(url) = valid_urls.next() {
                         entry.url = url;
+                        entry.mirrors = if config.as_mirrors {
+                            valid_urls.collect()
+                        } else {
+                            Vec::new()
+                        };
                         entry.config = (&config).into();
-                        let _ = db.init_entry(gid, entry).log_err("更新任务配置失败");
+                        entry.start_at = start_at_from_ui(&config.start_at);
+                        entry.priority = Priority::from_ui(config.priority);
+                        let priority = entry.priority;
+                        app.set_task_speed_limit(gid, entry.config.speed_limit);
+                        let _ = app.db.init_entry(gid, entry).log_err("更新任务配置失败");
+                        app.set_priority(gid, priority);
                     }
                 },
             )
@@ -308,10 +417,11 @@ async fn main() -> color_eyre::Result<()> {
 
     let _ = slint::spawn_local({
         let app = app.clone();
+        let ui = ui.as_weak();
         async move {
             loop {
                 app.task_set.wait_last().await;
-                let Some(ui) = app.ui.upgrade() else { break };
+                let Some(ui) = ui.upgrade() else { break };
                 let visible = ui.window().is_visible();
                 info!(main_window_visible = visible, "所有任务已完成");
                 if !visible && app.db.is_exit_after_download() {
@@ -323,7 +433,7 @@ async fn main() -> color_eyre::Result<()> {
     })
     .log_err("无法检测程序下载状态");
 
-    let is_hidden = args.iter().any(|s| s == "--hidden");
+    let is_hidden = cli.hidden;
     #[cfg(target_os = "linux")]
     {
         ui.show()?;
@@ -345,22 +455,122 @@ async fn main() -> color_eyre::Result<()> {
     Ok(())
 }
 
+/// 添加命令行中的链接和 Metalink 文件
+fn add_cli_tasks(app: &App, list_model: &Rc<VecModel<EntryData>>, msgs: Vec<IpcMessage>) {
+    for msg in msgs {
+        match msg {
+            IpcMessage::Metalink { path } => {
+                let config = app.db.get_ui_download_config();
+                let _ = import_metalink(app, &path, &config, true, list_model);
+            }
+            IpcMessage::Download(e) => {
+                handle_download(app, list_model, e);
+            }
+            _ => {}
+        }
+    }
+}
+
+/// 两种模式共用的后台服务，需要在任务列表所在线程调用，返回的服务需保持存活
+async fn start_services(
+    app: &App,
+    list_model: &Rc<VecModel<EntryData>>,
+) -> (Option<Rc<RpcServer>>, Option<Rc<ApiServer>>) {
+    tokio::spawn({
+        let app = app.clone();
+        async move {
+            let mut interval = tokio::time::interval(Duration::from_secs(5));
+            loop {
+                interval.tick().await;
+                app.check_disk_space();
+            }
+        }
+    });
+    let _ = init_ipc(app.clone(), list_model.clone())
+        .await
+        .log_err("初始化 ipc 通道错误");
+    let rpc = RpcServer::new(app.clone(), list_model.clone())
+        .log_err("初始化 RPC 服务错误")
+        .ok();
+    if let Some(rpc) = &rpc {
+        rpc.apply();
+    }
+    let api = ApiServer::new(app.clone(), list_model.clone())
+        .log_err("初始化 REST API 错误")
+        .ok();
+    if let Some(api) = &api {
+        api.apply();
+    }
+    (rpc, api)
+}
+
+/// 无界面运行，进度写入日志，收到 Ctrl+C 或 SIGTERM 时保存并退出
+async fn run_daemon(
+    db: Database,
+    list_model: Rc<VecModel<EntryData>>,
+    msgs: Vec<IpcMessage>,
+) -> color_eyre::Result<()> {
+    set_headless();
+    LocalSet::new()
+        .run_until(async move {
+            let app = App::new(db, Arc::new(HeadlessSink::new(list_model.clone())?));
+            tokio::spawn(log_task_events(app.events.subscribe()));
+            app.apply_schedule(&list_model);
+            resume_entries(&app, &list_model);
+            let _servers = start_services(&app, &list_model).await;
+            add_cli_tasks(&app, &list_model, msgs);
+            info!("以无界面模式运行");
+            let mut shutdown = pin!(shutdown_signal());
+            let mut interval = tokio::time::interval(Duration::from_secs(10));
+            loop {
+                tokio::select! {
+                    _ = interval.tick() => app.apply_schedule(&list_model),
+                    _ = &mut shutdown => break,
+                }
+            }
+            info!("收到退出信号，正在保存");
+            app.exit();
+            pending().await
+        })
+        .await
+}
+
+async fn shutdown_signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{SignalKind, signal};
+        match signal(SignalKind::terminate()).log_err("监听 SIGTERM 失败") {
+            Ok(mut term) => tokio::select! {
+                _ = tokio::signal::ctrl_c() => {}
+                _ = term.recv() => {}
+            },
+            Err(_) => {
+                let _ = tokio::signal::ctrl_c().await;
+            }
+        }
+    }
+    #[cfg(not(unix))]
+    let _ = tokio::signal::ctrl_c().await;
+}
+
 /// 设置 UI 列表的各种过滤视图
 fn setup_ui_lists(ui: &MainWindow, list_model: Rc<VecModel<EntryData>>) {
     ui.set_all_list(ModelRc::new(
         list_model.clone().sort_by(|a, b| b.gid.cmp(&a.gid)),
     ));
-    let filter_view = |status: Status| {
+    let filter_view = |filter: fn(&Status) -> bool| {
         ModelRc::new(
             list_model
                 .clone()
-                .filter(move |e| e.status == status)
+                .filter(move |e| filter(&e.status))
                 .sort_by(|a, b| b.gid.cmp(&a.gid)),
         )
     };
-    ui.set_running_list(filter_view(Status::Running));
-    ui.set_waiting_list(filter_view(Status::Waiting));
-    ui.set_paused_list(filter_view(Status::Paused));
-    ui.set_completed_list(filter_view(Status::Completed));
-    ui.set_error_list(filter_view(Status::Error));
+    ui.set_running_list(filter_view(|s| {
+        matches!(s, Status::Running | Status::Verifying | Status::Extracting)
+    }));
+    ui.set_waiting_list(filter_view(|s| *s == Status::Waiting));
+    ui.set_paused_list(filter_view(|s| *s == Status::Paused));
+    ui.set_completed_list(filter_view(|s| *s == Status::Completed));
+    ui.set_error_list(filter_view(|s| *s == Status::Error));
 }
@@ -0,0 +1,65 @@
+use std::{
+    io,
+    path::{Path, PathBuf},
+};
+
+/// 路径所在磁盘的可用空间，路径不存在时使用最近的已存在的上级目录
+#[cfg(target_os = "windows")]
+pub fn free_space(path: &Path) -> io::Result<u64> {
+    use windows::Win32::Storage::FileSystem::GetDiskFreeSpaceExW;
+    use windows::core::HSTRING;
+
+    let path = existing_ancestor(path)?;
+    let mut free = 0;
+    unsafe { GetDiskFreeSpaceExW(&HSTRING::from(path), Some(&raw mut free), None, None) }?;
+    Ok(free)
+}
+
+/// 路径所在磁盘的可用空间，路径不存在时使用最近的已存在的上级目录
+#[cfg(not(target_os = "windows"))]
+pub fn free_space(path: &Path) -> io::Result<u64> {
+    use std::{ffi::CString, os::unix::ffi::OsStrExt};
+
+    let path = existing_ancestor(path)?;
+    let path = CString::new(path.as_os_str().as_bytes())?;
+    let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+    if unsafe { libc::statvfs(path.as_ptr(), &mut stat) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    #[allow(clippy::unnecessary_cast)]
+    Ok(stat.f_bavail as u64 * stat.f_frsize as u64)
+}
+
+/// 路径所在磁盘的挂载点，用于判断两个路径是否在同一个磁盘上
+#[cfg(target_os = "windows")]
+pub fn volume_root(path: &Path) -> io::Result<PathBuf> {
+    use windows::Win32::Storage::FileSystem::GetVolumePathNameW;
+    use windows::core::HSTRING;
+
+    let path = existing_ancestor(path)?;
+    let mut buf = [0u16; 1024];
+    unsafe { GetVolumePathNameW(&HSTRING::from(path), &mut buf) }?;
+    let len = buf.iter().position(|&c| c == 0).unwrap_or(buf.len());
+    Ok(PathBuf::from(String::from_utf16_lossy(&buf[..len])))
+}
+
+/// 路径所在磁盘的挂载点，用于判断两个路径是否在同一个磁盘上
+#[cfg(not(target_os = "windows"))]
+pub fn volume_root(path: &Path) -> io::Result<PathBuf> {
+    use std::os::unix::fs::MetadataExt;
+
+    let mut root = existing_ancestor(path)?;
+    let dev = root.metadata()?.dev();
+    while let Some(parent) = root.parent()
+        && parent.metadata().is_ok_and(|m| m.dev() == dev)
+    {
+        root = parent;
+    }
+    Ok(root.to_path_buf())
+}
+
+fn existing_ancestor(path: &Path) -> io::Result<&Path> {
+    path.ancestors()
+        .find(|p| p.exists())
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "路径不存在"))
+}
@@ -1,13 +1,19 @@
 mod admin;
 mod attach_console;
 mod auto_start;
+mod disk;
 mod spawn_self;
 mod tray;
+#[cfg(target_os = "windows")]
+mod user;
 mod wakeup;
 
 pub use admin::*;
 pub use attach_console::*;
 pub use auto_start::*;
+pub use disk::*;
 pub use spawn_self::*;
 pub use tray::*;
+#[cfg(target_os = "windows")]
+pub use user::*;
 pub use wakeup::*;
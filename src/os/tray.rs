@@ -1,10 +1,19 @@
-use crate::{core::App, os::wakeup_window};
+use crate::core::App;
 use color_eyre::eyre::Context;
 use tray_icon::{
     MouseButton, TrayIcon, TrayIconBuilder, TrayIconEvent,
-    menu::{Menu, MenuEvent, MenuItem, PredefinedMenuItem},
+    menu::{Menu, MenuEvent, MenuItem, PredefinedMenuItem, Submenu},
 };
 
+/// 托盘菜单中的限速档位，单位 KiB/s
+const SPEED_LIMIT_PRESETS: [(&str, i32); 5] = [
+    ("不限速", 0),
+    ("1 MiB/s", 1024),
+    ("5 MiB/s", 5 * 1024),
+    ("10 MiB/s", 10 * 1024),
+    ("50 MiB/s", 50 * 1024),
+];
+
 pub fn setup_tray(app: App) -> color_eyre::Result<TrayIcon> {
     let icon = {
         #[cfg(target_os = "macos")]
@@ -20,12 +29,19 @@ pub fn setup_tray(app: App) -> color_eyre::Result<TrayIcon> {
     let tray_menu = Menu::new();
     let show_item = MenuItem::new("显示主界面", true, None);
     let pause_all_item = MenuItem::new("全部暂停", true, None);
+    let speed_limit_menu = Submenu::new("全局限速", true);
+    let speed_limit_items =
+        SPEED_LIMIT_PRESETS.map(|(text, kib)| (MenuItem::new(text, true, None), kib));
+    for (item, _) in &speed_limit_items {
+        speed_limit_menu.append(item).context("无法创建托盘")?;
+    }
     let quit_item = MenuItem::new("退出", true, None);
     tray_menu
         .append_items(&[
             &show_item,
             &PredefinedMenuItem::separator(),
             &pause_all_item,
+            &speed_limit_menu,
             &PredefinedMenuItem::separator(),
             &quit_item,
         ])
@@ -37,7 +53,7 @@ pub fn setup_tray(app: App) -> color_eyre::Result<TrayIcon> {
         .with_menu_on_left_click(false)
         .build()?;
     TrayIconEvent::set_event_handler(Some({
-        let ui = app.ui.clone();
+        let sink = app.sink.clone();
         move |event| match event {
             TrayIconEvent::Click {
                 button: MouseButton::Left,
@@ -46,11 +62,7 @@ pub fn setup_tray(app: App) -> color_eyre::Result<TrayIcon> {
             | TrayIconEvent::DoubleClick {
                 button: MouseButton::Left,
                 ..
-            } => {
-                let _ = ui.upgrade_in_event_loop(|ui| {
-                    wakeup_window(&ui);
-                });
-            }
+            } => sink.wake_up(),
             _ => {}
         }
     }));
@@ -59,16 +71,19 @@ pub fn setup_tray(app: App) -> color_eyre::Result<TrayIcon> {
         let show_item_id = show_item.into_id();
         let pause_all_item_id = pause_all_item.into_id();
         let quit_item_id = quit_item.into_id();
+        let speed_limit_ids = speed_limit_items.map(|(item, kib)| (item.into_id(), kib));
         move |event: MenuEvent| {
             let id = event.id;
             if id == show_item_id {
-                let _ = app.ui.upgrade_in_event_loop(|ui| {
-                    wakeup_window(&ui);
-                });
+                app.sink.wake_up();
             } else if id == pause_all_item_id {
                 app.task_set.cancel_all();
             } else if id == quit_item_id {
                 app.exit();
+            } else if let Some((_, kib)) = speed_limit_ids.iter().find(|(i, _)| *i == id) {
+                let mut general_config = app.db.get_ui_general_config();
+                general_config.speed_limit = *kib;
+                app.set_config(app.db.get_ui_download_config(), general_config, None);
             }
         }
     }));
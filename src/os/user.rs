@@ -0,0 +1,31 @@
+/// 当前用户的 SID 字符串，例如 `S-1-5-21-...`
+pub fn current_user_sid() -> color_eyre::Result<String> {
+    use windows::Win32::Foundation::{CloseHandle, HANDLE, HLOCAL, LocalFree};
+    use windows::Win32::Security::Authorization::ConvertSidToStringSidW;
+    use windows::Win32::Security::{GetTokenInformation, TOKEN_QUERY, TOKEN_USER, TokenUser};
+    use windows::Win32::System::Threading::{GetCurrentProcess, OpenProcessToken};
+    use windows::core::PWSTR;
+    unsafe {
+        let mut token: HANDLE = HANDLE::default();
+        OpenProcessToken(GetCurrentProcess(), TOKEN_QUERY, &mut token)?;
+        let mut size = 0;
+        let _ = GetTokenInformation(token, TokenUser, None, 0, &mut size);
+        // 按 8 字节对齐，TOKEN_USER 中有指针
+        let mut buffer = vec![0u64; (size as usize).div_ceil(8)];
+        let result = GetTokenInformation(
+            token,
+            TokenUser,
+            Some(buffer.as_mut_ptr().cast()),
+            size,
+            &mut size,
+        );
+        let _ = CloseHandle(token);
+        result?;
+        let user = &*(buffer.as_ptr() as *const TOKEN_USER);
+        let mut sid = PWSTR::null();
+        ConvertSidToStringSidW(user.User.Sid, &mut sid)?;
+        let s = sid.to_string();
+        let _ = LocalFree(Some(HLOCAL(sid.0.cast())));
+        Ok(s?)
+    }
+}
@@ -1,12 +1,18 @@
-use crate::utils::parse_header_hashmap;
+use crate::{
+    persist::Priority,
+    utils::{
+        Category, Checksum, LogErr, PostAction, ScheduleRule, parse_header_hashmap,
+        parse_post_actions,
+    },
+};
 use fast_down_ffi::{Proxy, WriteMethod};
 use itertools::Itertools;
 use serde::{Deserialize, Serialize};
-use slint::ToSharedString;
+use slint::{SharedString, ToSharedString};
 use std::{
     collections::{HashMap, HashSet},
-    net::IpAddr,
-    path::PathBuf,
+    net::{IpAddr, Ipv4Addr},
+    path::{Path, PathBuf},
     time::Duration,
 };
 
@@ -30,7 +36,17 @@ pub struct DownloadConfig {
     pub retry_times: usize,
     pub chunk_window: u64,
     pub pre_allocate: bool,
+    /// 下载时写入 `.fdpart` 临时文件，完成后再重命名
+    pub part_file: bool,
     pub parse_filename: bool,
+    /// 下载完成后用于校验的摘要
+    pub checksum: Option<Checksum>,
+    /// 单任务限速，字节每秒，0 为不限速
+    pub speed_limit: u64,
+    /// 预期的文件大小，与服务器返回的不一致时停止下载
+    pub expected_size: Option<u64>,
+    /// 任务单独的完成后操作，在全局操作之后执行
+    pub post_actions: Vec<PostAction>,
 }
 
 impl Default for DownloadConfig {
@@ -54,7 +70,12 @@ impl Default for DownloadConfig {
             retry_times: 3,
             chunk_window: 8 * 1024,
             pre_allocate: false,
+            part_file: true,
             parse_filename: false,
+            checksum: None,
+            speed_limit: 0,
+            expected_size: None,
+            post_actions: Vec::new(),
         }
     }
 }
@@ -97,8 +118,23 @@ impl DownloadConfig {
             retry_times: self.retry_times as i32,
             chunk_window: self.chunk_window as i32,
             pre_allocate: self.pre_allocate,
+            part_file: self.part_file,
             file_name: self.file_name.to_shared_string(),
             parse_filename: self.parse_filename,
+            checksum: self
+                .checksum
+                .as_ref()
+                .map(|c| c.to_shared_string())
+                .unwrap_or_default(),
+            speed_limit: (self.speed_limit / 1024) as i32,
+            start_at: SharedString::new(),
+            priority: Priority::Normal.to_ui(),
+            as_mirrors: false,
+            expected_size: self
+                .expected_size
+                .map(|s| s.to_shared_string())
+                .unwrap_or_default(),
+            post_actions: self.post_actions.iter().join("\n").into(),
         }
     }
 }
@@ -136,11 +172,27 @@ impl From<&crate::ui::DownloadConfig> for DownloadConfig {
             retry_times: value.retry_times as usize,
             chunk_window: value.chunk_window as u64,
             pre_allocate: value.pre_allocate,
+            part_file: value.part_file,
             parse_filename: value.parse_filename,
+            checksum: match value.checksum.trim() {
+                "" => None,
+                checksum => checksum.parse().log_warn("无法解析校验值").ok(),
+            },
+            speed_limit: value.speed_limit.max(0) as u64 * 1024,
+            expected_size: match value.expected_size.trim() {
+                "" => None,
+                size => size.parse().log_warn("无法解析文件大小").ok(),
+            },
+            post_actions: parse_post_actions(&value.post_actions),
         }
     }
 }
 
+pub const DEFAULT_MIN_FREE_SPACE: u64 = 512 * 1024 * 1024;
+pub const DEFAULT_HOST_CONNECTIONS: usize = 32;
+pub const DEFAULT_RPC_PORT: u16 = 6800;
+pub const DEFAULT_API_PORT: u16 = 6801;
+
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct GeneralConfig {
     pub max_concurrency: usize,
@@ -149,6 +201,42 @@ pub struct GeneralConfig {
     pub ask_before_download: bool,
     pub skip_headers: HashSet<String>,
     pub run_as_admin: bool,
+    /// 全局限速，字节每秒，0 为不限速
+    pub speed_limit: u64,
+    /// 计划规则，按顺序匹配
+    pub schedule: Vec<ScheduleRule>,
+    /// 启动时恢复上次未完成的任务
+    pub auto_resume: bool,
+    pub retry: RetryPolicy,
+    /// 继续下载时发现服务器上的文件已改变的处理方式
+    pub file_changed: FileChangedPolicy,
+    /// 磁盘剩余空间低于该值时暂停任务，字节，0 为不检查
+    pub min_free_space: u64,
+    /// 所有任务对同一主机的连接数之和的上限，0 为不限制
+    pub host_connections: usize,
+    /// 单独设置的主机连接数，同时匹配子域名
+    pub host_overrides: HashMap<String, usize>,
+    /// 按文件类型保存到不同的文件夹
+    pub categorize: bool,
+    pub category_dirs: CategoryDirs,
+    /// 所有任务下载完成后依次执行的操作
+    pub post_actions: Vec<PostAction>,
+    /// 下载完成后自动解压压缩包
+    pub auto_extract: bool,
+    /// 解压成功后删除压缩包
+    pub delete_archive: bool,
+    /// 在本机开启兼容 aria2 的 JSON-RPC
+    pub rpc_enabled: bool,
+    pub rpc_port: u16,
+    /// RPC 密钥，为空时不开启 RPC
+    pub rpc_secret: String,
+    /// 在本机开启 REST API
+    pub api_enabled: bool,
+    pub api_port: u16,
+    /// REST API 的 Bearer 令牌，为空时不开启，也是网页界面的密码
+    pub api_token: String,
+    /// REST API 和网页界面监听的地址，只能是本机地址
+    pub api_host: IpAddr,
 }
 
 impl Default for GeneralConfig {
@@ -160,6 +248,26 @@ impl Default for GeneralConfig {
             ask_before_download: false,
             skip_headers: HashSet::new(),
             run_as_admin: false,
+            speed_limit: 0,
+            schedule: Vec::new(),
+            auto_resume: true,
+            retry: RetryPolicy::default(),
+            file_changed: FileChangedPolicy::default(),
+            min_free_space: DEFAULT_MIN_FREE_SPACE,
+            host_connections: DEFAULT_HOST_CONNECTIONS,
+            host_overrides: HashMap::new(),
+            categorize: false,
+            category_dirs: CategoryDirs::default(),
+            post_actions: Vec::new(),
+            auto_extract: false,
+            delete_archive: false,
+            rpc_enabled: false,
+            rpc_port: DEFAULT_RPC_PORT,
+            rpc_secret: String::new(),
+            api_enabled: false,
+            api_port: DEFAULT_API_PORT,
+            api_token: String::new(),
+            api_host: Ipv4Addr::LOCALHOST.into(),
         }
     }
 }
@@ -178,6 +286,59 @@ impl From<&crate::ui::GeneralConfig> for GeneralConfig {
                 .filter(|s| !s.is_empty())
                 .collect(),
             run_as_admin: value.run_as_admin,
+            speed_limit: value.speed_limit.max(0) as u64 * 1024,
+            schedule: value
+                .schedule
+                .lines()
+                .map(str::trim)
+                .filter(|s| !s.is_empty())
+                .filter_map(|s| s.parse().log_warn("无法解析计划规则").ok())
+                .collect(),
+            auto_resume: value.auto_resume,
+            retry: RetryPolicy {
+                max_attempts: value.retry_max_attempts.max(0) as u32,
+                base_delay: Duration::from_secs(value.retry_base_delay_s.max(0) as u64),
+                max_delay: Duration::from_secs(value.retry_max_delay_s.max(0) as u64),
+                retry_network: value.retry_network,
+                retry_disk: value.retry_disk,
+                retry_other: value.retry_other,
+            },
+            file_changed: FileChangedPolicy::from_ui(value.file_changed),
+            min_free_space: value.min_free_space.max(0) as u64 * 1024 * 1024,
+            host_connections: value.host_connections.max(0) as usize,
+            host_overrides: value
+                .host_overrides
+                .lines()
+                .map(str::trim)
+                .filter(|s| !s.is_empty())
+                .filter_map(|s| {
+                    let (host, n) = s.split_once('=')?;
+                    let n = n.trim().parse().log_warn("无法解析主机连接数").ok()?;
+                    Some((host.trim().to_lowercase(), n))
+                })
+                .collect(),
+            categorize: value.categorize,
+            category_dirs: CategoryDirs {
+                video: value.category_video.as_str().into(),
+                archive: value.category_archive.as_str().into(),
+                document: value.category_document.as_str().into(),
+                image: value.category_image.as_str().into(),
+                program: value.category_program.as_str().into(),
+            },
+            post_actions: parse_post_actions(&value.post_actions),
+            auto_extract: value.auto_extract,
+            delete_archive: value.delete_archive,
+            rpc_enabled: value.rpc_enabled,
+            rpc_port: value.rpc_port.clamp(1, u16::MAX as i32) as u16,
+            rpc_secret: value.rpc_secret.trim().to_string(),
+            api_enabled: value.api_enabled,
+            api_port: value.api_port.clamp(1, u16::MAX as i32) as u16,
+            api_token: value.api_token.trim().to_string(),
+            api_host: value
+                .api_host
+                .trim()
+                .parse()
+                .unwrap_or(Ipv4Addr::LOCALHOST.into()),
         }
     }
 }
@@ -191,6 +352,151 @@ impl GeneralConfig {
             ask_before_download: self.ask_before_download,
             skip_headers: self.skip_headers.iter().join("\n").into(),
             run_as_admin: self.run_as_admin,
+            speed_limit: (self.speed_limit / 1024) as i32,
+            schedule: self.schedule.iter().join("\n").into(),
+            auto_resume: self.auto_resume,
+            retry_max_attempts: self.retry.max_attempts as i32,
+            retry_base_delay_s: self.retry.base_delay.as_secs() as i32,
+            retry_max_delay_s: self.retry.max_delay.as_secs() as i32,
+            retry_network: self.retry.retry_network,
+            retry_disk: self.retry.retry_disk,
+            retry_other: self.retry.retry_other,
+            file_changed: self.file_changed.to_ui(),
+            min_free_space: (self.min_free_space / 1024 / 1024) as i32,
+            host_connections: self.host_connections as i32,
+            host_overrides: self
+                .host_overrides
+                .iter()
+                .map(|(host, n)| format!("{host} = {n}"))
+                .sorted()
+                .join("\n")
+                .into(),
+            categorize: self.categorize,
+            category_video: self.category_dirs.video.to_string_lossy().as_ref().into(),
+            category_archive: self.category_dirs.archive.to_string_lossy().as_ref().into(),
+            category_document: self
+                .category_dirs
+                .document
+                .to_string_lossy()
+                .as_ref()
+                .into(),
+            category_image: self.category_dirs.image.to_string_lossy().as_ref().into(),
+            category_program: self.category_dirs.program.to_string_lossy().as_ref().into(),
+            post_actions: self.post_actions.iter().join("\n").into(),
+            auto_extract: self.auto_extract,
+            delete_archive: self.delete_archive,
+            rpc_enabled: self.rpc_enabled,
+            rpc_port: self.rpc_port as i32,
+            rpc_secret: self.rpc_secret.as_str().into(),
+            api_enabled: self.api_enabled,
+            api_port: self.api_port as i32,
+            api_token: self.api_token.as_str().into(),
+            api_host: self.api_host.to_shared_string(),
+        }
+    }
+}
+
+/// 各类文件保存的文件夹，相对路径基于任务的保存文件夹
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct CategoryDirs {
+    pub video: PathBuf,
+    pub archive: PathBuf,
+    pub document: PathBuf,
+    pub image: PathBuf,
+    pub program: PathBuf,
+}
+
+impl Default for CategoryDirs {
+    fn default() -> Self {
+        Self {
+            video: "视频".into(),
+            archive: "压缩包".into(),
+            document: "文档".into(),
+            image: "图片".into(),
+            program: "程序".into(),
         }
     }
 }
+
+impl CategoryDirs {
+    pub fn get(&self, category: Category) -> &Path {
+        match category {
+            Category::Video => &self.video,
+            Category::Archive => &self.archive,
+            Category::Document => &self.document,
+            Category::Image => &self.image,
+            Category::Program => &self.program,
+        }
+    }
+}
+
+/// 服务器上的文件和已下载的部分不一致时的处理方式
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum FileChangedPolicy {
+    /// 弹窗询问用户
+    #[default]
+    Ask,
+    /// 删除已下载的部分，从头下载
+    Restart,
+    /// 保留已下载的部分继续下载
+    Keep,
+    /// 报错并停止任务
+    Fail,
+}
+
+impl FileChangedPolicy {
+    pub fn from_ui(value: i32) -> Self {
+        match value {
+            1 => FileChangedPolicy::Restart,
+            2 => FileChangedPolicy::Keep,
+            3 => FileChangedPolicy::Fail,
+            _ => FileChangedPolicy::Ask,
+        }
+    }
+
+    pub fn to_ui(self) -> i32 {
+        match self {
+            FileChangedPolicy::Ask => 0,
+            FileChangedPolicy::Restart => 1,
+            FileChangedPolicy::Keep => 2,
+            FileChangedPolicy::Fail => 3,
+        }
+    }
+}
+
+/// 任务出错后的自动重试策略
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RetryPolicy {
+    /// 最多重试次数，0 为不重试
+    pub max_attempts: u32,
+    /// 第一次重试前的等待时间，之后每次翻倍
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+    /// 重试网络错误
+    pub retry_network: bool,
+    /// 重试磁盘读写错误
+    pub retry_disk: bool,
+    /// 重试其他错误
+    pub retry_other: bool,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 5,
+            base_delay: Duration::from_secs(10),
+            max_delay: Duration::from_secs(10 * 60),
+            retry_network: true,
+            retry_disk: false,
+            retry_other: false,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// 第 attempt 次重试前的等待时间，从 1 开始
+    pub fn delay(&self, attempt: u32) -> Duration {
+        let factor = 2u32.saturating_pow(attempt.saturating_sub(1));
+        self.base_delay.saturating_mul(factor).min(self.max_delay)
+    }
+}
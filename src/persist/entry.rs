@@ -6,7 +6,12 @@ use crate::{
 use fast_down_ffi::{FileId, Total};
 use serde::{Deserialize, Serialize};
 use slint::{SharedString, VecModel};
-use std::{ops::Range, path::PathBuf, rc::Rc, time::Duration};
+use std::{
+    ops::Range,
+    path::PathBuf,
+    rc::Rc,
+    time::{Duration, SystemTime},
+};
 use url::Url;
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
@@ -20,6 +25,19 @@ pub struct DatabaseEntry {
     pub url: Url,
     pub config: DownloadConfig,
     pub status: Status,
+    /// 校验结果，未设置校验值时为 None
+    pub verified: Option<bool>,
+    /// 定时开始的时间
+    pub start_at: Option<SystemTime>,
+    pub priority: Priority,
+    /// 连续失败后自动重试的次数
+    pub attempts: u32,
+    /// 最近一次出错的类型
+    pub error_kind: Option<ErrorKind>,
+    /// 与 url 相同文件的镜像地址
+    pub mirrors: Vec<Url>,
+    /// 最近一次完成后操作的结果
+    pub action_result: Option<ActionResult>,
 }
 
 impl DatabaseEntry {
@@ -54,18 +72,132 @@ impl DatabaseEntry {
             status: match self.status {
                 Status::Completed => crate::ui::Status::Completed,
                 Status::Error => crate::ui::Status::Error,
-                Status::Paused => crate::ui::Status::Paused,
+                // 刚加载时任务都没有在运行，是否恢复由启动流程决定
+                Status::Paused | Status::Waiting | Status::Running => crate::ui::Status::Paused,
             },
             time: format_time(elapsed.as_secs()).into(),
             total: format_size(file_size as f64).into(),
-            error: SharedString::new(),
+            error: match (&self.status, &self.error_kind) {
+                (Status::Error | Status::Paused, Some(kind)) => kind.message().into(),
+                _ => SharedString::new(),
+            },
+            hint: match (&self.status, &self.error_kind) {
+                (Status::Error | Status::Paused, Some(kind)) => kind.suggestion().into(),
+                _ => SharedString::new(),
+            },
+            action_result: self
+                .action_result
+                .as_ref()
+                .map(|r| r.message.as_str().into())
+                .unwrap_or_default(),
+            action_failed: self.action_result.as_ref().is_some_and(|r| r.failed),
         }
     }
 }
 
+/// 完成后操作的执行结果，失败时任务仍然是已完成状态
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ActionResult {
+    pub failed: bool,
+    pub message: String,
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub enum Status {
     Completed,
     Error,
     Paused,
+    /// 在队列中等待，下次启动时会恢复
+    Waiting,
+    /// 正在下载，下次启动时会恢复
+    Running,
+}
+
+impl Status {
+    /// 下次启动时是否需要恢复
+    pub fn is_active(&self) -> bool {
+        matches!(self, Status::Waiting | Status::Running)
+    }
+}
+
+/// 排队优先级，优先级高的任务排在队列前面
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Priority {
+    Low,
+    #[default]
+    Normal,
+    High,
+}
+
+impl Priority {
+    pub fn from_ui(value: i32) -> Self {
+        match value {
+            0 => Priority::High,
+            2 => Priority::Low,
+            _ => Priority::Normal,
+        }
+    }
+
+    pub fn to_ui(self) -> i32 {
+        match self {
+            Priority::High => 0,
+            Priority::Normal => 1,
+            Priority::Low => 2,
+        }
+    }
+}
+
+/// 下载出错的类型，用于展示错误提示和决定是否重试
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorKind {
+    /// 服务器返回了错误的状态码
+    Http(u16),
+    Network,
+    DiskFull,
+    PermissionDenied,
+    /// 服务器上的文件和已下载的部分不一致
+    FileChanged,
+    ChecksumMismatch,
+    Io,
+    Cancelled,
+    Other,
+}
+
+impl ErrorKind {
+    pub fn message(&self) -> String {
+        match self {
+            ErrorKind::Http(code) => format!("服务器返回错误状态码 {code}"),
+            ErrorKind::Network => "网络连接失败".into(),
+            ErrorKind::DiskFull => "磁盘空间不足".into(),
+            ErrorKind::PermissionDenied => "没有写入权限".into(),
+            ErrorKind::FileChanged => "服务器上的文件已改变".into(),
+            ErrorKind::ChecksumMismatch => "文件校验失败".into(),
+            ErrorKind::Io => "读写文件失败".into(),
+            ErrorKind::Cancelled => "任务已取消".into(),
+            ErrorKind::Other => "下载出错".into(),
+        }
+    }
+
+    /// 给用户的解决建议
+    pub fn suggestion(&self) -> &'static str {
+        match self {
+            ErrorKind::Http(401 | 403) => {
+                "链接可能需要登录或已过期，请在请求头中填写 Cookie，或重新获取下载链接"
+            }
+            ErrorKind::Http(404 | 410) => "文件已不存在，请检查链接是否正确",
+            ErrorKind::Http(416) => "服务器不支持从当前位置继续下载，请移除任务后重新下载",
+            ErrorKind::Http(429) => "请求过于频繁，请减少线程数或稍后重试",
+            ErrorKind::Http(500..) => "服务器暂时出错，请稍后重试",
+            ErrorKind::Http(_) => "请检查链接是否正确",
+            ErrorKind::Network => "请检查网络连接和代理设置，或稍后重试",
+            ErrorKind::DiskFull => "请清理磁盘空间，或更换保存文件夹后重试",
+            ErrorKind::PermissionDenied => "请更换保存文件夹，或以管理员身份运行",
+            ErrorKind::FileChanged => {
+                "已下载的部分已失效，请重新下载，或在设置中修改服务器文件改变时的处理方式"
+            }
+            ErrorKind::ChecksumMismatch => "文件可能已损坏或被篡改，请确认校验值后重新下载",
+            ErrorKind::Io => "请检查保存文件夹是否可用",
+            ErrorKind::Cancelled | ErrorKind::Other => "",
+        }
+    }
 }
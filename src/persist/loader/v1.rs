@@ -57,6 +57,8 @@ impl From<Config> for crate::persist::DownloadConfig {
             write_method: c.write_method,
             pre_allocate: false,
             parse_filename: false,
+            part_file: false,
+            ..Default::default()
         }
     }
 }
@@ -86,6 +88,13 @@ impl From<DatabaseEntry> for crate::persist::DatabaseEntry {
             url: e.url,
             config: e.config.into(),
             status: e.status.into(),
+            verified: None,
+            start_at: None,
+            priority: crate::persist::Priority::Normal,
+            attempts: 0,
+            error_kind: None,
+            mirrors: Vec::new(),
+            action_result: None,
         }
     }
 }
@@ -126,9 +135,11 @@ impl From<DatabaseInner> for crate::persist::DatabaseInner {
                 ask_before_download: false,
                 skip_headers: HashSet::new(),
                 run_as_admin: false,
+                ..Default::default()
             }),
             download_config: Mutex::new(db.config.into_inner().into()),
             max_gid: db.max_gid,
+            ..Default::default()
         }
     }
 }
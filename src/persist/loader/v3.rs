@@ -60,6 +60,8 @@ impl From<DownloadConfig> for crate::persist::DownloadConfig {
             write_method: c.write_method,
             pre_allocate: false,
             parse_filename: false,
+            part_file: false,
+            ..Default::default()
         }
     }
 }
@@ -73,6 +75,7 @@ impl From<GeneralConfig> for crate::persist::GeneralConfig {
             ask_before_download: false,
             skip_headers: HashSet::new(),
             run_as_admin: false,
+            ..Default::default()
         }
     }
 }
@@ -102,6 +105,13 @@ impl From<DatabaseEntry> for crate::persist::DatabaseEntry {
             url: e.url,
             config: e.config.into(),
             status: e.status.into(),
+            verified: None,
+            start_at: None,
+            priority: crate::persist::Priority::Normal,
+            attempts: 0,
+            error_kind: None,
+            mirrors: Vec::new(),
+            action_result: None,
         }
     }
 }
@@ -138,6 +148,7 @@ impl From<DatabaseInner> for crate::persist::DatabaseInner {
             download_config: Mutex::new(db.download_config.into_inner().into()),
             general_config: Mutex::new(db.general_config.into_inner().into()),
             max_gid: db.max_gid,
+            ..Default::default()
         }
     }
 }
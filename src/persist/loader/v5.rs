@@ -63,6 +63,8 @@ impl From<DownloadConfig> for crate::persist::DownloadConfig {
             write_method: c.write_method,
             pre_allocate: c.pre_allocate,
             parse_filename: false,
+            part_file: false,
+            ..Default::default()
         }
     }
 }
@@ -76,6 +78,7 @@ impl From<GeneralConfig> for crate::persist::GeneralConfig {
             ask_before_download: false,
             skip_headers: HashSet::new(),
             run_as_admin: false,
+            ..Default::default()
         }
     }
 }
@@ -105,6 +108,13 @@ impl From<DatabaseEntry> for crate::persist::DatabaseEntry {
             url: e.url,
             config: e.config.into(),
             status: e.status.into(),
+            verified: None,
+            start_at: None,
+            priority: crate::persist::Priority::Normal,
+            attempts: 0,
+            error_kind: None,
+            mirrors: Vec::new(),
+            action_result: None,
         }
     }
 }
@@ -141,6 +151,7 @@ impl From<DatabaseInner> for crate::persist::DatabaseInner {
             download_config: Mutex::new(db.download_config.into_inner().into()),
             general_config: Mutex::new(db.general_config.into_inner().into()),
             max_gid: db.max_gid,
+            ..Default::default()
         }
     }
 }
@@ -67,6 +67,8 @@ impl From<DownloadConfig> for crate::persist::DownloadConfig {
             write_method: c.write_method,
             pre_allocate: c.pre_allocate,
             parse_filename: c.parse_filename,
+            part_file: false,
+            ..Default::default()
         }
     }
 }
@@ -80,6 +82,7 @@ impl From<GeneralConfig> for crate::persist::GeneralConfig {
             ask_before_download: c.ask_before_download,
             skip_headers: c.skip_headers,
             run_as_admin: c.run_as_admin,
+            ..Default::default()
         }
     }
 }
@@ -109,6 +112,13 @@ impl From<DatabaseEntry> for crate::persist::DatabaseEntry {
             url: e.url,
             config: e.config.into(),
             status: e.status.into(),
+            verified: None,
+            start_at: None,
+            priority: crate::persist::Priority::Normal,
+            attempts: 0,
+            error_kind: None,
+            mirrors: Vec::new(),
+            action_result: None,
         }
     }
 }
@@ -145,6 +155,7 @@ impl From<DatabaseInner> for crate::persist::DatabaseInner {
             download_config: Mutex::new(db.download_config.into_inner().into()),
             general_config: Mutex::new(db.general_config.into_inner().into()),
             max_gid: db.max_gid,
+            ..Default::default()
         }
     }
 }
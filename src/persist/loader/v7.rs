@@ -0,0 +1,355 @@
+use crate::{
+    persist::{RetryPolicy, loader::Loader},
+    utils::{Checksum, ScheduleRule, SiteRule},
+};
+use dashmap::DashMap;
+use fast_down_ffi::{FileId, ProgressEntry, Proxy, WriteMethod};
+use parking_lot::Mutex;
+use serde::Deserialize;
+use std::{
+    collections::{HashMap, HashSet},
+    net::IpAddr,
+    path::PathBuf,
+    sync::atomic::AtomicI32,
+    time::{Duration, SystemTime},
+};
+use url::Url;
+
+#[derive(Deserialize, Debug)]
+pub struct DownloadConfig {
+    pub save_dir: PathBuf,
+    pub file_name: String,
+    pub threads: usize,
+    pub proxy: Proxy<String>,
+    pub headers: HashMap<String, String>,
+    pub min_chunk_size: u64,
+    pub write_buffer_size: usize,
+    pub write_queue_cap: usize,
+    pub retry_gap: Duration,
+    pub pull_timeout: Duration,
+    pub accept_invalid_certs: bool,
+    pub accept_invalid_hostnames: bool,
+    pub local_address: Vec<IpAddr>,
+    pub max_speculative: usize,
+    pub write_method: WriteMethod,
+    pub retry_times: usize,
+    pub chunk_window: u64,
+    pub pre_allocate: bool,
+    pub parse_filename: bool,
+    pub checksum: Option<Checksum>,
+    pub speed_limit: u64,
+    pub part_file: bool,
+    pub expected_size: Option<u64>,
+    pub post_actions: Vec<PostAction>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct GeneralConfig {
+    pub max_concurrency: usize,
+    pub auto_start: bool,
+    pub exit_after_download: bool,
+    pub ask_before_download: bool,
+    pub skip_headers: HashSet<String>,
+    pub run_as_admin: bool,
+    pub speed_limit: u64,
+    pub schedule: Vec<ScheduleRule>,
+    pub auto_resume: bool,
+    pub retry: RetryPolicy,
+    pub file_changed: FileChangedPolicy,
+    pub min_free_space: u64,
+    pub host_connections: usize,
+    pub host_overrides: HashMap<String, usize>,
+    pub categorize: bool,
+    pub category_dirs: CategoryDirs,
+    pub post_actions: Vec<PostAction>,
+    pub auto_extract: bool,
+    pub delete_archive: bool,
+    pub rpc_enabled: bool,
+    pub rpc_port: u16,
+    pub rpc_secret: String,
+    pub api_enabled: bool,
+    pub api_port: u16,
+    pub api_token: String,
+    pub api_host: IpAddr,
+}
+
+impl From<DownloadConfig> for crate::persist::DownloadConfig {
+    fn from(c: DownloadConfig) -> Self {
+        Self {
+            file_name: c.file_name,
+            proxy: c.proxy,
+            retry_times: c.retry_times,
+            chunk_window: c.chunk_window,
+            save_dir: c.save_dir,
+            threads: c.threads,
+            headers: c.headers,
+            min_chunk_size: c.min_chunk_size,
+            write_buffer_size: c.write_buffer_size,
+            write_queue_cap: c.write_queue_cap,
+            retry_gap: c.retry_gap,
+            pull_timeout: c.pull_timeout,
+            accept_invalid_certs: c.accept_invalid_certs,
+            accept_invalid_hostnames: c.accept_invalid_hostnames,
+            local_address: c.local_address,
+            max_speculative: c.max_speculative,
+            write_method: c.write_method,
+            pre_allocate: c.pre_allocate,
+            parse_filename: c.parse_filename,
+            checksum: c.checksum,
+            speed_limit: c.speed_limit,
+            part_file: c.part_file,
+            expected_size: c.expected_size,
+            post_actions: c.post_actions.into_iter().map(Into::into).collect(),
+        }
+    }
+}
+
+impl From<GeneralConfig> for crate::persist::GeneralConfig {
+    fn from(c: GeneralConfig) -> Self {
+        Self {
+            max_concurrency: c.max_concurrency,
+            auto_start: c.auto_start,
+            exit_after_download: c.exit_after_download,
+            ask_before_download: c.ask_before_download,
+            skip_headers: c.skip_headers,
+            run_as_admin: c.run_as_admin,
+            speed_limit: c.speed_limit,
+            schedule: c.schedule,
+            auto_resume: c.auto_resume,
+            retry: c.retry,
+            file_changed: c.file_changed.into(),
+            min_free_space: c.min_free_space,
+            host_connections: c.host_connections,
+            host_overrides: c.host_overrides,
+            categorize: c.categorize,
+            category_dirs: c.category_dirs.into(),
+            post_actions: c.post_actions.into_iter().map(Into::into).collect(),
+            auto_extract: c.auto_extract,
+            delete_archive: c.delete_archive,
+            rpc_enabled: c.rpc_enabled,
+            rpc_port: c.rpc_port,
+            rpc_secret: c.rpc_secret,
+            api_enabled: c.api_enabled,
+            api_port: c.api_port,
+            api_token: c.api_token,
+            api_host: c.api_host,
+        }
+    }
+}
+
+#[derive(Deserialize, Debug)]
+pub struct DatabaseEntry {
+    pub file_name: String,
+    pub file_path: PathBuf,
+    pub file_size: u64,
+    pub file_id: FileId,
+    pub progress: Vec<ProgressEntry>,
+    pub elapsed: Duration,
+    pub url: Url,
+    pub config: DownloadConfig,
+    pub status: Status,
+    pub verified: Option<bool>,
+    pub start_at: Option<SystemTime>,
+    pub priority: Priority,
+    pub attempts: u32,
+    pub error_kind: Option<ErrorKind>,
+    pub mirrors: Vec<Url>,
+    pub action_result: Option<ActionResult>,
+}
+
+impl From<DatabaseEntry> for crate::persist::DatabaseEntry {
+    fn from(e: DatabaseEntry) -> Self {
+        Self {
+            file_name: e.file_name,
+            file_path: e.file_path,
+            file_size: e.file_size,
+            file_id: e.file_id,
+            progress: e.progress,
+            elapsed: e.elapsed,
+            url: e.url,
+            config: e.config.into(),
+            status: e.status.into(),
+            verified: e.verified,
+            start_at: e.start_at,
+            priority: e.priority.into(),
+            attempts: e.attempts,
+            error_kind: e.error_kind.map(Into::into),
+            mirrors: e.mirrors,
+            action_result: e.action_result.map(Into::into),
+        }
+    }
+}
+
+#[derive(Deserialize, Debug)]
+pub enum Status {
+    Completed,
+    Error,
+    Paused,
+    Waiting,
+    Running,
+}
+
+impl From<Status> for crate::persist::Status {
+    fn from(value: Status) -> Self {
+        match value {
+            Status::Completed => crate::persist::Status::Completed,
+            Status::Error => crate::persist::Status::Error,
+            Status::Paused => crate::persist::Status::Paused,
+            Status::Waiting => crate::persist::Status::Waiting,
+            Status::Running => crate::persist::Status::Running,
+        }
+    }
+}
+
+#[derive(Deserialize, Debug)]
+pub enum Priority {
+    Low,
+    Normal,
+    High,
+}
+
+impl From<Priority> for crate::persist::Priority {
+    fn from(value: Priority) -> Self {
+        match value {
+            Priority::Low => crate::persist::Priority::Low,
+            Priority::Normal => crate::persist::Priority::Normal,
+            Priority::High => crate::persist::Priority::High,
+        }
+    }
+}
+
+#[derive(Deserialize, Debug)]
+pub enum FileChangedPolicy {
+    Ask,
+    Restart,
+    Keep,
+    Fail,
+}
+
+impl From<FileChangedPolicy> for crate::persist::FileChangedPolicy {
+    fn from(value: FileChangedPolicy) -> Self {
+        match value {
+            FileChangedPolicy::Ask => crate::persist::FileChangedPolicy::Ask,
+            FileChangedPolicy::Restart => crate::persist::FileChangedPolicy::Restart,
+            FileChangedPolicy::Keep => crate::persist::FileChangedPolicy::Keep,
+            FileChangedPolicy::Fail => crate::persist::FileChangedPolicy::Fail,
+        }
+    }
+}
+
+#[derive(Deserialize, Debug)]
+pub struct CategoryDirs {
+    pub video: PathBuf,
+    pub archive: PathBuf,
+    pub document: PathBuf,
+    pub image: PathBuf,
+    pub program: PathBuf,
+}
+
+impl From<CategoryDirs> for crate::persist::CategoryDirs {
+    fn from(value: CategoryDirs) -> Self {
+        Self {
+            video: value.video,
+            archive: value.archive,
+            document: value.document,
+            image: value.image,
+            program: value.program,
+        }
+    }
+}
+
+#[derive(Deserialize, Debug)]
+pub enum PostAction {
+    Run(String),
+    Move(PathBuf),
+    Open,
+    Extract,
+}
+
+impl From<PostAction> for crate::utils::PostAction {
+    fn from(value: PostAction) -> Self {
+        match value {
+            PostAction::Run(cmd) => crate::utils::PostAction::Run(cmd),
+            PostAction::Move(dir) => crate::utils::PostAction::Move(dir),
+            PostAction::Open => crate::utils::PostAction::Open,
+            PostAction::Extract => crate::utils::PostAction::Extract,
+        }
+    }
+}
+
+#[derive(Deserialize, Debug)]
+pub struct ActionResult {
+    pub failed: bool,
+    pub message: String,
+}
+
+impl From<ActionResult> for crate::persist::ActionResult {
+    fn from(value: ActionResult) -> Self {
+        Self {
+            failed: value.failed,
+            message: value.message,
+        }
+    }
+}
+
+#[derive(Deserialize, Debug)]
+pub enum ErrorKind {
+    Http(u16),
+    Network,
+    DiskFull,
+    PermissionDenied,
+    FileChanged,
+    ChecksumMismatch,
+    Io,
+    Cancelled,
+    Other,
+}
+
+impl From<ErrorKind> for crate::persist::ErrorKind {
+    fn from(value: ErrorKind) -> Self {
+        match value {
+            ErrorKind::Http(code) => crate::persist::ErrorKind::Http(code),
+            ErrorKind::Network => crate::persist::ErrorKind::Network,
+            ErrorKind::DiskFull => crate::persist::ErrorKind::DiskFull,
+            ErrorKind::PermissionDenied => crate::persist::ErrorKind::PermissionDenied,
+            ErrorKind::FileChanged => crate::persist::ErrorKind::FileChanged,
+            ErrorKind::ChecksumMismatch => crate::persist::ErrorKind::ChecksumMismatch,
+            ErrorKind::Io => crate::persist::ErrorKind::Io,
+            ErrorKind::Cancelled => crate::persist::ErrorKind::Cancelled,
+            ErrorKind::Other => crate::persist::ErrorKind::Other,
+        }
+    }
+}
+
+#[derive(Deserialize, Debug)]
+pub struct DatabaseInner {
+    pub data: DashMap<i32, DatabaseEntry>,
+    pub download_config: Mutex<DownloadConfig>,
+    pub general_config: Mutex<GeneralConfig>,
+    pub max_gid: AtomicI32,
+    pub queue: Mutex<Vec<i32>>,
+    pub site_rules: Mutex<Vec<SiteRule>>,
+}
+
+impl From<DatabaseInner> for crate::persist::DatabaseInner {
+    fn from(db: DatabaseInner) -> Self {
+        Self {
+            data: db.data.into_iter().map(|(k, v)| (k, v.into())).collect(),
+            download_config: Mutex::new(db.download_config.into_inner().into()),
+            general_config: Mutex::new(db.general_config.into_inner().into()),
+            max_gid: db.max_gid,
+            queue: db.queue,
+            site_rules: db.site_rules,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct V7Loader;
+
+impl Loader for V7Loader {
+    fn load(&self, bytes: &[u8]) -> Option<crate::persist::DatabaseInner> {
+        let db: DatabaseInner = bitcode::deserialize(bytes).ok()?;
+        Some(db.into())
+    }
+}
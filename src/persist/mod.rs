@@ -5,7 +5,10 @@ mod loader;
 pub use config::*;
 pub use entry::*;
 
-use crate::persist::loader::{BoxLoader, Loader};
+use crate::{
+    persist::loader::{BoxLoader, Loader},
+    utils::{SiteRule, apply_site_rules},
+};
 use color_eyre::Result;
 use dashmap::DashMap;
 use parking_lot::Mutex;
@@ -40,6 +43,10 @@ pub struct DatabaseInner {
     pub download_config: Mutex<DownloadConfig>,
     pub general_config: Mutex<GeneralConfig>,
     pub max_gid: AtomicI32,
+    /// 等待队列的顺序
+    pub queue: Mutex<Vec<i32>>,
+    /// 站点规则，按顺序应用
+    pub site_rules: Mutex<Vec<SiteRule>>,
 }
 
 impl DatabaseInner {
@@ -72,6 +79,10 @@ impl DatabaseInner {
     pub fn is_ask_before_download(&self) -> bool {
         self.general_config.lock().ask_before_download
     }
+
+    pub fn is_auto_resume(&self) -> bool {
+        self.general_config.lock().auto_resume
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -158,6 +169,25 @@ impl Database {
         self.inner.next_gid()
     }
 
+    pub fn get_ui_site_rules(&self) -> Vec<crate::ui::SiteRuleData> {
+        self.inner
+            .site_rules
+            .lock()
+            .iter()
+            .map(SiteRule::to_ui)
+            .collect()
+    }
+
+    pub fn set_site_rules(&self, rules: Vec<SiteRule>) {
+        *self.inner.site_rules.lock() = rules;
+        self.is_dirty.store(true, Ordering::Relaxed);
+    }
+
+    /// 用匹配该链接的站点规则覆盖任务设置
+    pub fn apply_site_rules(&self, url: &url::Url, config: &mut crate::ui::DownloadConfig) {
+        apply_site_rules(&self.inner.site_rules.lock(), url, config);
+    }
+
     pub fn init_entry(&self, gid: i32, entry: DatabaseEntry) -> Result<()> {
         self.inner.data.insert(gid, entry);
         self.is_dirty.store(true, Ordering::Relaxed);
@@ -179,6 +209,77 @@ impl Database {
         }
     }
 
+    pub fn update_verified(&self, gid: i32, verified: bool) {
+        if let Some(mut e) = self.inner.data.get_mut(&gid) {
+            e.verified = Some(verified);
+            self.is_dirty.store(true, Ordering::Relaxed);
+        }
+    }
+
+    /// 临时文件重命名后更新保存路径
+    pub fn update_file_path(&self, gid: i32, file_path: PathBuf) {
+        if let Some(mut e) = self.inner.data.get_mut(&gid) {
+            if let Some(file_name) = file_path.file_name() {
+                e.file_name = file_name.to_string_lossy().to_string();
+            }
+            e.file_path = file_path;
+            self.is_dirty.store(true, Ordering::Relaxed);
+        }
+    }
+
+    pub fn update_attempts(&self, gid: i32, attempts: u32) {
+        if let Some(mut e) = self.inner.data.get_mut(&gid) {
+            e.attempts = attempts;
+            self.is_dirty.store(true, Ordering::Relaxed);
+        }
+    }
+
+    pub fn update_error_kind(&self, gid: i32, error_kind: Option<ErrorKind>) {
+        if let Some(mut e) = self.inner.data.get_mut(&gid) {
+            e.error_kind = error_kind;
+            self.is_dirty.store(true, Ordering::Relaxed);
+        }
+    }
+
+    pub fn update_action_result(&self, gid: i32, action_result: Option<ActionResult>) {
+        if let Some(mut e) = self.inner.data.get_mut(&gid) {
+            e.action_result = action_result;
+            self.is_dirty.store(true, Ordering::Relaxed);
+        }
+    }
+
+    /// 修改任务的单任务限速，字节每秒
+    pub fn update_speed_limit(&self, gid: i32, speed_limit: u64) {
+        if let Some(mut e) = self.inner.data.get_mut(&gid) {
+            e.config.speed_limit = speed_limit;
+            self.is_dirty.store(true, Ordering::Relaxed);
+        }
+    }
+
+    pub fn update_priority(&self, gid: i32, priority: Priority) {
+        if let Some(mut e) = self.inner.data.get_mut(&gid) {
+            e.priority = priority;
+            self.is_dirty.store(true, Ordering::Relaxed);
+        }
+    }
+
+    pub fn set_queue(&self, queue: Vec<i32>) {
+        let mut old = self.inner.queue.lock();
+        if *old != queue {
+            *old = queue;
+            self.is_dirty.store(true, Ordering::Relaxed);
+        }
+    }
+
+    /// 按保存的队列顺序排序，不在队列中的排在后面
+    pub fn sort_by_queue<T>(&self, items: &mut [T], gid: impl Fn(&T) -> i32) {
+        let queue = self.inner.queue.lock();
+        items.sort_by_key(|item| {
+            let gid = gid(item);
+            queue.iter().position(|g| *g == gid).unwrap_or(usize::MAX)
+        });
+    }
+
     pub fn remove_entry(&self, gid: i32) -> Result<()> {
         self.inner.data.remove(&gid);
         self.is_dirty.store(true, Ordering::Relaxed);
@@ -210,6 +311,10 @@ impl Database {
     pub fn is_ask_before_download(&self) -> bool {
         self.inner.is_ask_before_download()
     }
+
+    pub fn is_auto_resume(&self) -> bool {
+        self.inner.is_auto_resume()
+    }
 }
 
 impl Drop for Database {
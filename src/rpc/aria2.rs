@@ -0,0 +1,718 @@
+use crate::{
+    core::{App, TaskEvent, start_entry_by_gid, start_new_entry},
+    ui::{self, EntryData, Status},
+    utils::secret_eq,
+};
+use fast_down_ffi::Total;
+use serde::{Deserialize, de::DeserializeOwned};
+use serde_json::{Map, Value, json};
+use slint::{Model, VecModel};
+use std::iter;
+use url::Url;
+
+pub const PARSE_ERROR: i64 = -32700;
+pub const INVALID_REQUEST: i64 = -32600;
+pub const METHOD_NOT_FOUND: i64 = -32601;
+pub const INVALID_PARAMS: i64 = -32602;
+
+const METHODS: &[&str] = &[
+    "aria2.addUri",
+    "aria2.remove",
+    "aria2.forceRemove",
+    "aria2.pause",
+    "aria2.pauseAll",
+    "aria2.forcePause",
+    "aria2.forcePauseAll",
+    "aria2.unpause",
+    "aria2.unpauseAll",
+    "aria2.tellStatus",
+    "aria2.tellActive",
+    "aria2.tellWaiting",
+    "aria2.tellStopped",
+    "aria2.getOption",
+    "aria2.changeOption",
+    "aria2.getGlobalOption",
+    "aria2.changeGlobalOption",
+    "aria2.getGlobalStat",
+    "aria2.purgeDownloadResult",
+    "aria2.removeDownloadResult",
+    "aria2.getVersion",
+    "system.multicall",
+    "system.listMethods",
+    "system.listNotifications",
+];
+
+const NOTIFICATIONS: &[&str] = &[
+    "aria2.onDownloadStart",
+    "aria2.onDownloadPause",
+    "aria2.onDownloadStop",
+    "aria2.onDownloadComplete",
+    "aria2.onDownloadError",
+];
+
+/// JSON-RPC 错误，与 aria2 一样，方法执行失败时 code 为 1
+#[derive(Debug, Clone, PartialEq)]
+pub struct RpcError {
+    pub code: i64,
+    pub message: String,
+}
+
+impl RpcError {
+    pub fn new(code: i64, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+        }
+    }
+
+    fn failed(message: impl Into<String>) -> Self {
+        Self::new(1, message)
+    }
+
+    fn invalid_params(message: impl Into<String>) -> Self {
+        Self::new(INVALID_PARAMS, message)
+    }
+
+    pub fn to_json(&self) -> Value {
+        json!({ "code": self.code, "message": self.message })
+    }
+}
+
+/// aria2 的 gid 为 16 位十六进制字符串
+pub fn format_gid(gid: i32) -> String {
+    format!("{:016x}", gid as u32)
+}
+
+pub fn parse_gid(s: &str) -> Option<i32> {
+    (s.len() <= 16)
+        .then(|| u32::from_str_radix(s, 16).ok())
+        .flatten()
+        .map(|n| n as i32)
+}
+
+/// 任务事件对应的 aria2 通知，没有对应通知时返回 None
+pub fn notification(event: TaskEvent) -> Option<Value> {
+    let (gid, method) = match event {
+        TaskEvent::Status { gid, status } => (
+            gid,
+            match status {
+                Status::Running => "aria2.onDownloadStart",
+                Status::Paused => "aria2.onDownloadPause",
+                Status::Completed => "aria2.onDownloadComplete",
+                Status::Error => "aria2.onDownloadError",
+                _ => return None,
+            },
+        ),
+        TaskEvent::Removed { gid } => (gid, "aria2.onDownloadStop"),
+        TaskEvent::Progress { .. } | TaskEvent::ConfigChanged => return None,
+    };
+    Some(json!({
+        "jsonrpc": "2.0",
+        "method": method,
+        "params": [{ "gid": format_gid(gid) }],
+    }))
+}
+
+/// 在 UI 线程执行一个方法，除 system.* 外第一个参数须为 `token:密钥`
+pub fn call(
+    app: &App,
+    list: &VecModel<EntryData>,
+    method: &str,
+    mut params: Vec<Value>,
+) -> Result<Value, RpcError> {
+    match method {
+        "system.listMethods" => return Ok(json!(METHODS)),
+        "system.listNotifications" => return Ok(json!(NOTIFICATIONS)),
+        "system.multicall" => return multicall(app, list, params),
+        _ => {}
+    }
+    check_token(&app.db.inner.general_config.lock().rpc_secret, &mut params)?;
+    let mut p = Params(params.into_iter());
+    match method {
+        "aria2.addUri" => {
+            let uris: Vec<String> = p.required("uris")?;
+            let options: Map<String, Value> = p.optional()?.unwrap_or_default();
+            add_uri(app, list, &uris, &options).map(|gid| json!(format_gid(gid)))
+        }
+        "aria2.tellStatus" => {
+            let gid = p.gid()?;
+            let keys: Vec<String> = p.optional()?.unwrap_or_default();
+            let row = find_row(list, gid)?;
+            status_json(app, &row, &keys).ok_or_else(|| not_found(gid))
+        }
+        "aria2.tellActive" => {
+            let keys: Vec<String> = p.optional()?.unwrap_or_default();
+            Ok(list_json(app, list, &["active"], 0, usize::MAX, &keys))
+        }
+        "aria2.tellWaiting" | "aria2.tellStopped" => {
+            let offset: i64 = p.required("offset")?;
+            let num: usize = p.required("num")?;
+            let keys: Vec<String> = p.optional()?.unwrap_or_default();
+            let statuses: &[&str] = if method == "aria2.tellWaiting" {
+                &["waiting", "paused"]
+            } else {
+                &["complete", "error"]
+            };
+            Ok(list_json(app, list, statuses, offset, num, &keys))
+        }
+        "aria2.pause" | "aria2.forcePause" => {
+            let gid = p.gid()?;
+            find_row(list, gid)?;
+            app.task_set.cancel_task(&gid);
+            Ok(json!(format_gid(gid)))
+        }
+        "aria2.pauseAll" | "aria2.forcePauseAll" => {
+            for row in list.iter() {
+                if matches!(aria2_status(row.status), "active" | "waiting") {
+                    app.task_set.cancel_task(&row.gid);
+                }
+            }
+            Ok(json!("OK"))
+        }
+        "aria2.unpause" => {
+            let gid = p.gid()?;
+            find_row(list, gid)?;
+            start_entry_by_gid(app, gid, list);
+            Ok(json!(format_gid(gid)))
+        }
+        "aria2.unpauseAll" => {
+            let gids = gids_where(list, |status| status == Status::Paused);
+            for gid in gids {
+                start_entry_by_gid(app, gid, list);
+            }
+            Ok(json!("OK"))
+        }
+        "aria2.remove" | "aria2.forceRemove" => {
+            let gid = p.gid()?;
+            if !app.remove_entry(gid, list) {
+                return Err(not_found(gid));
+            }
+            Ok(json!(format_gid(gid)))
+        }
+        "aria2.removeDownloadResult" => {
+            let gid = p.gid()?;
+            let row = find_row(list, gid)?;
+            if !matches!(aria2_status(row.status), "complete" | "error") {
+                return Err(RpcError::failed(format!(
+                    "任务 {} 没有结束",
+                    format_gid(gid)
+                )));
+            }
+            app.remove_entry(gid, list);
+            Ok(json!("OK"))
+        }
+        "aria2.purgeDownloadResult" => {
+            let gids = gids_where(list, |status| {
+                matches!(aria2_status(status), "complete" | "error")
+            });
+            for gid in gids {
+                app.remove_entry(gid, list);
+            }
+            Ok(json!("OK"))
+        }
+        "aria2.getOption" => {
+            let gid = p.gid()?;
+            let entry = app.db.inner.data.get(&gid).ok_or_else(|| not_found(gid))?;
+            Ok(json!({
+                "dir": entry.config.save_dir.to_string_lossy(),
+                "out": entry.file_name,
+                "split": entry.config.threads.to_string(),
+                "max-download-limit": entry.config.speed_limit.to_string(),
+            }))
+        }
+        "aria2.changeOption" => {
+            let gid = p.gid()?;
+            let options: Map<String, Value> = p.required("options")?;
+            find_row(list, gid)?;
+            if let Some(value) = options.get("max-download-limit") {
+                let limit = parse_size(&option_str(value)?)?;
+                app.db.update_speed_limit(gid, limit);
+                app.set_task_speed_limit(gid, limit);
+            }
+            Ok(json!("OK"))
+        }
+        "aria2.getGlobalOption" => {
+            let download_config = app.db.get_download_config();
+            let general_config = app.db.get_general_config();
+            Ok(json!({
+                "dir": download_config.save_dir.to_string_lossy(),
+                "split": download_config.threads.to_string(),
+                "max-concurrent-downloads": general_config.max_concurrency.to_string(),
+                "max-overall-download-limit": general_config.speed_limit.to_string(),
+            }))
+        }
+        "aria2.changeGlobalOption" => {
+            let options: Map<String, Value> = p.required("options")?;
+            change_global_option(app, &options)?;
+            Ok(json!("OK"))
+        }
+        "aria2.getGlobalStat" => Ok(global_stat(app, list)),
+        "aria2.getVersion" => Ok(json!({
+            "version": env!("CARGO_PKG_VERSION"),
+            "enabledFeatures": ["HTTPS"],
+        })),
+        _ => Err(RpcError::new(
+            METHOD_NOT_FOUND,
+            format!("不支持的方法：{method}"),
+        )),
+    }
+}
+
+fn check_token(secret: &str, params: &mut Vec<Value>) -> Result<(), RpcError> {
+    let token = params
+        .first()
+        .and_then(Value::as_str)
+        .and_then(|s| s.strip_prefix("token:"))
+        .map(str::to_string);
+    if let Some(token) = token {
+        params.remove(0);
+        if token_matches(secret, &token) {
+            return Ok(());
+        }
+    }
+    Err(RpcError::failed("Unauthorized"))
+}
+
+/// 密钥为空时不接受任何令牌
+fn token_matches(secret: &str, token: &str) -> bool {
+    !secret.is_empty() && secret_eq(token, secret)
+}
+
+/// 请求中是否带有正确的密钥，包括批量请求和 `system.multicall` 中的调用
+pub fn has_valid_token(secret: &str, body: &str) -> bool {
+    fn check(secret: &str, method: Option<&Value>, params: Option<&Value>) -> bool {
+        let first = params.and_then(|p| p.get(0));
+        if let Some(token) = first
+            .and_then(Value::as_str)
+            .and_then(|s| s.strip_prefix("token:"))
+        {
+            return token_matches(secret, token);
+        }
+        method.and_then(Value::as_str) == Some("system.multicall")
+            && first.and_then(Value::as_array).is_some_and(|calls| {
+                calls
+                    .iter()
+                    .any(|c| check(secret, c.get("methodName"), c.get("params")))
+            })
+    }
+    let requests = match serde_json::from_str::<Value>(body) {
+        Ok(Value::Array(requests)) => requests,
+        Ok(req) => vec![req],
+        Err(_) => return false,
+    };
+    requests
+        .iter()
+        .any(|req| check(secret, req.get("method"), req.get("params")))
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct MultiCall {
+    method_name: String,
+    #[serde(default)]
+    params: Vec<Value>,
+}
+
+/// 成功的结果包在数组中，失败时为错误对象
+fn multicall(app: &App, list: &VecModel<EntryData>, params: Vec<Value>) -> Result<Value, RpcError> {
+    let calls: Vec<MultiCall> = Params(params.into_iter()).required("calls")?;
+    Ok(calls
+        .into_iter()
+        .map(|c| {
+            let res = if c.method_name == "system.multicall" {
+                Err(RpcError::failed("不能嵌套调用 system.multicall"))
+            } else {
+                call(app, list, &c.method_name, c.params)
+            };
+            match res {
+                Ok(value) => json!([value]),
+                Err(e) => e.to_json(),
+            }
+        })
+        .collect())
+}
+
+struct Params(std::vec::IntoIter<Value>);
+
+impl Params {
+    fn optional<T: DeserializeOwned>(&mut self) -> Result<Option<T>, RpcError> {
+        match self.0.next() {
+            None | Some(Value::Null) => Ok(None),
+            Some(value) => serde_json::from_value(value)
+                .map(Some)
+                .map_err(|e| RpcError::invalid_params(format!("参数错误：{e}"))),
+        }
+    }
+
+    fn required<T: DeserializeOwned>(&mut self, name: &str) -> Result<T, RpcError> {
+        self.optional()?
+            .ok_or_else(|| RpcError::invalid_params(format!("缺少参数 {name}")))
+    }
+
+    fn gid(&mut self) -> Result<i32, RpcError> {
+        let gid: String = self.required("gid")?;
+        parse_gid(&gid).ok_or_else(|| RpcError::failed(format!("无效的 gid：{gid}")))
+    }
+}
+
+fn not_found(gid: i32) -> RpcError {
+    RpcError::failed(format!("GID {} is not found", format_gid(gid)))
+}
+
+fn find_row(list: &VecModel<EntryData>, gid: i32) -> Result<EntryData, RpcError> {
+    list.iter()
+        .find(|row| row.gid == gid)
+        .ok_or_else(|| not_found(gid))
+}
+
+fn gids_where(list: &VecModel<EntryData>, f: impl Fn(Status) -> bool) -> Vec<i32> {
+    list.iter()
+        .filter(|row| f(row.status))
+        .map(|row| row.gid)
+        .collect()
+}
+
+fn aria2_status(status: Status) -> &'static str {
+    match status {
+        Status::Running | Status::Verifying | Status::Extracting => "active",
+        Status::Waiting => "waiting",
+        Status::Paused => "paused",
+        Status::Completed => "complete",
+        Status::Error => "error",
+    }
+}
+
+/// 按 aria2 的 tellStatus 格式返回任务信息，数字都是字符串
+fn status_json(app: &App, row: &EntryData, keys: &[String]) -> Option<Value> {
+    let gid = row.gid;
+    let entry = app.db.inner.data.get(&gid)?;
+    let status = aria2_status(row.status);
+    let completed = entry.progress.total().to_string();
+    let total = entry.file_size.to_string();
+    let speed = app.speeds.get(&gid).map_or(0, |s| *s);
+    let connections = if status == "active" {
+        entry.config.threads
+    } else {
+        0
+    };
+    let dir = entry
+        .file_path
+        .parent()
+        .filter(|p| !p.as_os_str().is_empty())
+        .unwrap_or(&entry.config.save_dir);
+    let uris: Vec<Value> = iter::once(&entry.url)
+        .chain(&entry.mirrors)
+        .map(|url| json!({ "uri": url.as_str(), "status": "used" }))
+        .collect();
+    let mut value = json!({
+        "gid": format_gid(gid),
+        "status": status,
+        "totalLength": total,
+        "completedLength": completed,
+        "uploadLength": "0",
+        "downloadSpeed": speed.to_string(),
+        "uploadSpeed": "0",
+        "connections": connections.to_string(),
+        "dir": dir.to_string_lossy(),
+        "errorCode": if status == "error" { "1" } else { "0" },
+        "files": [{
+            "index": "1",
+            "path": entry.file_path.to_string_lossy(),
+            "length": total,
+            "completedLength": completed,
+            "selected": "true",
+            "uris": uris,
+        }],
+    });
+    if status == "error" {
+        value["errorMessage"] = row.error.as_str().into();
+    }
+    if !keys.is_empty()
+        && let Value::Object(map) = &mut value
+    {
+        map.retain(|k, _| keys.contains(k));
+    }
+    Some(value)
+}
+
+/// offset 为负数时从队尾开始倒序返回
+fn list_json(
+    app: &App,
+    list: &VecModel<EntryData>,
+    statuses: &[&str],
+    offset: i64,
+    num: usize,
+    keys: &[String],
+) -> Value {
+    let mut rows: Vec<_> = list
+        .iter()
+        .filter(|row| statuses.contains(&aria2_status(row.status)))
+        .collect();
+    app.db.sort_by_queue(&mut rows, |row| row.gid);
+    let rows: Vec<_> = if offset >= 0 {
+        rows.into_iter().skip(offset as usize).take(num).collect()
+    } else {
+        rows.into_iter()
+            .rev()
+            .skip(offset.unsigned_abs() as usize - 1)
+            .take(num)
+            .collect()
+    };
+    rows.iter()
+        .filter_map(|row| status_json(app, row, keys))
+        .collect()
+}
+
+fn global_stat(app: &App, list: &VecModel<EntryData>) -> Value {
+    let (mut active, mut waiting, mut stopped) = (0, 0, 0);
+    for row in list.iter() {
+        match aria2_status(row.status) {
+            "active" => active += 1,
+            "waiting" | "paused" => waiting += 1,
+            _ => stopped += 1,
+        }
+    }
+    let speed: u64 = app.speeds.iter().map(|s| *s).sum();
+    json!({
+        "downloadSpeed": speed.to_string(),
+        "uploadSpeed": "0",
+        "numActive": active.to_string(),
+        "numWaiting": waiting.to_string(),
+        "numStopped": stopped.to_string(),
+        "numStoppedTotal": stopped.to_string(),
+    })
+}
+
+/// 所有链接作为同一个文件的镜像
+fn add_uri(
+    app: &App,
+    list: &VecModel<EntryData>,
+    uris: &[String],
+    options: &Map<String, Value>,
+) -> Result<i32, RpcError> {
+    let mut urls = uris
+        .iter()
+        .map(|uri| {
+            Url::parse(uri)
+                .ok()
+                .filter(|url| matches!(url.scheme(), "http" | "https"))
+                .ok_or_else(|| RpcError::failed(format!("不支持的链接：{uri}")))
+        })
+        .collect::<Result<Vec<_>, _>>()?
+        .into_iter();
+    let url = urls
+        .next()
+        .ok_or_else(|| RpcError::invalid_params("缺少链接"))?;
+    let mut config = app.db.get_ui_download_config();
+    // 请求中的选项优先于站点规则
+    app.db.apply_site_rules(&url, &mut config);
+    apply_options(&mut config, options)?;
+    Ok(start_new_entry(app, url, urls.collect(), &config, list))
+}
+
+/// 把 aria2 的任务选项转为任务设置，不支持的选项会被忽略
+fn apply_options(
+    config: &mut ui::DownloadConfig,
+    options: &Map<String, Value>,
+) -> Result<(), RpcError> {
+    let mut headers: Vec<String> = config.headers.lines().map(str::to_string).collect();
+    for (key, value) in options {
+        match key.as_str() {
+            "dir" => config.save_dir = option_str(value)?.into(),
+            "out" => {
+                config.file_name = option_str(value)?.into();
+                config.parse_filename = false;
+            }
+            "header" => match value {
+                Value::Array(items) => {
+                    for item in items {
+                        headers.push(option_str(item)?);
+                    }
+                }
+                value => headers.push(option_str(value)?),
+            },
+            "user-agent" => headers.push(format!("User-Agent: {}", option_str(value)?)),
+            "referer" => headers.push(format!("Referer: {}", option_str(value)?)),
+            "checksum" => config.checksum = option_str(value)?.into(),
+            "all-proxy" => config.proxy = option_str(value)?.into(),
+            "split" => config.threads = parse_count(value)?.max(1),
+            "max-download-limit" => {
+                config.speed_limit = parse_kib(value)?;
+            }
+            _ => tracing::debug!(option = key, "忽略不支持的 aria2 选项"),
+        }
+    }
+    config.headers = headers.join("\n").into();
+    Ok(())
+}
+
+fn change_global_option(app: &App, options: &Map<String, Value>) -> Result<(), RpcError> {
+    let mut download_config = app.db.get_ui_download_config();
+    let mut general_config = app.db.get_ui_general_config();
+    for (key, value) in options {
+        match key.as_str() {
+            "dir" => download_config.save_dir = option_str(value)?.into(),
+            "split" => download_config.threads = parse_count(value)?.max(1),
+            "max-concurrent-downloads" => {
+                general_config.max_concurrency = parse_count(value)?.max(1);
+            }
+            "max-overall-download-limit" => {
+                general_config.speed_limit = parse_kib(value)?;
+            }
+            _ => tracing::debug!(option = key, "忽略不支持的 aria2 选项"),
+        }
+    }
+    app.set_config(download_config, general_config, None);
+    Ok(())
+}
+
+/// aria2 的选项值都是字符串，也接受数字
+fn option_str(value: &Value) -> Result<String, RpcError> {
+    match value {
+        Value::String(s) => Ok(s.clone()),
+        Value::Number(n) => Ok(n.to_string()),
+        _ => Err(RpcError::invalid_params(format!(
+            "选项值应为字符串：{value}"
+        ))),
+    }
+}
+
+fn parse_count(value: &Value) -> Result<i32, RpcError> {
+    let s = option_str(value)?;
+    s.trim()
+        .parse::<u32>()
+        .ok()
+        .and_then(|n| i32::try_from(n).ok())
+        .ok_or_else(|| RpcError::invalid_params(format!("无效的数字：{s}")))
+}
+
+/// 解析 aria2 的大小并转为界面设置中的 KiB
+fn parse_kib(value: &Value) -> Result<i32, RpcError> {
+    let s = option_str(value)?;
+    i32::try_from(parse_size(&s)? / 1024)
+        .map_err(|_| RpcError::invalid_params(format!("大小超出范围：{s}")))
+}
+
+/// 解析 aria2 的大小，例如 `1M`、`500K`、`1024`
+fn parse_size(s: &str) -> Result<u64, RpcError> {
+    let s = s.trim();
+    let (num, unit) = match s.char_indices().last() {
+        Some((i, c)) if c.is_ascii_alphabetic() => (&s[..i], c.to_ascii_uppercase()),
+        _ => (s, 'B'),
+    };
+    let unit = match unit {
+        'B' => 1,
+        'K' => 1024,
+        'M' => 1024 * 1024,
+        'G' => 1024 * 1024 * 1024,
+        _ => return Err(RpcError::invalid_params(format!("无效的大小：{s}"))),
+    };
+    num.trim()
+        .parse::<u64>()
+        .ok()
+        .and_then(|n| n.checked_mul(unit))
+        .ok_or_else(|| RpcError::invalid_params(format!("无效的大小：{s}")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_gid() {
+        assert_eq!(format_gid(26), "000000000000001a");
+        assert_eq!(parse_gid("000000000000001a"), Some(26));
+        assert_eq!(parse_gid(&format_gid(-1)), Some(-1));
+        assert_eq!(parse_gid("xyz"), None);
+        assert_eq!(parse_gid("00000000000000001"), None);
+    }
+
+    #[test]
+    fn test_check_token() {
+        let mut params = vec![json!("token:abc"), json!(["https://example.com"])];
+        assert!(check_token("abc", &mut params).is_ok());
+        assert_eq!(params, vec![json!(["https://example.com"])]);
+        assert!(check_token("abc", &mut vec![json!("token:abd")]).is_err());
+        assert!(check_token("abc", &mut vec![json!(["https://example.com"])]).is_err());
+        assert!(check_token("", &mut vec![json!("token:")]).is_err());
+    }
+
+    #[test]
+    fn test_has_valid_token() {
+        let req = |token: &str| json!({ "id": 1, "method": "aria2.tellActive", "params": [format!("token:{token}")] });
+        assert!(has_valid_token("abc", &req("abc").to_string()));
+        assert!(!has_valid_token("abc", &req("abd").to_string()));
+        assert!(!has_valid_token("", &req("").to_string()));
+        assert!(has_valid_token(
+            "abc",
+            &json!([req("x"), req("abc")]).to_string()
+        ));
+        let multicall = json!({
+            "method": "system.multicall",
+            "params": [[{ "methodName": "aria2.getVersion", "params": ["token:abc"] }]],
+        });
+        assert!(has_valid_token("abc", &multicall.to_string()));
+        let listed = json!({ "method": "system.listMethods" });
+        assert!(!has_valid_token("abc", &listed.to_string()));
+        assert!(!has_valid_token("abc", "not json"));
+    }
+
+    #[test]
+    fn test_parse_size() {
+        assert_eq!(parse_size("1024"), Ok(1024));
+        assert_eq!(parse_size("500K"), Ok(500 * 1024));
+        assert_eq!(parse_size("2m"), Ok(2 * 1024 * 1024));
+        assert!(parse_size("1T").is_err());
+        assert!(parse_size("abc").is_err());
+        assert!(parse_size(&format!("{}G", u64::MAX)).is_err());
+        assert!(parse_kib(&json!("2048G")).is_err());
+        assert_eq!(parse_kib(&json!("1M")), Ok(1024));
+        assert!(parse_count(&json!("4294967295")).is_err());
+        assert_eq!(parse_count(&json!(5)), Ok(5));
+    }
+
+    #[test]
+    fn test_apply_options() {
+        let mut config = ui::DownloadConfig {
+            headers: "Cookie: a=b".into(),
+            ..Default::default()
+        };
+        let options = json!({
+            "dir": "/tmp/downloads",
+            "out": "a.iso",
+            "header": ["X-Token: 1"],
+            "split": "8",
+            "max-download-limit": "1M",
+            "bt-seed": "false",
+        });
+        apply_options(&mut config, options.as_object().unwrap()).unwrap();
+        assert_eq!(config.save_dir, "/tmp/downloads");
+        assert_eq!(config.file_name, "a.iso");
+        assert!(!config.parse_filename);
+        assert_eq!(config.headers, "Cookie: a=b\nX-Token: 1");
+        assert_eq!(config.threads, 8);
+        assert_eq!(config.speed_limit, 1024);
+    }
+
+    #[test]
+    fn test_notification() {
+        assert_eq!(
+            notification(TaskEvent::Status {
+                gid: 1,
+                status: Status::Completed
+            }),
+            Some(json!({
+                "jsonrpc": "2.0",
+                "method": "aria2.onDownloadComplete",
+                "params": [{ "gid": "0000000000000001" }],
+            }))
+        );
+        assert_eq!(
+            notification(TaskEvent::Status {
+                gid: 1,
+                status: Status::Waiting
+            }),
+            None
+        );
+    }
+}
@@ -0,0 +1,203 @@
+mod aria2;
+
+pub use aria2::*;
+
+use crate::{
+    core::{App, TaskEvent, UiBridge, on_config_changed},
+    ui::EntryData,
+    utils::LocalServer,
+};
+use axum::{
+    Json, Router,
+    extract::{
+        State,
+        ws::{Message, WebSocket, WebSocketUpgrade},
+    },
+    http::{HeaderValue, Method, StatusCode, header},
+    middleware::{self, Next},
+    response::Response,
+    routing::get,
+};
+use serde_json::{Value, json};
+use slint::VecModel;
+use std::{future, net::Ipv4Addr, rc::Rc, sync::Arc};
+use tokio::sync::broadcast;
+use tokio_util::sync::CancellationToken;
+use tracing::error;
+
+/// 兼容 aria2 的 JSON-RPC 服务，只监听本机，地址为 `/jsonrpc`
+pub struct RpcServer {
+    app: App,
+    bridge: UiBridge,
+    server: LocalServer,
+}
+
+#[derive(Clone)]
+struct RpcState {
+    bridge: UiBridge,
+    events: broadcast::Sender<TaskEvent>,
+    secret: Arc<str>,
+    shutdown: CancellationToken,
+}
+
+impl RpcServer {
+    /// 需要在 UI 线程调用，创建后调用 apply 按设置启动，之后设置修改时自动重启
+    pub fn new(app: App, list_model: Rc<VecModel<EntryData>>) -> color_eyre::Result<Rc<Self>> {
+        let server = Rc::new(Self {
+            bridge: UiBridge::new(app.clone(), list_model)?,
+            app,
+            server: LocalServer::new("RPC"),
+        });
+        on_config_changed(&server.app.events, Rc::downgrade(&server), Self::apply)?;
+        Ok(server)
+    }
+
+    /// 按常规设置启动、重启或停止服务
+    pub fn apply(&self) {
+        let (addr, secret) = {
+            let config = self.app.db.inner.general_config.lock();
+            (
+                config
+                    .rpc_enabled
+                    .then(|| (Ipv4Addr::LOCALHOST, config.rpc_port).into()),
+                config.rpc_secret.clone(),
+            )
+        };
+        self.server.apply(addr, &secret, |shutdown| {
+            let state = RpcState {
+                bridge: self.bridge.clone(),
+                events: self.app.events.clone(),
+                secret: secret.as_str().into(),
+                shutdown,
+            };
+            Router::new()
+                .route("/jsonrpc", get(handle_ws).post(handle_http))
+                .layer(middleware::from_fn(cors))
+                .with_state(state)
+        });
+    }
+}
+
+/// AriaNg 等网页工具跨域访问，请求本身由密钥保护
+async fn cors(req: axum::extract::Request, next: Next) -> Response {
+    let mut res = if req.method() == Method::OPTIONS {
+        Response::default()
+    } else {
+        next.run(req).await
+    };
+    let headers = res.headers_mut();
+    headers.insert(
+        header::ACCESS_CONTROL_ALLOW_ORIGIN,
+        HeaderValue::from_static("*"),
+    );
+    headers.insert(
+        header::ACCESS_CONTROL_ALLOW_HEADERS,
+        HeaderValue::from_static("Content-Type"),
+    );
+    headers.insert(
+        header::ACCESS_CONTROL_ALLOW_METHODS,
+        HeaderValue::from_static("GET, POST, OPTIONS"),
+    );
+    res
+}
+
+async fn handle_http(State(state): State<RpcState>, body: String) -> (StatusCode, Json<Value>) {
+    (StatusCode::OK, Json(handle_body(&state, &body).await))
+}
+
+async fn handle_ws(ws: WebSocketUpgrade, State(state): State<RpcState>) -> Response {
+    ws.on_upgrade(move |socket| handle_socket(socket, state))
+}
+
+/// 处理 WebSocket 上的请求，同时推送任务事件的通知
+///
+/// 任何网页都可以连接本机的 WebSocket，收到带正确密钥的请求后才开始推送通知
+async fn handle_socket(mut socket: WebSocket, state: RpcState) {
+    let mut events = None;
+    loop {
+        let msg = tokio::select! {
+            _ = state.shutdown.cancelled() => break,
+            event = recv(&mut events) => match event {
+                Ok(event) => match notification(event) {
+                    Some(notification) => notification,
+                    None => continue,
+                },
+                Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                Err(_) => break,
+            },
+            msg = socket.recv() => match msg {
+                Some(Ok(Message::Text(text))) => {
+                    if events.is_none() && has_valid_token(&state.secret, &text) {
+                        events = Some(state.events.subscribe());
+                    }
+                    handle_body(&state, &text).await
+                }
+                Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                Some(Ok(_)) => continue,
+            },
+        };
+        if socket
+            .send(Message::Text(msg.to_string().into()))
+            .await
+            .is_err()
+        {
+            break;
+        }
+    }
+}
+
+/// 没有订阅时一直等待
+async fn recv(
+    events: &mut Option<broadcast::Receiver<TaskEvent>>,
+) -> Result<TaskEvent, broadcast::error::RecvError> {
+    match events {
+        Some(events) => events.recv().await,
+        None => future::pending().await,
+    }
+}
+
+/// 处理单个请求或批量请求
+async fn handle_body(state: &RpcState, body: &str) -> Value {
+    match serde_json::from_str::<Value>(body) {
+        Ok(Value::Array(requests)) => {
+            let mut replies = Vec::with_capacity(requests.len());
+            for req in requests {
+                replies.push(handle_request(state, req).await);
+            }
+            Value::Array(replies)
+        }
+        Ok(req) => handle_request(state, req).await,
+        Err(e) => error_reply(
+            Value::Null,
+            RpcError::new(PARSE_ERROR, format!("无法解析请求：{e}")),
+        ),
+    }
+}
+
+async fn handle_request(state: &RpcState, req: Value) -> Value {
+    let id = req.get("id").cloned().unwrap_or(Value::Null);
+    let Some(method) = req.get("method").and_then(Value::as_str) else {
+        return error_reply(id, RpcError::new(INVALID_REQUEST, "缺少 method"));
+    };
+    let params = match req.get("params") {
+        None | Some(Value::Null) => Vec::new(),
+        Some(Value::Array(params)) => params.clone(),
+        Some(_) => return error_reply(id, RpcError::new(INVALID_PARAMS, "params 应为数组")),
+    };
+    let reply = state.bridge.run({
+        let method = method.to_string();
+        move |app, list| call(app, list, &method, params)
+    });
+    match reply.await {
+        Some(Ok(result)) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+        Some(Err(e)) => error_reply(id, e),
+        None => {
+            error!(method = method, "RPC 请求没有被处理");
+            error_reply(id, RpcError::new(1, "请求没有被处理"))
+        }
+    }
+}
+
+fn error_reply(id: Value, e: RpcError) -> Value {
+    json!({ "jsonrpc": "2.0", "id": id, "error": e.to_json() })
+}
@@ -0,0 +1,99 @@
+use std::path::Path;
+
+/// 按文件类型分类保存时的类别
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Category {
+    Video,
+    Archive,
+    Document,
+    Image,
+    Program,
+}
+
+impl Category {
+    /// 优先按扩展名判断，没有扩展名时按 Content-Type 判断
+    pub fn detect(file_name: &str, content_type: Option<&str>) -> Option<Self> {
+        let ext = Path::new(file_name)
+            .extension()
+            .map(|e| e.to_string_lossy().to_lowercase());
+        match ext {
+            Some(ext) => Self::from_ext(&ext),
+            None => Self::from_content_type(content_type?),
+        }
+    }
+
+    fn from_ext(ext: &str) -> Option<Self> {
+        Some(match ext {
+            "mp4" | "mkv" | "avi" | "mov" | "wmv" | "flv" | "webm" | "m4v" | "mpg" | "mpeg"
+            | "ts" | "rmvb" | "3gp" => Self::Video,
+            "zip" | "rar" | "7z" | "tar" | "gz" | "tgz" | "bz2" | "xz" | "zst" | "lz4" | "lzma"
+            | "cab" | "iso" => Self::Archive,
+            "pdf" | "doc" | "docx" | "xls" | "xlsx" | "ppt" | "pptx" | "odt" | "ods" | "odp"
+            | "txt" | "md" | "rtf" | "epub" | "csv" => Self::Document,
+            "jpg" | "jpeg" | "jpe" | "png" | "gif" | "bmp" | "webp" | "svg" | "ico" | "tif"
+            | "tiff" | "heic" | "avif" => Self::Image,
+            "exe" | "msi" | "msix" | "appx" | "dmg" | "pkg" | "apk" | "deb" | "rpm"
+            | "appimage" | "flatpak" => Self::Program,
+            _ => return None,
+        })
+    }
+
+    fn from_content_type(content_type: &str) -> Option<Self> {
+        let mime_type = content_type.split(';').next()?.trim().to_lowercase();
+        let (kind, sub) = mime_type.split_once('/')?;
+        Some(match (kind, sub) {
+            ("video", _) => Self::Video,
+            ("image", _) => Self::Image,
+            ("text", "plain" | "markdown" | "csv") => Self::Document,
+            (
+                "application",
+                "zip" | "gzip" | "x-gzip" | "x-tar" | "x-7z-compressed" | "vnd.rar"
+                | "x-rar-compressed" | "x-bzip2" | "x-xz" | "zstd",
+            ) => Self::Archive,
+            ("application", "pdf" | "msword" | "epub+zip" | "rtf") => Self::Document,
+            ("application", sub)
+                if sub.starts_with("vnd.openxmlformats-officedocument")
+                    || sub.starts_with("vnd.ms-")
+                    || sub.starts_with("vnd.oasis.opendocument") =>
+            {
+                Self::Document
+            }
+            (
+                "application",
+                "x-msdownload"
+                | "vnd.microsoft.portable-executable"
+                | "x-msi"
+                | "x-apple-diskimage"
+                | "vnd.android.package-archive"
+                | "vnd.debian.binary-package"
+                | "x-rpm",
+            ) => Self::Program,
+            _ => return None,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detect() {
+        assert_eq!(Category::detect("movie.MKV", None), Some(Category::Video));
+        assert_eq!(
+            Category::detect("src.tar.gz", Some("video/mp4")),
+            Some(Category::Archive)
+        );
+        assert_eq!(Category::detect("setup.exe", None), Some(Category::Program));
+        assert_eq!(Category::detect("index.html", None), None);
+        assert_eq!(
+            Category::detect("report", Some("application/pdf")),
+            Some(Category::Document)
+        );
+        assert_eq!(
+            Category::detect("photo", Some("image/jpeg; charset=binary")),
+            Some(Category::Image)
+        );
+        assert_eq!(Category::detect("unknown", None), None);
+    }
+}
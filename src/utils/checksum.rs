@@ -0,0 +1,264 @@
+use serde::{Deserialize, Serialize};
+use sha1::Digest;
+use std::{
+    fmt,
+    fs::File,
+    io::{self, Read},
+    path::Path,
+    str::FromStr,
+};
+use tokio_util::sync::CancellationToken;
+use url::Url;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HashAlgorithm {
+    Sha256,
+    Sha1,
+    Md5,
+    Blake3,
+}
+
+impl HashAlgorithm {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Sha256 => "sha256",
+            Self::Sha1 => "sha1",
+            Self::Md5 => "md5",
+            Self::Blake3 => "blake3",
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().replace(['-', '_'], "").as_str() {
+            "sha256" => Some(Self::Sha256),
+            "sha1" => Some(Self::Sha1),
+            "md5" => Some(Self::Md5),
+            "blake3" | "b3" => Some(Self::Blake3),
+            _ => None,
+        }
+    }
+
+    /// 十六进制摘要长度
+    fn hex_len(&self) -> usize {
+        match self {
+            Self::Sha256 | Self::Blake3 => 64,
+            Self::Sha1 => 40,
+            Self::Md5 => 32,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Checksum {
+    pub algorithm: HashAlgorithm,
+    /// 小写十六进制
+    pub value: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseChecksumError;
+
+impl fmt::Display for ParseChecksumError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("无法识别的校验值，格式应为 sha256:<十六进制摘要>")
+    }
+}
+
+impl std::error::Error for ParseChecksumError {}
+
+impl FromStr for Checksum {
+    type Err = ParseChecksumError;
+
+    /// 支持 `sha256:abcd`、`sha-256=abcd` 以及不带算法的纯摘要（按长度推断，不支持 blake3）
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let (algorithm, value) = match s.split_once([':', '=']) {
+            Some((name, value)) => (
+                HashAlgorithm::from_name(name.trim()).ok_or(ParseChecksumError)?,
+                value.trim(),
+            ),
+            None => (
+                match s.len() {
+                    64 => HashAlgorithm::Sha256,
+                    40 => HashAlgorithm::Sha1,
+                    32 => HashAlgorithm::Md5,
+                    _ => return Err(ParseChecksumError),
+                },
+                s,
+            ),
+        };
+        if value.len() != algorithm.hex_len() || !value.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(ParseChecksumError);
+        }
+        Ok(Self {
+            algorithm,
+            value: value.to_ascii_lowercase(),
+        })
+    }
+}
+
+impl fmt::Display for Checksum {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.algorithm.name(), self.value)
+    }
+}
+
+impl Checksum {
+    /// 从 URL 片段中提取校验值，例如 `#sha256=abcd`
+    pub fn from_url(url: &Url) -> Option<Self> {
+        url.fragment()?
+            .split('&')
+            .find_map(|part| part.parse().ok())
+    }
+}
+
+enum Hasher {
+    Sha256(sha2::Sha256),
+    Sha1(sha1::Sha1),
+    Md5(md5::Md5),
+    Blake3(Box<blake3::Hasher>),
+}
+
+impl Hasher {
+    fn new(algorithm: HashAlgorithm) -> Self {
+        match algorithm {
+            HashAlgorithm::Sha256 => Self::Sha256(sha2::Sha256::new()),
+            HashAlgorithm::Sha1 => Self::Sha1(sha1::Sha1::new()),
+            HashAlgorithm::Md5 => Self::Md5(md5::Md5::new()),
+            HashAlgorithm::Blake3 => Self::Blake3(Box::default()),
+        }
+    }
+
+    fn update(&mut self, data: &[u8]) {
+        match self {
+            Self::Sha256(h) => h.update(data),
+            Self::Sha1(h) => h.update(data),
+            Self::Md5(h) => h.update(data),
+            Self::Blake3(h) => {
+                h.update(data);
+            }
+        }
+    }
+
+    fn finalize(self) -> String {
+        match self {
+            Self::Sha256(h) => format!("{:x}", h.finalize()),
+            Self::Sha1(h) => format!("{:x}", h.finalize()),
+            Self::Md5(h) => format!("{:x}", h.finalize()),
+            Self::Blake3(h) => h.finalize().to_hex().to_string(),
+        }
+    }
+}
+
+/// 阻塞地计算文件摘要，`on_progress` 接收已读取的字节数，被取消时返回 `Ok(None)`
+pub fn hash_file(
+    path: &Path,
+    algorithm: HashAlgorithm,
+    cancel_token: &CancellationToken,
+    mut on_progress: impl FnMut(u64),
+) -> io::Result<Option<String>> {
+    let mut file = File::open(path)?;
+    let mut hasher = Hasher::new(algorithm);
+    let mut buf = vec![0; 1024 * 1024];
+    let mut read = 0;
+    loop {
+        if cancel_token.is_cancelled() {
+            return Ok(None);
+        }
+        let n = file.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buf[..n]);
+        read += n as u64;
+        on_progress(read);
+    }
+    Ok(Some(hasher.finalize()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_checksum() {
+        let sha256 = "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855";
+
+        // 带算法前缀
+        let c: Checksum = format!("sha256:{sha256}").parse().unwrap();
+        assert_eq!(c.algorithm, HashAlgorithm::Sha256);
+        assert_eq!(c.value, sha256);
+
+        // aria2 风格，大写摘要
+        let c: Checksum = format!("SHA-256={}", sha256.to_uppercase())
+            .parse()
+            .unwrap();
+        assert_eq!(c.value, sha256);
+
+        // 按长度推断
+        let c: Checksum = "d41d8cd98f00b204e9800998ecf8427e".parse().unwrap();
+        assert_eq!(c.algorithm, HashAlgorithm::Md5);
+        let c: Checksum = "da39a3ee5e6b4b0d3255bfef95601890afd80709".parse().unwrap();
+        assert_eq!(c.algorithm, HashAlgorithm::Sha1);
+
+        // 长度与算法不符、非十六进制、未知算法
+        assert!(
+            "sha1:d41d8cd98f00b204e9800998ecf8427e"
+                .parse::<Checksum>()
+                .is_err()
+        );
+        assert!(
+            format!("md5:{}", "z".repeat(32))
+                .parse::<Checksum>()
+                .is_err()
+        );
+        assert!(format!("crc32:{sha256}").parse::<Checksum>().is_err());
+        assert!("".parse::<Checksum>().is_err());
+
+        // 往返
+        let c: Checksum = format!("blake3:{sha256}").parse().unwrap();
+        assert_eq!(c.to_string().parse::<Checksum>().unwrap(), c);
+    }
+
+    #[test]
+    fn test_checksum_from_url() {
+        let url = Url::parse("https://example.com/a.zip#foo&md5=d41d8cd98f00b204e9800998ecf8427e")
+            .unwrap();
+        let c = Checksum::from_url(&url).unwrap();
+        assert_eq!(c.algorithm, HashAlgorithm::Md5);
+
+        let url = Url::parse("https://example.com/a.zip#section").unwrap();
+        assert_eq!(Checksum::from_url(&url), None);
+    }
+
+    #[test]
+    fn test_hash_file() {
+        let path = std::env::temp_dir().join("fd-test-hash-file");
+        std::fs::write(&path, b"abc").unwrap();
+        let token = CancellationToken::new();
+        let hash = |algorithm| {
+            hash_file(&path, algorithm, &token, |_| {})
+                .unwrap()
+                .unwrap()
+        };
+        assert_eq!(
+            hash(HashAlgorithm::Sha256),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+        assert_eq!(
+            hash(HashAlgorithm::Sha1),
+            "a9993e364706816aba3e25717850c26c9cd0d89d"
+        );
+        assert_eq!(hash(HashAlgorithm::Md5), "900150983cd24fb0d6963f7d28e17f72");
+        assert_eq!(
+            hash(HashAlgorithm::Blake3),
+            "6437b3ac38465133ffb63b75273a8db548c558465d79db03fd359c6cd5bd9d85"
+        );
+        token.cancel();
+        assert_eq!(
+            hash_file(&path, HashAlgorithm::Md5, &token, |_| {}).unwrap(),
+            None
+        );
+        let _ = std::fs::remove_file(&path);
+    }
+}
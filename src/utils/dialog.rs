@@ -1,11 +1,10 @@
+use crate::persist::FileChangedPolicy;
 use crate::ui::{DialogType, DownloadConfig, TaskDialog};
-use crate::utils::LogErr;
-#[cfg(target_os = "macos")]
-use i_slint_backend_winit::WinitWindowAccessor;
-use rfd::FileDialog;
-#[cfg(target_os = "macos")]
-use slint::CloseRequestResponse;
-use slint::{ComponentHandle, SharedString, ToSharedString};
+use crate::utils::{LogErr, is_metalink};
+use i_slint_backend_winit::{EventResult, WinitWindowAccessor, winit::event::WindowEvent};
+use rfd::{AsyncMessageDialog, FileDialog, MessageButtons, MessageDialogResult, MessageLevel};
+use slint::{CloseRequestResponse, ComponentHandle, SharedString, ToSharedString};
+use std::{cell::RefCell, rc::Rc};
 
 /// 显示添加任务对话框
 pub fn show_task_dialog(
@@ -47,17 +46,37 @@ pub fn show_task_dialog(
         });
     };
 
+    // 取消或关闭对话框时丢弃 on_confirm，调用方可以借此得知对话框被取消
+    let on_confirm = Rc::new(RefCell::new(Some(on_confirm)));
+
     #[cfg(target_os = "macos")]
     {
         let hide_dialog_clone = hide_dialog.clone();
+        let on_confirm = on_confirm.clone();
         dialog.window().on_close_requested(move || {
             hide_dialog_clone();
+            on_confirm.borrow_mut().take();
             // 返回保持展示仅是为了绕过 slint 内置的隐藏策略 窗体由 hide_dialog_clone 隐藏
             CloseRequestResponse::KeepWindowShown
         });
     }
+    #[cfg(not(target_os = "macos"))]
+    {
+        let on_confirm = on_confirm.clone();
+        dialog.window().on_close_requested(move || {
+            on_confirm.borrow_mut().take();
+            CloseRequestResponse::HideWindow
+        });
+    }
 
-    dialog.on_canceled(hide_dialog.clone());
+    dialog.on_canceled({
+        let hide_dialog = hide_dialog.clone();
+        let on_confirm = on_confirm.clone();
+        move || {
+            hide_dialog();
+            on_confirm.borrow_mut().take();
+        }
+    });
 
     dialog.on_browse_folder({
         let dialog = dialog.as_weak();
@@ -73,10 +92,44 @@ pub fn show_task_dialog(
         }
     });
 
-    let mut handle = Some(on_confirm);
+    dialog.on_browse_metalink({
+        let dialog = dialog.as_weak();
+        move || {
+            let dialog = dialog.clone();
+            std::thread::spawn(move || {
+                if let Some(files) = FileDialog::new()
+                    .add_filter("Metalink", &["meta4", "metalink"])
+                    .pick_files()
+                {
+                    let _ = dialog.upgrade_in_event_loop(move |d| {
+                        for file in files {
+                            d.invoke_add_url(file.to_string_lossy().to_shared_string());
+                        }
+                    });
+                }
+            });
+        }
+    });
+
+    // 拖入的 Metalink 文件追加到下载链接中
+    dialog.window().on_winit_window_event({
+        let dialog = dialog.as_weak();
+        move |_, event| {
+            if let WindowEvent::DroppedFile(path) = event
+                && is_metalink(path)
+                && let Some(d) = dialog.upgrade()
+            {
+                d.invoke_add_url(path.to_string_lossy().to_shared_string());
+                return EventResult::PreventDefault;
+            }
+            EventResult::Propagate
+        }
+    });
+
     dialog.on_confirm(move |urls, config, bg_download| {
         hide_dialog();
-        if let Some(h) = handle.take() {
+        let handle = on_confirm.borrow_mut().take();
+        if let Some(h) = handle {
             h(urls, config, bg_download);
         }
     });
@@ -84,3 +137,21 @@ pub fn show_task_dialog(
     dialog.show()?;
     Ok(())
 }
+
+/// 询问服务器上的文件改变后如何处理，关闭对话框视为停止下载
+pub async fn ask_file_changed(file_name: &str) -> FileChangedPolicy {
+    let res = AsyncMessageDialog::new()
+        .set_level(MessageLevel::Warning)
+        .set_title("服务器上的文件已改变")
+        .set_description(format!(
+            "{file_name} 在服务器上已改变，已下载的部分可能已失效\n\n是：从头重新下载\n否：保留已下载的部分继续下载\n取消：停止下载"
+        ))
+        .set_buttons(MessageButtons::YesNoCancel)
+        .show()
+        .await;
+    match res {
+        MessageDialogResult::Yes => FileChangedPolicy::Restart,
+        MessageDialogResult::No => FileChangedPolicy::Keep,
+        _ => FileChangedPolicy::Fail,
+    }
+}
@@ -0,0 +1,340 @@
+use crate::utils::{sanitize, sanitize_path};
+use flate2::read::GzDecoder;
+use std::{
+    fs::{self, File},
+    io::{self, BufReader, Read},
+    path::{Component, Path, PathBuf},
+    process::Command,
+};
+
+/// 支持解压的压缩包格式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArchiveKind {
+    Zip,
+    Tar,
+    TarGz,
+    TarZst,
+    /// 需要安装 7-Zip
+    SevenZ,
+}
+
+impl ArchiveKind {
+    /// 按文件名判断格式，返回格式和去掉扩展名后的名字
+    pub fn detect(file_name: &str) -> Option<(Self, &str)> {
+        let lower = file_name.to_lowercase();
+        [
+            (".zip", Self::Zip),
+            (".tar", Self::Tar),
+            (".tar.gz", Self::TarGz),
+            (".tgz", Self::TarGz),
+            (".tar.zst", Self::TarZst),
+            (".tzst", Self::TarZst),
+            (".7z", Self::SevenZ),
+        ]
+        .into_iter()
+        .find(|(ext, _)| lower.ends_with(ext))
+        .map(|(ext, kind)| (kind, &file_name[..file_name.len() - ext.len()]))
+    }
+}
+
+/// 返回压缩包的格式和解压到的文件夹，即压缩包旁边去掉扩展名的同名文件夹
+pub fn extract_dir(archive: &Path) -> Option<(ArchiveKind, PathBuf)> {
+    let file_name = archive.file_name()?.to_string_lossy();
+    let (kind, stem) = ArchiveKind::detect(&file_name)?;
+    let dir_name = sanitize(stem, 255);
+    if dir_name.is_empty() {
+        return None;
+    }
+    Some((kind, archive.with_file_name(dir_name)))
+}
+
+/// 压缩包内的路径转为解压文件夹内的相对路径，绝对路径或含有 `..` 时返回 None，防止写到文件夹外
+pub fn entry_path(name: &Path) -> Option<PathBuf> {
+    let is_relative = name
+        .components()
+        .all(|c| matches!(c, Component::Normal(_) | Component::CurDir));
+    let path = sanitize_path(name);
+    (is_relative && path.components().any(|c| matches!(c, Component::Normal(_)))).then_some(path)
+}
+
+/// 解压到 dest 文件夹，返回解压出的文件数，on_progress 为已读取的压缩包字节数
+pub fn extract(
+    archive: &Path,
+    kind: ArchiveKind,
+    dest: &Path,
+    on_progress: impl FnMut(u64),
+) -> io::Result<usize> {
+    fs::create_dir_all(dest)?;
+    let open = || File::open(archive).map(BufReader::new);
+    match kind {
+        ArchiveKind::Zip => extract_zip(open()?, dest, on_progress),
+        ArchiveKind::Tar => extract_tar(ProgressReader::new(open()?, on_progress), dest),
+        ArchiveKind::TarGz => extract_tar(
+            GzDecoder::new(ProgressReader::new(open()?, on_progress)),
+            dest,
+        ),
+        ArchiveKind::TarZst => extract_tar(
+            zstd::Decoder::new(ProgressReader::new(open()?, on_progress))?,
+            dest,
+        ),
+        ArchiveKind::SevenZ => extract_7z(archive, dest),
+    }
+}
+
+struct ProgressReader<R, F> {
+    inner: R,
+    read: u64,
+    on_progress: F,
+}
+
+impl<R, F> ProgressReader<R, F> {
+    fn new(inner: R, on_progress: F) -> Self {
+        Self {
+            inner,
+            read: 0,
+            on_progress,
+        }
+    }
+}
+
+impl<R: Read, F: FnMut(u64)> Read for ProgressReader<R, F> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.read += n as u64;
+        (self.on_progress)(self.read);
+        Ok(n)
+    }
+}
+
+fn extract_zip(
+    file: BufReader<File>,
+    dest: &Path,
+    mut on_progress: impl FnMut(u64),
+) -> io::Result<usize> {
+    let mut archive = zip::ZipArchive::new(file)?;
+    let mut count = 0;
+    let mut read = 0;
+    for i in 0..archive.len() {
+        let mut entry = archive.by_index(i)?;
+        read += entry.compressed_size();
+        let Some(path) = entry_path(Path::new(entry.name())) else {
+            tracing::warn!(name = entry.name(), "跳过压缩包中不安全的路径");
+            continue;
+        };
+        let path = dest.join(path);
+        if entry.is_dir() {
+            fs::create_dir_all(&path)?;
+        } else {
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            io::copy(&mut entry, &mut File::create(&path)?)?;
+            count += 1;
+        }
+        on_progress(read);
+    }
+    Ok(count)
+}
+
+fn extract_tar(reader: impl Read, dest: &Path) -> io::Result<usize> {
+    let mut archive = tar::Archive::new(reader);
+    let mut count = 0;
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let name = entry.path()?.into_owned();
+        let Some(path) = entry_path(&name) else {
+            tracing::warn!(name = ?name, "跳过压缩包中不安全的路径");
+            continue;
+        };
+        let path = dest.join(path);
+        match entry.header().entry_type() {
+            tar::EntryType::Directory => fs::create_dir_all(&path)?,
+            tar::EntryType::Regular | tar::EntryType::Continuous => {
+                if let Some(parent) = path.parent() {
+                    fs::create_dir_all(parent)?;
+                }
+                io::copy(&mut entry, &mut File::create(&path)?)?;
+                count += 1;
+            }
+            // 链接可能指向文件夹外，不解压
+            _ => {}
+        }
+    }
+    Ok(count)
+}
+
+/// 7z 交给 7-Zip 解压，解压前先检查压缩包内的路径
+fn extract_7z(archive: &Path, dest: &Path) -> io::Result<usize> {
+    let exe = find_7z()?;
+    let output = Command::new(&exe)
+        .args(["l", "-slt"])
+        .arg(archive)
+        .output()?;
+    if !output.status.success() {
+        return Err(io::Error::other(format!(
+            "无法读取压缩包：{}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+    let count = check_7z_listing(&String::from_utf8_lossy(&output.stdout))?;
+    let mut out_dir = std::ffi::OsString::from("-o");
+    out_dir.push(dest);
+    let status = Command::new(&exe)
+        .args(["x", "-y", "-bd"])
+        .arg(out_dir)
+        .arg(archive)
+        .status()?;
+    if !status.success() {
+        return Err(io::Error::other(format!("7-Zip 解压失败：{status}")));
+    }
+    Ok(count)
+}
+
+/// 检查 `7z l -slt` 列出的条目，返回文件数
+///
+/// 7-Zip 会按原样创建链接，链接可能指向文件夹外，有链接时整个压缩包都不解压
+fn check_7z_listing(listing: &str) -> io::Result<usize> {
+    let listing = listing.replace("\r\n", "\n");
+    // 分隔线之前是压缩包本身的信息
+    let entries = listing.split_once("\n----------\n").map_or("", |(_, e)| e);
+    let mut count = 0;
+    for block in entries.split("\n\n") {
+        let mut name = None;
+        let mut is_dir = false;
+        let mut is_link = false;
+        for line in block.lines() {
+            match line.trim_end().split_once(" = ") {
+                Some(("Path", path)) => name = Some(path),
+                Some(("Folder", "+")) => is_dir = true,
+                Some(("Symbolic Link" | "Hard Link", _)) => is_link = true,
+                Some(("Attributes", attr)) => {
+                    is_dir |= attr.starts_with('D');
+                    // Windows 属性之后是 Unix 权限，如 `A_ -rw-r--r--`，`l` 开头为符号链接
+                    is_link |= attr.split_whitespace().any(|a| a.starts_with('l'));
+                }
+                _ => {}
+            }
+        }
+        let Some(name) = name else { continue };
+        if entry_path(Path::new(name)).is_none() {
+            return Err(io::Error::other(format!("压缩包中有不安全的路径：{name}")));
+        }
+        if is_link {
+            return Err(io::Error::other(format!("压缩包中有链接：{name}")));
+        }
+        if !is_dir {
+            count += 1;
+        }
+    }
+    Ok(count)
+}
+
+fn find_7z() -> io::Result<PathBuf> {
+    let mut candidates: Vec<PathBuf> = vec!["7z".into(), "7zz".into(), "7za".into()];
+    if cfg!(windows) {
+        for var in ["ProgramFiles", "ProgramFiles(x86)"] {
+            if let Some(dir) = std::env::var_os(var) {
+                candidates.push(Path::new(&dir).join("7-Zip").join("7z.exe"));
+            }
+        }
+    }
+    candidates
+        .into_iter()
+        .find(|exe| Command::new(exe).arg("i").output().is_ok())
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "没有找到 7-Zip，请先安装"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    #[test]
+    fn test_entry_path() {
+        assert_eq!(entry_path(Path::new("a/./b.txt")), Some("a/b.txt".into()));
+        assert_eq!(entry_path(Path::new("../evil.txt")), None);
+        assert_eq!(entry_path(Path::new("a/../../evil.txt")), None);
+        assert_eq!(entry_path(Path::new("/etc/passwd")), None);
+        assert_eq!(entry_path(Path::new(".")), None);
+    }
+
+    #[test]
+    fn test_extract_dir() {
+        let (kind, dir) = extract_dir(Path::new("/tmp/src-1.0.tar.zst")).unwrap();
+        assert_eq!(kind, ArchiveKind::TarZst);
+        assert_eq!(dir, Path::new("/tmp/src-1.0"));
+        assert_eq!(
+            extract_dir(Path::new("/tmp/a.TGZ")).map(|(k, _)| k),
+            Some(ArchiveKind::TarGz)
+        );
+        assert!(extract_dir(Path::new("/tmp/a.txt")).is_none());
+        assert!(extract_dir(Path::new("/tmp/.zip")).is_none());
+    }
+
+    #[test]
+    fn test_check_7z_listing() {
+        let listing = |entries: &str| format!("Path = a.7z\nType = 7z\n\n----------\n{entries}\n");
+        let ok = listing(
+            "Path = docs\nFolder = +\nAttributes = D_ drwxr-xr-x\n\n\
+             Path = docs/a.txt\nFolder = -\nAttributes = A_ -rw-r--r--",
+        );
+        assert_eq!(check_7z_listing(&ok).unwrap(), 1);
+        assert_eq!(check_7z_listing(&ok.replace('\n', "\r\n")).unwrap(), 1);
+        let unix_link = listing("Path = link\nFolder = -\nAttributes = A_ lrwxrwxrwx");
+        assert!(check_7z_listing(&unix_link).is_err());
+        let link = listing("Path = link\nFolder = -\nSymbolic Link = /etc");
+        assert!(check_7z_listing(&link).is_err());
+        let evil = listing("Path = ../evil.txt\nFolder = -\nAttributes = A_ -rw-r--r--");
+        assert!(check_7z_listing(&evil).is_err());
+    }
+
+    #[test]
+    fn test_extract_zip() {
+        let dir = std::env::temp_dir().join(format!("fd-extract-{}", std::process::id()));
+        let archive = dir.join("test.zip");
+        fs::create_dir_all(&dir).unwrap();
+        {
+            let mut zip = zip::ZipWriter::new(File::create(&archive).unwrap());
+            let options = zip::write::SimpleFileOptions::default();
+            zip.start_file("docs/a.txt", options).unwrap();
+            zip.write_all(b"hello").unwrap();
+            zip.start_file("../evil.txt", options).unwrap();
+            zip.write_all(b"evil").unwrap();
+            zip.finish().unwrap();
+        }
+        let (kind, dest) = extract_dir(&archive).unwrap();
+        assert_eq!(
+            (kind, dest.as_path()),
+            (ArchiveKind::Zip, dir.join("test").as_path())
+        );
+        let mut read = 0;
+        assert_eq!(extract(&archive, kind, &dest, |n| read = n).unwrap(), 1);
+        assert!(read > 0);
+        assert_eq!(fs::read(dest.join("docs/a.txt")).unwrap(), b"hello");
+        assert!(!dir.join("evil.txt").exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_extract_tar_zst() {
+        let dir = std::env::temp_dir().join(format!("fd-extract-zst-{}", std::process::id()));
+        let archive = dir.join("test.tar.zst");
+        fs::create_dir_all(&dir).unwrap();
+        {
+            let encoder = zstd::Encoder::new(File::create(&archive).unwrap(), 0).unwrap();
+            let mut builder = tar::Builder::new(encoder.auto_finish());
+            let mut header = tar::Header::new_gnu();
+            header.set_size(5);
+            header.set_mode(0o644);
+            header.set_cksum();
+            builder
+                .append_data(&mut header, "dir/b.txt", &b"world"[..])
+                .unwrap();
+            builder.finish().unwrap();
+        }
+        let (kind, dest) = extract_dir(&archive).unwrap();
+        assert_eq!(extract(&archive, kind, &dest, |_| {}).unwrap(), 1);
+        assert_eq!(fs::read(dest.join("dir/b.txt")).unwrap(), b"world");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,21 @@
+use std::{cell::Cell, future::Future};
+
+thread_local! {
+    static HEADLESS: Cell<bool> = const { Cell::new(false) };
+}
+
+/// 标记当前线程没有 Slint 事件循环，之后的 [`spawn_local`] 交给 tokio 的 `LocalSet`
+pub fn set_headless() {
+    HEADLESS.set(true);
+}
+
+/// 在当前线程运行不需要 Send 的任务，有界面时在 Slint 事件循环中运行，
+/// 无界面时须在 `LocalSet` 中调用
+pub fn spawn_local(fut: impl Future<Output = ()> + 'static) -> color_eyre::Result<()> {
+    if HEADLESS.get() {
+        tokio::task::spawn_local(fut);
+    } else {
+        slint::spawn_local(fut)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,177 @@
+use crate::utils::{Checksum, HashAlgorithm};
+use color_eyre::eyre::eyre;
+use roxmltree::{Document, Node};
+use std::path::Path;
+use url::Url;
+
+/// Metalink 中的一个文件
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MetalinkFile {
+    pub name: String,
+    pub size: Option<u64>,
+    pub checksum: Option<Checksum>,
+    /// 按优先级排序的下载地址，第一个为主地址
+    pub urls: Vec<Url>,
+}
+
+/// 校验算法的优先顺序，越靠前越可靠
+const HASH_RANK: [HashAlgorithm; 4] = [
+    HashAlgorithm::Sha256,
+    HashAlgorithm::Blake3,
+    HashAlgorithm::Sha1,
+    HashAlgorithm::Md5,
+];
+
+pub fn is_metalink(path: &Path) -> bool {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .is_some_and(|e| e.eq_ignore_ascii_case("meta4") || e.eq_ignore_ascii_case("metalink"))
+}
+
+/// 读取 `.meta4` 或 `.metalink` 文件
+pub fn read_metalink(path: &Path) -> color_eyre::Result<Vec<MetalinkFile>> {
+    let text = std::fs::read_to_string(path)?;
+    parse_metalink(&text)
+}
+
+/// 解析 Metalink 4 (RFC 5854) 和 Metalink 3，忽略没有可用 http 地址的文件
+pub fn parse_metalink(text: &str) -> color_eyre::Result<Vec<MetalinkFile>> {
+    let doc = Document::parse(text)?;
+    let root = doc.root_element();
+    if root.tag_name().name() != "metalink" {
+        return Err(eyre!("不是 Metalink 文件"));
+    }
+    Ok(root
+        .descendants()
+        .filter(|n| n.has_tag_name("file"))
+        .filter_map(parse_file)
+        .collect())
+}
+
+fn parse_file(file: Node) -> Option<MetalinkFile> {
+    let name = file.attribute("name")?;
+    // 只取最后一段，避免写到保存文件夹之外
+    let name = name.rsplit(['/', '\\']).next().unwrap_or(name).trim();
+    let size = child_text(file, "size").and_then(|s| s.parse().ok());
+    let checksum = file
+        .descendants()
+        .filter(|n| n.has_tag_name("hash"))
+        // pieces 中的是分块摘要
+        .filter(|n| {
+            n.parent_element()
+                .is_some_and(|p| !p.has_tag_name("pieces"))
+        })
+        .filter_map(|n| {
+            format!("{}:{}", n.attribute("type")?, n.text()?)
+                .parse()
+                .ok()
+        })
+        .min_by_key(|c: &Checksum| HASH_RANK.iter().position(|a| *a == c.algorithm));
+    let mut urls: Vec<_> = file
+        .descendants()
+        .filter(|n| n.has_tag_name("url"))
+        .filter_map(|n| {
+            let url = Url::parse(n.text()?.trim()).ok()?;
+            // Metalink 4 的 priority 越小越优先，Metalink 3 的 preference 越大越优先
+            let rank = match (n.attribute("priority"), n.attribute("preference")) {
+                (Some(p), _) => p.parse().unwrap_or(u32::MAX),
+                (None, Some(p)) => 100u32.saturating_sub(p.parse().unwrap_or(0)),
+                (None, None) => u32::MAX,
+            };
+            Some((rank, url))
+        })
+        .filter(|(_, u)| matches!(u.scheme(), "http" | "https"))
+        .collect();
+    urls.sort_by_key(|(rank, _)| *rank);
+    let urls: Vec<_> = urls.into_iter().map(|(_, u)| u).collect();
+    if urls.is_empty() {
+        return None;
+    }
+    Some(MetalinkFile {
+        name: name.to_string(),
+        size,
+        checksum,
+        urls,
+    })
+}
+
+fn child_text<'a>(node: Node<'a, '_>, name: &str) -> Option<&'a str> {
+    node.children()
+        .find(|n| n.has_tag_name(name))
+        .and_then(|n| n.text())
+        .map(str::trim)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_metalink4() {
+        let text = r#"<?xml version="1.0" encoding="UTF-8"?>
+<metalink xmlns="urn:ietf:params:xml:ns:metalink">
+  <file name="dir/example.iso">
+    <size>14471447</size>
+    <hash type="md5">0123456789abcdef0123456789abcdef</hash>
+    <hash type="sha-256">f0ad929cd259957e160ea442eb80986b5f01a0dba2bb8e2c1bd6bd1fa83d34b0</hash>
+    <pieces length="262144" type="sha-1">
+      <hash>0123456789abcdef0123456789abcdef01234567</hash>
+    </pieces>
+    <url priority="2">https://b.example.com/example.iso</url>
+    <url priority="1">https://a.example.com/example.iso</url>
+    <url>ftp://ftp.example.com/example.iso</url>
+    <metaurl mediatype="torrent">https://example.com/example.torrent</metaurl>
+  </file>
+  <file name="empty.bin">
+    <url>ftp://ftp.example.com/empty.bin</url>
+  </file>
+</metalink>"#;
+        let files = parse_metalink(text).unwrap();
+        assert_eq!(files.len(), 1);
+        let file = &files[0];
+        assert_eq!(file.name, "example.iso");
+        assert_eq!(file.size, Some(14471447));
+        assert_eq!(
+            file.checksum.as_ref().unwrap().to_string(),
+            "sha256:f0ad929cd259957e160ea442eb80986b5f01a0dba2bb8e2c1bd6bd1fa83d34b0"
+        );
+        assert_eq!(
+            file.urls.iter().map(Url::as_str).collect::<Vec<_>>(),
+            [
+                "https://a.example.com/example.iso",
+                "https://b.example.com/example.iso"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_metalink3() {
+        let text = r#"<?xml version="1.0" encoding="UTF-8"?>
+<metalink version="3.0" xmlns="http://www.metalinker.org/">
+  <files>
+    <file name="example.zip">
+      <size>1024</size>
+      <verification>
+        <hash type="sha1">0123456789abcdef0123456789abcdef01234567</hash>
+      </verification>
+      <resources>
+        <url type="http" preference="10">http://slow.example.com/example.zip</url>
+        <url type="http" preference="90">http://fast.example.com/example.zip</url>
+      </resources>
+    </file>
+  </files>
+</metalink>"#;
+        let files = parse_metalink(text).unwrap();
+        assert_eq!(files.len(), 1);
+        assert_eq!(files[0].size, Some(1024));
+        assert_eq!(
+            files[0].checksum.as_ref().unwrap().algorithm,
+            HashAlgorithm::Sha1
+        );
+        assert_eq!(
+            files[0].urls[0].as_str(),
+            "http://fast.example.com/example.zip"
+        );
+        assert!(parse_metalink("<html></html>").is_err());
+    }
+}
@@ -1,13 +1,31 @@
 mod auto_ext;
+mod category;
+mod checksum;
 mod dialog;
+mod extract;
 mod force_send;
 mod header;
+mod local;
 mod log;
+mod metalink;
+mod post_action;
 mod sanitize;
+mod schedule;
+mod server;
+mod site_rule;
 
 pub use auto_ext::*;
+pub use category::*;
+pub use checksum::*;
 pub use dialog::*;
+pub use extract::*;
 pub use force_send::*;
 pub use header::*;
+pub use local::*;
 pub use log::*;
+pub use metalink::*;
+pub use post_action::*;
 pub use sanitize::*;
+pub use schedule::*;
+pub use server::*;
+pub use site_rule::*;
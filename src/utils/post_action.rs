@@ -0,0 +1,91 @@
+use crate::utils::LogErr;
+use serde::{Deserialize, Serialize};
+use std::{fmt, path::PathBuf, str::FromStr};
+
+/// 下载完成后的操作，例如 `运行 notify-send 下载完成` 或 `移动 D:\视频`
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub enum PostAction {
+    /// 运行命令，文件路径和链接通过环境变量 FD_PATH 和 FD_URL 传入
+    Run(String),
+    /// 移动文件到文件夹
+    Move(PathBuf),
+    /// 用默认程序打开文件
+    Open,
+    /// 解压到文件旁边的同名文件夹
+    Extract,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParsePostActionError(String);
+
+impl fmt::Display for ParsePostActionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "无法解析完成后操作：{}", self.0)
+    }
+}
+
+impl std::error::Error for ParsePostActionError {}
+
+impl FromStr for PostAction {
+    type Err = ParsePostActionError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let err = || ParsePostActionError(s.to_string());
+        let s = s.trim();
+        let (action, arg) = s.split_once(char::is_whitespace).unwrap_or((s, ""));
+        let arg = arg.trim();
+        match (action.to_lowercase().as_str(), arg) {
+            ("运行" | "run", "") | ("移动" | "move", "") => Err(err()),
+            ("运行" | "run", cmd) => Ok(Self::Run(cmd.to_string())),
+            ("移动" | "move", dir) => Ok(Self::Move(dir.into())),
+            ("打开" | "open", "") => Ok(Self::Open),
+            ("解压" | "extract", "") => Ok(Self::Extract),
+            _ => Err(err()),
+        }
+    }
+}
+
+impl fmt::Display for PostAction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Run(cmd) => write!(f, "运行 {cmd}"),
+            Self::Move(dir) => write!(f, "移动 {}", dir.display()),
+            Self::Open => f.write_str("打开"),
+            Self::Extract => f.write_str("解压"),
+        }
+    }
+}
+
+/// 解析界面中每行一个的完成后操作
+pub fn parse_post_actions(s: &str) -> Vec<PostAction> {
+    s.lines()
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .filter_map(|s| s.parse().log_warn("无法解析完成后操作").ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_post_action() {
+        assert_eq!(
+            "运行 notify-send \"$FD_PATH\"".parse(),
+            Ok(PostAction::Run("notify-send \"$FD_PATH\"".into()))
+        );
+        assert_eq!(
+            "move  /home/me/Videos ".parse(),
+            Ok(PostAction::Move("/home/me/Videos".into()))
+        );
+        assert_eq!("打开".parse(), Ok(PostAction::Open));
+        assert_eq!("Extract".parse(), Ok(PostAction::Extract));
+        assert!("运行".parse::<PostAction>().is_err());
+        assert!("打开 a.txt".parse::<PostAction>().is_err());
+        assert!("删除".parse::<PostAction>().is_err());
+        for s in ["运行 echo 1", "移动 /tmp", "打开", "解压"] {
+            assert_eq!(s.parse::<PostAction>().unwrap().to_string(), s);
+        }
+    }
+}
@@ -0,0 +1,275 @@
+use crate::utils::LogErr;
+use chrono::{DateTime, Datelike, Local, NaiveDateTime, NaiveTime, TimeZone, Timelike};
+use serde::{Deserialize, Serialize};
+use std::{fmt, str::FromStr, time::SystemTime};
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScheduleAction {
+    /// 正常运行
+    Run,
+    /// 暂停全部任务
+    Pause,
+    /// 限速，字节每秒
+    Limit(u64),
+}
+
+/// 计划规则，例如 `工作日 01:00-07:00 运行`
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ScheduleRule {
+    /// 第 0 位为周一，第 6 位为周日
+    pub days: u8,
+    /// 从 0 点开始的分钟数
+    pub start: u16,
+    /// 从 0 点开始的分钟数，小于 start 时表示跨过午夜
+    pub end: u16,
+    pub action: ScheduleAction,
+}
+
+const EVERYDAY: u8 = 0b111_1111;
+const WEEKDAYS: u8 = 0b001_1111;
+const WEEKENDS: u8 = 0b110_0000;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseScheduleError(String);
+
+impl fmt::Display for ParseScheduleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "无法解析计划规则：{}", self.0)
+    }
+}
+
+impl std::error::Error for ParseScheduleError {}
+
+fn parse_days(s: &str) -> Option<u8> {
+    match s.to_lowercase().as_str() {
+        "每天" | "daily" | "*" => return Some(EVERYDAY),
+        "工作日" | "weekdays" => return Some(WEEKDAYS),
+        "周末" | "weekends" => return Some(WEEKENDS),
+        _ => {}
+    }
+    let mut days = 0;
+    for part in s.split([',', '，']) {
+        let (a, b): (u8, u8) = match part.split_once('-') {
+            Some((a, b)) => (a.trim().parse().ok()?, b.trim().parse().ok()?),
+            None => {
+                let d = part.trim().parse().ok()?;
+                (d, d)
+            }
+        };
+        if !(1..=7).contains(&a) || !(1..=7).contains(&b) || a > b {
+            return None;
+        }
+        for d in a..=b {
+            days |= 1 << (d - 1);
+        }
+    }
+    Some(days)
+}
+
+fn parse_minutes(s: &str) -> Option<u16> {
+    let (h, m) = s.trim().split_once(':')?;
+    let (h, m): (u16, u16) = (h.parse().ok()?, m.parse().ok()?);
+    match (h, m) {
+        (24, 0) => Some(24 * 60),
+        (0..24, 0..60) => Some(h * 60 + m),
+        _ => None,
+    }
+}
+
+impl FromStr for ScheduleRule {
+    type Err = ParseScheduleError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let err = || ParseScheduleError(s.to_string());
+        let mut parts = s.split_whitespace();
+        let days = parts.next().and_then(parse_days).ok_or_else(err)?;
+        let (start, end) = parts
+            .next()
+            .and_then(|r| r.split_once(['-', '~']))
+            .ok_or_else(err)?;
+        let start = parse_minutes(start)
+            .filter(|m| *m < 24 * 60)
+            .ok_or_else(err)?;
+        let end = parse_minutes(end).ok_or_else(err)?;
+        let action = match parts.next().map(|a| a.to_lowercase()).as_deref() {
+            Some("运行" | "run") => ScheduleAction::Run,
+            Some("暂停" | "pause") => ScheduleAction::Pause,
+            Some("限速" | "limit") => {
+                let kib: u64 = parts.next().and_then(|n| n.parse().ok()).ok_or_else(err)?;
+                ScheduleAction::Limit(kib * 1024)
+            }
+            _ => return Err(err()),
+        };
+        if parts.next().is_some() {
+            return Err(err());
+        }
+        Ok(Self {
+            days,
+            start,
+            end,
+            action,
+        })
+    }
+}
+
+impl fmt::Display for ScheduleRule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.days {
+            EVERYDAY => f.write_str("每天")?,
+            WEEKDAYS => f.write_str("工作日")?,
+            WEEKENDS => f.write_str("周末")?,
+            days => {
+                let days: Vec<_> = (0..7)
+                    .filter(|d| days & (1 << d) != 0)
+                    .map(|d| (d + 1).to_string())
+                    .collect();
+                f.write_str(&days.join(","))?
+            }
+        }
+        write!(
+            f,
+            " {:02}:{:02}-{:02}:{:02} ",
+            self.start / 60,
+            self.start % 60,
+            self.end / 60,
+            self.end % 60
+        )?;
+        match self.action {
+            ScheduleAction::Run => f.write_str("运行"),
+            ScheduleAction::Pause => f.write_str("暂停"),
+            ScheduleAction::Limit(rate) => write!(f, "限速 {}", rate / 1024),
+        }
+    }
+}
+
+impl ScheduleRule {
+    /// weekday 为 0 ~ 6，0 为周一
+    fn matches(&self, weekday: u32, minutes: u16) -> bool {
+        let has_day = |d: u32| self.days & (1 << (d % 7)) != 0;
+        if self.start <= self.end {
+            has_day(weekday) && (self.start..self.end).contains(&minutes)
+        } else {
+            // 跨过午夜的时间段，归属于开始的那一天
+            (has_day(weekday) && minutes >= self.start)
+                || (has_day(weekday + 6) && minutes < self.end)
+        }
+    }
+}
+
+/// 按顺序匹配第一条规则，都不匹配时正常运行
+pub fn current_action<Tz: TimeZone>(rules: &[ScheduleRule], now: &DateTime<Tz>) -> ScheduleAction {
+    let weekday = now.weekday().num_days_from_monday();
+    let minutes = (now.hour() * 60 + now.minute()) as u16;
+    rules
+        .iter()
+        .find(|r| r.matches(weekday, minutes))
+        .map_or(ScheduleAction::Run, |r| r.action)
+}
+
+/// 解析定时开始的时间，支持 `2026-01-01 23:00` 和 `23:00`（今天，已过去则为明天）
+pub fn parse_start_at(s: &str, now: DateTime<Local>) -> Option<DateTime<Local>> {
+    let s = s.trim();
+    if let Ok(t) = NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M") {
+        return Local.from_local_datetime(&t).earliest();
+    }
+    let t = NaiveTime::parse_from_str(s, "%H:%M").ok()?;
+    let today = Local
+        .from_local_datetime(&now.date_naive().and_time(t))
+        .earliest()?;
+    if today > now {
+        Some(today)
+    } else {
+        Local
+            .from_local_datetime(&(now.date_naive().succ_opt()?.and_time(t)))
+            .earliest()
+    }
+}
+
+/// 解析界面中填写的定时开始时间，留空为不定时
+pub fn start_at_from_ui(s: &str) -> Option<SystemTime> {
+    match s.trim() {
+        "" => None,
+        s => parse_start_at(s, Local::now())
+            .log_warn("无法解析定时开始时间")
+            .map(Into::into),
+    }
+}
+
+pub fn format_start_at(t: SystemTime) -> String {
+    DateTime::<Local>::from(t)
+        .format("%Y-%m-%d %H:%M")
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDate;
+
+    fn at(day: u32, h: u32, m: u32) -> DateTime<Local> {
+        // 2024-01-01 是周一
+        let t = NaiveDate::from_ymd_opt(2024, 1, day)
+            .unwrap()
+            .and_hms_opt(h, m, 0)
+            .unwrap();
+        Local.from_local_datetime(&t).unwrap()
+    }
+
+    #[test]
+    fn test_parse_rule() {
+        let rule: ScheduleRule = "工作日 01:00-07:00 运行".parse().unwrap();
+        assert_eq!(rule.days, WEEKDAYS);
+        assert_eq!((rule.start, rule.end), (60, 420));
+        assert_eq!(rule.action, ScheduleAction::Run);
+        assert_eq!(rule.to_string(), "工作日 01:00-07:00 运行");
+
+        let rule: ScheduleRule = "1-3,7 22:30-06:00 limit 512".parse().unwrap();
+        assert_eq!(rule.days, 0b100_0111);
+        assert_eq!(rule.action, ScheduleAction::Limit(512 * 1024));
+        assert_eq!(rule.to_string(), "1,2,3,7 22:30-06:00 限速 512");
+
+        let rule: ScheduleRule = "每天 00:00-24:00 暂停".parse().unwrap();
+        assert_eq!(rule.end, 24 * 60);
+
+        assert!("工作日 01:00-07:00".parse::<ScheduleRule>().is_err());
+        assert!("0 01:00-07:00 运行".parse::<ScheduleRule>().is_err());
+        assert!("每天 25:00-07:00 运行".parse::<ScheduleRule>().is_err());
+        assert!("每天 01:00-07:00 限速".parse::<ScheduleRule>().is_err());
+    }
+
+    #[test]
+    fn test_current_action() {
+        let rules: Vec<ScheduleRule> = [
+            "工作日 01:00-07:00 运行",
+            "5 22:00-02:00 限速 100",
+            "每天 00:00-24:00 暂停",
+        ]
+        .iter()
+        .map(|s| s.parse().unwrap())
+        .collect();
+        // 周一凌晨
+        assert_eq!(current_action(&rules, &at(1, 3, 0)), ScheduleAction::Run);
+        // 周一白天，落到兜底规则
+        assert_eq!(current_action(&rules, &at(1, 12, 0)), ScheduleAction::Pause);
+        // 周五晚上跨午夜到周六凌晨
+        assert_eq!(
+            current_action(&rules, &at(5, 23, 0)),
+            ScheduleAction::Limit(100 * 1024)
+        );
+        assert_eq!(
+            current_action(&rules, &at(6, 1, 0)),
+            ScheduleAction::Limit(100 * 1024)
+        );
+        // 没有规则时正常运行
+        assert_eq!(current_action(&[], &at(6, 1, 0)), ScheduleAction::Run);
+    }
+
+    #[test]
+    fn test_parse_start_at() {
+        let now = at(1, 12, 0);
+        assert_eq!(parse_start_at("23:00", now), Some(at(1, 23, 0)));
+        assert_eq!(parse_start_at("08:00", now), Some(at(2, 8, 0)));
+        assert_eq!(parse_start_at("2024-01-03 08:30", now), Some(at(3, 8, 30)));
+        assert_eq!(parse_start_at("明天", now), None);
+    }
+}
@@ -0,0 +1,95 @@
+use crate::utils::LogErr;
+use axum::Router;
+use std::{cell::RefCell, net::SocketAddr};
+use tokio::net::TcpListener;
+use tokio_util::sync::CancellationToken;
+use tracing::{info, warn};
+
+/// 按设置启动、重启或停止的本地 HTTP 服务
+#[derive(Debug)]
+pub struct LocalServer {
+    name: &'static str,
+    /// 运行中的地址、密钥和用于停止服务的令牌
+    running: RefCell<Option<(SocketAddr, String, CancellationToken)>>,
+}
+
+impl LocalServer {
+    pub fn new(name: &'static str) -> Self {
+        Self {
+            name,
+            running: RefCell::default(),
+        }
+    }
+
+    /// addr 为 None 时停止服务，地址和密钥都没变时不重启，密钥为空时不启动
+    pub fn apply(
+        &self,
+        addr: Option<SocketAddr>,
+        secret: &str,
+        router: impl FnOnce(CancellationToken) -> Router,
+    ) {
+        let name = self.name;
+        let mut running = self.running.borrow_mut();
+        if let Some((a, s, _)) = running.as_ref()
+            && Some(*a) == addr
+            && s == secret
+        {
+            return;
+        }
+        if let Some((_, _, token)) = running.take() {
+            info!(name, "停止服务");
+            token.cancel();
+        }
+        let Some(addr) = addr else { return };
+        if secret.is_empty() {
+            return warn!(name, "没有设置密钥，不开启服务");
+        }
+        let shutdown = CancellationToken::new();
+        let router = router(shutdown.clone());
+        tokio::spawn({
+            let shutdown = shutdown.clone();
+            async move {
+                let _ = serve(name, addr, router, shutdown)
+                    .await
+                    .log_err(&format!("{name} 服务出错"));
+            }
+        });
+        *running = Some((addr, secret.to_string(), shutdown));
+    }
+}
+
+async fn serve(
+    name: &'static str,
+    addr: SocketAddr,
+    router: Router,
+    shutdown: CancellationToken,
+) -> color_eyre::Result<()> {
+    let listener = TcpListener::bind(addr).await?;
+    info!(name, addr = %addr, "服务已启动");
+    axum::serve(listener, router)
+        .with_graceful_shutdown(shutdown.cancelled_owned())
+        .await?;
+    Ok(())
+}
+
+/// 比较密钥，耗时与内容无关，避免通过响应时间逐位猜出密钥
+pub fn secret_eq(a: &str, b: &str) -> bool {
+    a.len() == b.len()
+        && a.bytes()
+            .zip(b.bytes())
+            .fold(0, |acc, (x, y)| acc | (x ^ y))
+            == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_secret_eq() {
+        assert!(secret_eq("abc", "abc"));
+        assert!(!secret_eq("abc", "abd"));
+        assert!(!secret_eq("abc", "ab"));
+        assert!(secret_eq("", ""));
+    }
+}
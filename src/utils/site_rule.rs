@@ -0,0 +1,253 @@
+use crate::utils::{LogErr, parse_header_hashmap};
+use fast_down_ffi::Proxy;
+use itertools::Itertools;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, path::PathBuf};
+use url::Url;
+
+/// 站点规则的匹配方式
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub enum SitePattern {
+    /// 主机名通配符，例如 `*.example.com`
+    Host(String),
+    /// 匹配完整链接的正则表达式
+    Regex(String),
+}
+
+impl SitePattern {
+    pub fn is_match(&self, url: &Url) -> bool {
+        match self {
+            Self::Host(glob) => url
+                .host_str()
+                .is_some_and(|host| glob_match(&glob.to_lowercase(), host)),
+            Self::Regex(re) => Regex::new(re).is_ok_and(|re| re.is_match(url.as_str())),
+        }
+    }
+}
+
+/// `*` 匹配任意个字符，`?` 匹配一个字符
+fn glob_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<_> = pattern.chars().collect();
+    let text: Vec<_> = text.chars().collect();
+    let (mut p, mut t) = (0, 0);
+    // 上一个 `*` 的位置和它开始匹配的位置
+    let mut star = None;
+    while t < text.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == text[t]) {
+            p += 1;
+            t += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            star = Some((p, t));
+            p += 1;
+        } else if let Some((sp, st)) = star {
+            p = sp + 1;
+            t = st + 1;
+            star = Some((sp, st + 1));
+        } else {
+            return false;
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+/// 站点规则，匹配的链接用规则中的设置覆盖任务设置，为空的项不覆盖
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SiteRule {
+    pub pattern: SitePattern,
+    /// 与任务的请求头合并，同名时以规则为准
+    pub headers: HashMap<String, String>,
+    pub proxy: Option<Proxy<String>>,
+    pub threads: Option<usize>,
+    pub save_dir: Option<PathBuf>,
+    /// 文件名模板，支持自定义文件名中的占位符
+    pub file_name: Option<String>,
+}
+
+impl SiteRule {
+    pub fn apply(&self, config: &mut crate::ui::DownloadConfig) {
+        if !self.headers.is_empty() {
+            let mut headers = parse_header_hashmap(&config.headers);
+            headers.extend(self.headers.clone());
+            config.headers = headers
+                .iter()
+                .map(|(k, v)| format!("{k}: {v}"))
+                .join("\n")
+                .into();
+        }
+        if let Some(proxy) = &self.proxy {
+            config.proxy = proxy_to_ui(proxy).into();
+        }
+        if let Some(threads) = self.threads {
+            config.threads = threads as i32;
+        }
+        if let Some(save_dir) = &self.save_dir {
+            config.save_dir = save_dir.to_string_lossy().as_ref().into();
+        }
+        if let Some(file_name) = &self.file_name {
+            config.file_name = file_name.into();
+            config.parse_filename = true;
+        }
+    }
+
+    pub fn to_ui(&self) -> crate::ui::SiteRuleData {
+        let (is_regex, pattern) = match &self.pattern {
+            SitePattern::Host(s) => (false, s),
+            SitePattern::Regex(s) => (true, s),
+        };
+        crate::ui::SiteRuleData {
+            is_regex,
+            pattern: pattern.into(),
+            headers: self
+                .headers
+                .iter()
+                .map(|(k, v)| format!("{k}: {v}"))
+                .join("\n")
+                .into(),
+            proxy: self
+                .proxy
+                .as_ref()
+                .map(proxy_to_ui)
+                .unwrap_or_default()
+                .into(),
+            threads: self.threads.unwrap_or_default() as i32,
+            save_dir: self
+                .save_dir
+                .as_ref()
+                .map(|s| s.to_string_lossy())
+                .unwrap_or_default()
+                .as_ref()
+                .into(),
+            file_name: self.file_name.clone().unwrap_or_default().into(),
+        }
+    }
+}
+
+fn proxy_to_ui(proxy: &Proxy<String>) -> &str {
+    match proxy.as_deref() {
+        Proxy::No => "null",
+        Proxy::System => "system",
+        Proxy::Custom(proxy) => proxy,
+    }
+}
+
+impl From<&crate::ui::SiteRuleData> for SiteRule {
+    fn from(value: &crate::ui::SiteRuleData) -> Self {
+        let pattern = value.pattern.trim().to_string();
+        let pattern = if value.is_regex {
+            let _ = Regex::new(&pattern).log_warn("无法解析站点规则的正则表达式");
+            SitePattern::Regex(pattern)
+        } else {
+            SitePattern::Host(pattern)
+        };
+        let non_empty = |s: &str| Some(s.trim()).filter(|s| !s.is_empty()).map(String::from);
+        Self {
+            pattern,
+            headers: parse_header_hashmap(&value.headers),
+            proxy: non_empty(&value.proxy).map(|proxy| match proxy.as_str() {
+                "null" => Proxy::No,
+                "system" => Proxy::System,
+                _ => Proxy::Custom(proxy),
+            }),
+            threads: (value.threads > 0).then_some(value.threads as usize),
+            save_dir: non_empty(&value.save_dir).map(PathBuf::from),
+            file_name: non_empty(&value.file_name),
+        }
+    }
+}
+
+/// 按顺序应用所有匹配的规则，靠后的规则优先
+pub fn apply_site_rules(rules: &[SiteRule], url: &Url, config: &mut crate::ui::DownloadConfig) {
+    for rule in rules.iter().filter(|r| r.pattern.is_match(url)) {
+        tracing::info!(url = url.as_str(), pattern = ?rule.pattern, "应用站点规则");
+        rule.apply(config);
+    }
+}
+
+/// 对话框确认后按链接重新应用站点规则，对话框中修改过的项保持不变
+///
+/// `base` 是没有应用站点规则的设置，`shown` 是对话框打开时显示的设置，
+/// 没有修改的项换成 `base` 应用该链接的站点规则后的值
+pub fn reapply_site_rules(
+    rules: &[SiteRule],
+    url: &Url,
+    base: &crate::ui::DownloadConfig,
+    shown: &crate::ui::DownloadConfig,
+    edited: &crate::ui::DownloadConfig,
+) -> crate::ui::DownloadConfig {
+    let mut ruled = base.clone();
+    apply_site_rules(rules, url, &mut ruled);
+    let mut config = edited.clone();
+    if edited.headers == shown.headers {
+        config.headers = ruled.headers;
+    }
+    if edited.proxy == shown.proxy {
+        config.proxy = ruled.proxy;
+    }
+    if edited.threads == shown.threads {
+        config.threads = ruled.threads;
+    }
+    if edited.save_dir == shown.save_dir {
+        config.save_dir = ruled.save_dir;
+    }
+    if edited.file_name == shown.file_name && edited.parse_filename == shown.parse_filename {
+        config.file_name = ruled.file_name;
+        config.parse_filename = ruled.parse_filename;
+    }
+    config
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_glob_match() {
+        assert!(glob_match("*.example.com", "cdn.example.com"));
+        assert!(!glob_match("*.example.com", "example.com"));
+        assert!(glob_match("example.com", "example.com"));
+        assert!(glob_match("dl?.example.*", "dl1.example.org"));
+        assert!(!glob_match("dl?.example.*", "dl12.example.org"));
+        assert!(glob_match("*", "anything"));
+    }
+
+    #[test]
+    fn test_site_pattern() {
+        let url = Url::parse("https://CDN.Example.com/files/a.zip").unwrap();
+        assert!(SitePattern::Host("*.EXAMPLE.com".into()).is_match(&url));
+        assert!(SitePattern::Regex(r"/files/.*\.zip$".into()).is_match(&url));
+        assert!(!SitePattern::Regex(r"\.iso$".into()).is_match(&url));
+        assert!(!SitePattern::Regex("(".into()).is_match(&url));
+    }
+
+    #[test]
+    fn test_reapply_site_rules() {
+        let rule = |host: &str, threads| SiteRule {
+            pattern: SitePattern::Host(host.into()),
+            headers: HashMap::new(),
+            proxy: None,
+            threads: Some(threads),
+            save_dir: Some(format!("/{host}").into()),
+            file_name: None,
+        };
+        let rules = [rule("a.com", 4), rule("b.com", 8)];
+        let base = crate::ui::DownloadConfig {
+            threads: 32,
+            save_dir: "/global".into(),
+            ..Default::default()
+        };
+        // 对话框打开时应用了剪贴板中链接的规则，之后用户只修改了线程数
+        let mut shown = base.clone();
+        apply_site_rules(&rules, &Url::parse("https://a.com/x").unwrap(), &mut shown);
+        let edited = crate::ui::DownloadConfig {
+            threads: 2,
+            ..shown.clone()
+        };
+        let b = Url::parse("https://b.com/y").unwrap();
+        let config = reapply_site_rules(&rules, &b, &base, &shown, &edited);
+        assert_eq!((config.save_dir.as_str(), config.threads), ("/b.com", 2));
+        let c = Url::parse("https://c.com/z").unwrap();
+        let config = reapply_site_rules(&rules, &c, &base, &shown, &edited);
+        assert_eq!((config.save_dir.as_str(), config.threads), ("/global", 2));
+    }
+}